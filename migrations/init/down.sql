@@ -1,4 +1,9 @@
 -- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS "webhook_deliveries";
+DROP TABLE IF EXISTS "webhook_subscriptions";
+DROP TABLE IF EXISTS "outbox_events";
+DROP TABLE IF EXISTS "applied_events";
+DROP TABLE IF EXISTS "product_search";
 DROP TABLE IF EXISTS "order_lines";
 DROP TABLE IF EXISTS "products";
 DROP TABLE IF EXISTS "categories";
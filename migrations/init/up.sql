@@ -38,6 +38,7 @@ CREATE TABLE "products" (
     "brand_id" INT4,
     "price" INT4 NOT NULL,
     "tax_rate" INT4 NOT NULL,
+    "sku" VARCHAR UNIQUE,
     FOREIGN KEY ("category_id") REFERENCES "categories"("id") ON DELETE SET NULL,
     FOREIGN KEY ("brand_id") REFERENCES "brands"("id") ON DELETE SET NULL
 );
@@ -118,6 +119,67 @@ CREATE TABLE "product_attributes" (
     FOREIGN KEY ("attribute_id") REFERENCES "attributes"("id") ON DELETE CASCADE
 );
 
+-- Search document of each product: its name, brand and category names and
+-- attribute values. Kept up to date by refresh_product_search().
+CREATE TABLE "product_search" (
+    "product_id" INT4 PRIMARY KEY,
+    "document" TSVECTOR NOT NULL,
+    FOREIGN KEY ("product_id") REFERENCES "products"("id") ON DELETE CASCADE
+);
+CREATE INDEX "product_search_document" ON "product_search" USING GIN ("document");
+
+-- Stream events that have been applied, used to skip redelivered events
+CREATE TABLE "applied_events" (
+    "producer_id" VARCHAR NOT NULL,
+    "event_id" VARCHAR NOT NULL,
+    "applied_at" TIMESTAMP DEFAULT NOW() NOT NULL,
+    PRIMARY KEY ("producer_id", "event_id")
+);
+
+-- Domain events waiting to be published, written in the same transaction as
+-- the change they describe
+CREATE TABLE "outbox_events" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "event_type" VARCHAR NOT NULL,
+    "aggregate_type" VARCHAR NOT NULL,
+    "aggregate_id" VARCHAR NOT NULL,
+    "payload" JSONB NOT NULL,
+    "created_at" TIMESTAMP DEFAULT NOW() NOT NULL,
+    "published_at" TIMESTAMP
+);
+CREATE INDEX "outbox_events_unpublished" ON "outbox_events" ("id") WHERE "published_at" IS NULL;
+
+-- Webhook subscriptions; an empty event_types list matches every event
+CREATE TABLE "webhook_subscriptions" (
+    "id" SERIAL PRIMARY KEY,
+    "url" VARCHAR NOT NULL,
+    "event_types" VARCHAR[] DEFAULT '{}' NOT NULL,
+    "secret" VARCHAR NOT NULL,
+    "active" BOOL DEFAULT true NOT NULL,
+    "consecutive_failures" INT4 DEFAULT 0 NOT NULL,
+    "created_at" TIMESTAMP DEFAULT NOW() NOT NULL,
+    "disabled_at" TIMESTAMP
+);
+
+-- Webhook deliveries and their latest attempt
+CREATE TABLE "webhook_deliveries" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "subscription_id" INT4 NOT NULL,
+    "event_id" INT8,
+    "event_type" VARCHAR NOT NULL,
+    "body" TEXT NOT NULL,
+    "status" VARCHAR DEFAULT 'pending' NOT NULL,
+    "attempts" INT4 DEFAULT 0 NOT NULL,
+    "next_attempt_at" TIMESTAMP DEFAULT NOW() NOT NULL,
+    "last_status_code" INT4,
+    "last_error" VARCHAR,
+    "created_at" TIMESTAMP DEFAULT NOW() NOT NULL,
+    "delivered_at" TIMESTAMP,
+    FOREIGN KEY ("subscription_id") REFERENCES "webhook_subscriptions"("id") ON DELETE CASCADE
+);
+CREATE INDEX "webhook_deliveries_due" ON "webhook_deliveries" ("next_attempt_at") WHERE "status" = 'pending';
+CREATE INDEX "webhook_deliveries_subscription" ON "webhook_deliveries" ("subscription_id", "id");
+
 -- Function to decrease stock quantity on INSERT
 CREATE OR REPLACE FUNCTION decrease_stock_quantity()
 RETURNS TRIGGER AS $$
@@ -198,6 +260,239 @@ AFTER UPDATE ON order_lines
 FOR EACH ROW
 EXECUTE FUNCTION update_stock_quantity();
 
+-- Function to record a domain event in the outbox. Arguments: aggregate type,
+-- column holding the aggregate id, then the event type for INSERT, UPDATE and
+-- DELETE. The payload holds the row before and after the change.
+CREATE OR REPLACE FUNCTION record_outbox_event()
+RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+    new_row JSONB := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+BEGIN
+    IF old_row = new_row THEN
+        RETURN NULL;
+    END IF;
+    INSERT INTO outbox_events (event_type, aggregate_type, aggregate_id, payload)
+    VALUES (
+        CASE TG_OP
+            WHEN 'INSERT' THEN TG_ARGV[2]
+            WHEN 'UPDATE' THEN TG_ARGV[3]
+            ELSE TG_ARGV[4]
+        END,
+        TG_ARGV[0],
+        COALESCE(new_row, old_row) ->> TG_ARGV[1],
+        jsonb_build_object('old', old_row, 'new', new_row)
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER product_events
+AFTER INSERT OR UPDATE OR DELETE ON products
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Product', 'id', 'ProductCreated', 'ProductUpdated', 'ProductDeleted');
+
+CREATE TRIGGER product_price_events
+AFTER UPDATE ON products
+FOR EACH ROW
+WHEN (OLD.price IS DISTINCT FROM NEW.price OR OLD.tax_rate IS DISTINCT FROM NEW.tax_rate)
+EXECUTE FUNCTION record_outbox_event('Product', 'id', 'PriceChanged', 'PriceChanged', 'PriceChanged');
+
+CREATE TRIGGER brand_events
+AFTER INSERT OR UPDATE OR DELETE ON brands
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Brand', 'id', 'BrandCreated', 'BrandUpdated', 'BrandDeleted');
+
+CREATE TRIGGER category_events
+AFTER INSERT OR UPDATE OR DELETE ON categories
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Category', 'id', 'CategoryCreated', 'CategoryUpdated', 'CategoryDeleted');
+
+CREATE TRIGGER discount_events
+AFTER INSERT OR UPDATE OR DELETE ON discounts
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Discount', 'id', 'DiscountCreated', 'DiscountUpdated', 'DiscountDeleted');
+
+CREATE TRIGGER discount_brand_events
+AFTER INSERT OR UPDATE OR DELETE ON discount_brands
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Discount', 'discount_id', 'DiscountLinked', 'DiscountLinked', 'DiscountUnlinked');
+
+CREATE TRIGGER discount_category_events
+AFTER INSERT OR UPDATE OR DELETE ON discount_categories
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Discount', 'discount_id', 'DiscountLinked', 'DiscountLinked', 'DiscountUnlinked');
+
+CREATE TRIGGER discount_product_events
+AFTER INSERT OR UPDATE OR DELETE ON discount_products
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Discount', 'discount_id', 'DiscountLinked', 'DiscountLinked', 'DiscountUnlinked');
+
+-- Also fires for stock changed by the order line triggers above
+CREATE TRIGGER stock_events
+AFTER INSERT OR UPDATE OR DELETE ON stock_quantities
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Product', 'product_id', 'StockChanged', 'StockChanged', 'StockChanged');
+
+CREATE TRIGGER cart_events
+AFTER INSERT OR UPDATE OR DELETE ON carts
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Cart', 'id', 'CartCreated', 'CartUpdated', 'CartDeleted');
+
+-- A cart is checked out when it stops being active
+CREATE TRIGGER cart_checkout_events
+AFTER UPDATE ON carts
+FOR EACH ROW
+WHEN (OLD.is_active AND NOT NEW.is_active)
+EXECUTE FUNCTION record_outbox_event('Cart', 'id', 'CartCheckedOut', 'CartCheckedOut', 'CartCheckedOut');
+
+CREATE TRIGGER order_line_events
+AFTER INSERT OR UPDATE OR DELETE ON order_lines
+FOR EACH ROW
+EXECUTE FUNCTION record_outbox_event('Cart', 'cart_id', 'CartItemAdded', 'CartItemUpdated', 'CartItemRemoved');
+
+-- Function to tell REST API replicas which cached responses a change makes
+-- stale. Argument: column holding the id of the changed row. Notifications are
+-- only delivered once the transaction commits.
+CREATE OR REPLACE FUNCTION notify_cache_invalidation()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed_row JSONB := to_jsonb(CASE WHEN TG_OP = 'DELETE' THEN OLD ELSE NEW END);
+BEGIN
+    PERFORM pg_notify(
+        'cache_invalidation',
+        jsonb_build_object('table', TG_TABLE_NAME, 'id', (changed_row ->> TG_ARGV[0])::INT4)::TEXT
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER product_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON products
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('id');
+
+CREATE TRIGGER brand_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON brands
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('id');
+
+CREATE TRIGGER category_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON categories
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('id');
+
+CREATE TRIGGER discount_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON discounts
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('id');
+
+CREATE TRIGGER discount_brand_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON discount_brands
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('discount_id');
+
+CREATE TRIGGER discount_category_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON discount_categories
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('discount_id');
+
+CREATE TRIGGER discount_product_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON discount_products
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('discount_id');
+
+CREATE TRIGGER stock_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON stock_quantities
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('product_id');
+
+CREATE TRIGGER attribute_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON attributes
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('id');
+
+CREATE TRIGGER product_attribute_cache_invalidation
+AFTER INSERT OR UPDATE OR DELETE ON product_attributes
+FOR EACH ROW
+EXECUTE FUNCTION notify_cache_invalidation('product_id');
+
+-- Search document of a product. Names weigh more than attribute values.
+CREATE OR REPLACE FUNCTION product_search_document(product INT4)
+RETURNS TSVECTOR AS $$
+    SELECT setweight(to_tsvector('simple', p.name), 'A')
+        || setweight(to_tsvector('simple', coalesce(b.name, '')), 'B')
+        || setweight(to_tsvector('simple', coalesce(c.name, '')), 'B')
+        || setweight(to_tsvector('simple', coalesce(string_agg(a.value, ' '), '')), 'C')
+    FROM products p
+    LEFT JOIN brands b ON b.id = p.brand_id
+    LEFT JOIN categories c ON c.id = p.category_id
+    LEFT JOIN product_attributes pa ON pa.product_id = p.id
+    LEFT JOIN attributes a ON a.id = pa.attribute_id
+    WHERE p.id = product
+    GROUP BY p.id, b.name, c.name;
+$$ LANGUAGE sql STABLE;
+
+-- A field of a product with the words matching a search wrapped in <mark>
+-- tags, or NULL when none match.
+CREATE OR REPLACE FUNCTION product_search_highlight(field TEXT, query TSQUERY)
+RETURNS TEXT AS $$
+    SELECT NULLIF(
+        ts_headline('simple', field, query, 'StartSel=<mark>, StopSel=</mark>, HighlightAll=true'),
+        field
+    );
+$$ LANGUAGE sql IMMUTABLE;
+
+-- Function to rebuild the search documents of the products a change touches.
+-- Deleted products lose theirs through the foreign key.
+CREATE OR REPLACE FUNCTION refresh_product_search()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed_row JSONB := to_jsonb(CASE WHEN TG_OP = 'DELETE' THEN OLD ELSE NEW END);
+    changed_id INT4 := (changed_row ->> CASE TG_TABLE_NAME WHEN 'product_attributes' THEN 'product_id' ELSE 'id' END)::INT4;
+BEGIN
+    INSERT INTO product_search (product_id, document)
+    SELECT p.id, product_search_document(p.id)
+    FROM products p
+    WHERE CASE TG_TABLE_NAME
+        WHEN 'products' THEN p.id = changed_id
+        WHEN 'brands' THEN p.brand_id = changed_id
+        WHEN 'categories' THEN p.category_id = changed_id
+        WHEN 'product_attributes' THEN p.id = changed_id
+        WHEN 'attributes' THEN p.id IN (
+            SELECT product_id FROM product_attributes WHERE attribute_id = changed_id
+        )
+    END
+    ON CONFLICT (product_id) DO UPDATE SET document = EXCLUDED.document;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER product_search_refresh
+AFTER INSERT OR UPDATE ON products
+FOR EACH ROW
+EXECUTE FUNCTION refresh_product_search();
+
+CREATE TRIGGER brand_search_refresh
+AFTER UPDATE OF name ON brands
+FOR EACH ROW
+EXECUTE FUNCTION refresh_product_search();
+
+CREATE TRIGGER category_search_refresh
+AFTER UPDATE OF name ON categories
+FOR EACH ROW
+EXECUTE FUNCTION refresh_product_search();
+
+CREATE TRIGGER attribute_search_refresh
+AFTER UPDATE OF value ON attributes
+FOR EACH ROW
+EXECUTE FUNCTION refresh_product_search();
+
+CREATE TRIGGER product_attribute_search_refresh
+AFTER INSERT OR UPDATE OR DELETE ON product_attributes
+FOR EACH ROW
+EXECUTE FUNCTION refresh_product_search();
+
 -- Insert categories
 
 -- Insert categories
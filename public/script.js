@@ -183,6 +183,15 @@ async function startProcessor() {
     });
 }
 
+async function fetchProcessorStats() {
+    const response = await fetch(`http://127.0.0.1:3030/stats`);
+    const stats = await response.json();
+    const modes = stats.modes.map(mode => `${mode.channel}: ${JSON.stringify(mode.mode)}`);
+    document.getElementById('processorMode').textContent = modes.join(', ');
+    document.getElementById('updatesPerMinute').textContent = stats.updates_per_minute;
+    document.getElementById('processorStatus').textContent = stats.active ? 'Active' : 'Paused';
+}
+
 
 
 async function fetchCarts() {
@@ -190,7 +199,7 @@ async function fetchCarts() {
         displayCachedHTML('cartList', cache['carts'].html);
     } else {
         const response = await fetch(`${API_URL}/cart`, { method: "GET" });
-        const carts = await response.json();
+        const { items: carts } = await response.json();
         const html = generateCartsHTML(carts);
         cache['carts'] = { data: carts, html };
         displayCachedHTML('cartList', html);
@@ -273,7 +282,7 @@ async function fetchProducts() {
         displayCachedHTML('productList', cache['products'].html);
     } else {
         const response = await fetch(`${API_URL}/product`);
-        const products = await response.json();
+        const { items: products } = await response.json();
         const html = generateProductsHTML(products);
         cache['products'] = { data: products, html };
         displayCachedHTML('productList', html);
@@ -304,7 +313,7 @@ async function addProduct(product) {
 }
 
 async function deleteProduct(id) {
-    await fetch(`${API_URL}/product/id/${id}`, {
+    await fetch(`${API_URL}/product/${id}`, {
         method: 'DELETE',
     });
 }
@@ -314,7 +323,7 @@ async function fetchBrands() {
         displayCachedHTML('brandList', cache['brands'].html);
     } else {
         const response = await fetch(`${API_URL}/brand`);
-        const brands = await response.json();
+        const { items: brands } = await response.json();
         const html = generateBrandsHTML(brands);
         cache['brands'] = { data: brands, html };
         displayCachedHTML('brandList', html);
@@ -340,7 +349,7 @@ async function addBrand(brand) {
 }
 
 async function deleteBrand(id) {
-    await fetch(`${API_URL}/brand/id/${id}`, {
+    await fetch(`${API_URL}/brand/${id}`, {
         method: 'DELETE',
     });
 }
@@ -350,7 +359,7 @@ async function fetchDiscounts() {
         displayCachedHTML('discountList', cache['discounts'].html);
     } else {
         const response = await fetch(`${API_URL}/discount`);
-        const discounts = await response.json();
+        const { items: discounts } = await response.json();
         const html = generateDiscountsHTML(discounts);
         cache['discounts'] = { data: discounts, html };
         displayCachedHTML('discountList', html);
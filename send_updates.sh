@@ -16,8 +16,12 @@ generate_random_product() {
   local price=$((RANDOM % 5000 + 1000)) # Random price between 1000 and 6000
   local tax_rate=$((RANDOM % 10 + 5)) # Random tax_rate between 5% and 15%
 
-  # Generate the JSON product
+  # Generate the JSON event. The id is derived from the index so running the
+  # script again resends the same events instead of creating new products.
   echo '{
+    "id": "product-'"$index"'",
+    "producer_id": "send_updates",
+    "data": {
     "NewProduct": {
       "name": "Product '"$index"'",
       "in_stock": '"true"',
@@ -32,6 +36,7 @@ generate_random_product() {
       "price": '"$price"',
       "tax_rate": '"$tax_rate"'
     }
+    }
   }'
 }
 
@@ -0,0 +1,206 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    body::BoxBody,
+    http::{
+        header::{self, HeaderValue, HttpDate},
+        Method,
+    },
+    web::Bytes,
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// Strong ETag of a response body: its quoted hex SHA-256.
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` value lists `etag`. Uses the weak comparison
+/// required for `If-None-Match`, so `W/` prefixes are ignored.
+fn none_match_lists(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Whether the resource is unchanged since an `If-Modified-Since` value.
+fn unmodified_since(if_modified_since: &str, last_modified: DateTime<Utc>) -> bool {
+    match if_modified_since.parse::<HttpDate>() {
+        Ok(since) => {
+            let since: DateTime<Utc> = SystemTime::from(since).into();
+            last_modified.timestamp() <= since.timestamp()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Adds `ETag` and `Last-Modified` to a `200 OK` response with `body`, and
+/// turns it into `304 Not Modified` if the request's `If-None-Match`, or
+/// failing that its `If-Modified-Since`, shows the client already has it.
+pub fn respond(
+    request: &HttpRequest,
+    mut response: HttpResponse<()>,
+    body: Bytes,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> HttpResponse {
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = last_modified {
+        let date = HttpDate::from(SystemTime::from(last_modified));
+        if let Ok(value) = HeaderValue::from_str(&date.to_string()) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    let safe_method = matches!(*request.method(), Method::GET | Method::HEAD);
+    let request_headers = request.headers();
+    let not_modified = match request_headers.get(header::IF_NONE_MATCH) {
+        Some(if_none_match) => if_none_match
+            .to_str()
+            .map(|if_none_match| none_match_lists(if_none_match, etag))
+            .unwrap_or(false),
+        None => match (
+            request_headers.get(header::IF_MODIFIED_SINCE),
+            last_modified,
+        ) {
+            (Some(if_modified_since), Some(last_modified)) => if_modified_since
+                .to_str()
+                .map(|since| unmodified_since(since, last_modified))
+                .unwrap_or(false),
+            _ => false,
+        },
+    };
+
+    if safe_method && not_modified {
+        let mut not_modified = HttpResponse::NotModified();
+        for (name, value) in response.headers() {
+            if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+                not_modified.insert_header((name.clone(), value.clone()));
+            }
+        }
+        not_modified.finish()
+    } else {
+        response.set_body(BoxBody::new(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const BODY: &[u8] = b"{\"id\":1}";
+
+    fn last_modified() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 17, 12, 0, 0).unwrap()
+    }
+
+    fn respond_to(request: TestRequest) -> HttpResponse {
+        let (response, _) = HttpResponse::Ok()
+            .content_type("application/json")
+            .finish()
+            .into_parts();
+        respond(
+            &request.to_http_request(),
+            response,
+            Bytes::from_static(BODY),
+            &etag(BODY),
+            Some(last_modified()),
+        )
+    }
+
+    #[test]
+    fn adds_validators() {
+        let response = respond_to(TestRequest::get());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::ETAG).unwrap(),
+            etag(BODY).as_str()
+        );
+        assert_eq!(
+            response.headers().get(header::LAST_MODIFIED).unwrap(),
+            "Mon, 17 Jun 2024 12:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn etag_is_the_quoted_sha256_of_the_body() {
+        assert_eq!(
+            etag(b""),
+            "\"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\""
+        );
+    }
+
+    #[test]
+    fn matching_if_none_match_is_not_modified() {
+        for if_none_match in [
+            etag(BODY),
+            format!("W/{}", etag(BODY)),
+            format!("\"other\", {}", etag(BODY)),
+            "*".to_string(),
+        ] {
+            let response = respond_to(
+                TestRequest::get().insert_header((header::IF_NONE_MATCH, if_none_match)),
+            );
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(
+                response.headers().get(header::ETAG).unwrap(),
+                etag(BODY).as_str()
+            );
+            assert!(response.headers().get(header::CONTENT_TYPE).is_none());
+        }
+    }
+
+    #[test]
+    fn other_if_none_match_is_served() {
+        let response =
+            respond_to(TestRequest::get().insert_header((header::IF_NONE_MATCH, "\"other\"")));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn if_modified_since_is_compared_to_last_modified() {
+        let at = |since: &str| {
+            respond_to(TestRequest::get().insert_header((header::IF_MODIFIED_SINCE, since)))
+                .status()
+        };
+        assert_eq!(
+            at("Mon, 17 Jun 2024 12:00:00 GMT"),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            at("Tue, 18 Jun 2024 00:00:00 GMT"),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(at("Mon, 17 Jun 2024 11:59:59 GMT"), StatusCode::OK);
+        assert_eq!(at("not a date"), StatusCode::OK);
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let response = respond_to(
+            TestRequest::get()
+                .insert_header((header::IF_NONE_MATCH, "\"other\""))
+                .insert_header((header::IF_MODIFIED_SINCE, "Tue, 18 Jun 2024 00:00:00 GMT")),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn only_safe_methods_are_not_modified() {
+        let response =
+            respond_to(TestRequest::post().insert_header((header::IF_NONE_MATCH, etag(BODY))));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
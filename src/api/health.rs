@@ -0,0 +1,43 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::redis::{RedisHealth, RedisManager};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthReport {
+    /// `ok`, or `degraded` when Redis can't be reached.
+    #[schema(value_type = String)]
+    pub status: &'static str,
+    pub redis: RedisHealth,
+}
+
+/// Reports whether Redis is reachable, with `503 Service Unavailable` if it
+/// isn't.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Redis is reachable", body = HealthReport),
+        (status = 503, description = "Redis can't be reached", body = HealthReport)
+    )
+)]
+pub async fn health(redis: web::Data<RedisManager>) -> impl Responder {
+    let redis = redis.health().await;
+    if redis.latency_ms.is_some() {
+        HttpResponse::Ok().json(HealthReport {
+            status: "ok",
+            redis,
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().json(HealthReport {
+            status: "degraded",
+            redis,
+        })
+    }
+}
+
+pub fn configure_health(cfg: &mut web::ServiceConfig) {
+    cfg.route("/health", web::get().to(health));
+}
@@ -0,0 +1,273 @@
+use actix_web::{HttpResponse, ResponseError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{
+    api::validation::Validate,
+    error::{DatabaseErrorWrapper, ValidationErrors},
+};
+
+/// Why a patch was not saved. Patch handlers read, merge and save in one
+/// transaction, which rolls back on either.
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error(transparent)]
+    Invalid(ValidationErrors),
+    #[error(transparent)]
+    Database(#[from] DatabaseErrorWrapper),
+}
+
+impl From<ValidationErrors> for PatchError {
+    fn from(errors: ValidationErrors) -> Self {
+        PatchError::Invalid(errors)
+    }
+}
+
+impl From<diesel::result::Error> for PatchError {
+    fn from(error: diesel::result::Error) -> Self {
+        PatchError::Database(DatabaseErrorWrapper(error))
+    }
+}
+
+impl ResponseError for PatchError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PatchError::Invalid(errors) => errors.error_response(),
+            PatchError::Database(err) => err.error_response(),
+        }
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            PatchError::Invalid(errors) => errors.status_code(),
+            PatchError::Database(err) => err.status_code(),
+        }
+    }
+}
+
+impl From<PatchError> for HttpResponse {
+    fn from(error: PatchError) -> Self {
+        error.error_response()
+    }
+}
+
+/// Applies an RFC 7396 JSON Merge Patch to `target`. Members of `patch`
+/// replace those of `target`, objects are merged recursively and `null`
+/// removes a member.
+pub fn merge(target: &mut Value, patch: &Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                for (key, value) in patch {
+                    if value.is_null() {
+                        target.remove(key);
+                    } else {
+                        merge(target.entry(key.clone()).or_insert(Value::Null), value);
+                    }
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+/// Why `value` can't be merged into a field, from the error deserializing it.
+fn field_error(value: &Value, error: serde_json::Error) -> String {
+    if value.is_null() {
+        "must not be null".to_string()
+    } else {
+        error.to_string()
+    }
+}
+
+/// `current` with `patch` merged into it. Fields the patch leaves out keep
+/// their value and `null` clears optional ones. Fields in `fixed`, such as
+/// ids, can't be changed. The result must pass [`Validate`].
+pub fn apply<T>(
+    current: &T,
+    patch: &Map<String, Value>,
+    fixed: &[&str],
+) -> Result<T, ValidationErrors>
+where
+    T: Serialize + DeserializeOwned + Validate,
+{
+    let current = serde_json::to_value(current).expect("resources serialize to JSON");
+
+    let mut errors = ValidationErrors::new();
+    for (field, value) in patch {
+        match current.get(field) {
+            None => errors.add(field, "is not a field of this resource"),
+            Some(existing) if fixed.contains(&field.as_str()) && existing != value => {
+                errors.add(field, "cannot be changed")
+            }
+            Some(_) => {}
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut patched = current.clone();
+    merge(&mut patched, &Value::Object(patch.clone()));
+    match serde_json::from_value::<T>(patched) {
+        Ok(patched) => {
+            patched.validate(&mut errors);
+            if errors.is_empty() {
+                Ok(patched)
+            } else {
+                Err(errors)
+            }
+        }
+        Err(_) => {
+            // Find the members that can't be merged on their own.
+            for (field, value) in patch {
+                let mut single = current.clone();
+                let mut member = Map::new();
+                member.insert(field.clone(), value.clone());
+                merge(&mut single, &Value::Object(member));
+                if let Err(e) = serde_json::from_value::<T>(single) {
+                    errors.add(field, &field_error(value, e));
+                }
+            }
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: i32,
+        name: String,
+        description: Option<String>,
+        price: i32,
+    }
+
+    impl Validate for Item {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.not_blank("name", &self.name);
+            errors.at_least("price", self.price, 0);
+        }
+    }
+
+    fn item() -> Item {
+        Item {
+            id: 1,
+            name: "Shoe".to_string(),
+            description: Some("Red".to_string()),
+            price: 100,
+        }
+    }
+
+    fn patch(patch: Value) -> Map<String, Value> {
+        match patch {
+            Value::Object(patch) => patch,
+            _ => panic!("patches are objects"),
+        }
+    }
+
+    fn fields(errors: &ValidationErrors) -> Vec<&str> {
+        errors
+            .errors
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn merges_recursively_and_removes_nulls() {
+        let mut target = json!({ "a": "b", "c": { "d": "e", "f": "g" }, "h": [1] });
+        merge(
+            &mut target,
+            &json!({ "a": "z", "c": { "f": null, "x": 1 }, "h": [2, 3] }),
+        );
+        assert_eq!(
+            target,
+            json!({ "a": "z", "c": { "d": "e", "x": 1 }, "h": [2, 3] })
+        );
+    }
+
+    #[test]
+    fn replaces_non_objects() {
+        let mut target = json!({ "a": "b" });
+        merge(&mut target, &json!(["c"]));
+        assert_eq!(target, json!(["c"]));
+
+        let mut target = json!("a");
+        merge(&mut target, &json!({ "b": "c", "d": null }));
+        assert_eq!(target, json!({ "b": "c" }));
+    }
+
+    #[test]
+    fn keeps_fields_the_patch_leaves_out() {
+        let patched = apply(&item(), &patch(json!({ "price": 80 })), &["id"]).unwrap();
+        assert_eq!(
+            patched,
+            Item {
+                price: 80,
+                ..item()
+            }
+        );
+    }
+
+    #[test]
+    fn null_clears_optional_fields() {
+        let patched = apply(&item(), &patch(json!({ "description": null })), &["id"]).unwrap();
+        assert_eq!(patched.description, None);
+    }
+
+    #[test]
+    fn null_is_rejected_for_required_fields() {
+        let errors = apply(&item(), &patch(json!({ "name": null })), &["id"]).unwrap_err();
+        assert_eq!(fields(&errors), ["name"]);
+        assert_eq!(errors.errors[0].message, "must not be null");
+    }
+
+    #[test]
+    fn fixed_fields_can_only_be_repeated() {
+        assert!(apply(&item(), &patch(json!({ "id": 1, "price": 5 })), &["id"]).is_ok());
+        let errors = apply(&item(), &patch(json!({ "id": 2 })), &["id"]).unwrap_err();
+        assert_eq!(fields(&errors), ["id"]);
+        assert_eq!(errors.errors[0].message, "cannot be changed");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let errors = apply(&item(), &patch(json!({ "colour": "red" })), &["id"]).unwrap_err();
+        assert_eq!(fields(&errors), ["colour"]);
+    }
+
+    #[test]
+    fn wrong_types_are_reported_per_field() {
+        let errors = apply(
+            &item(),
+            &patch(json!({ "price": "cheap", "name": 3, "description": "Blue" })),
+            &["id"],
+        )
+        .unwrap_err();
+        let mut fields = fields(&errors);
+        fields.sort_unstable();
+        assert_eq!(fields, ["name", "price"]);
+    }
+
+    #[test]
+    fn patched_resources_are_validated() {
+        let errors = apply(
+            &item(),
+            &patch(json!({ "name": " ", "price": -1 })),
+            &["id"],
+        )
+        .unwrap_err();
+        assert_eq!(fields(&errors), ["name", "price"]);
+    }
+}
@@ -1 +1,8 @@
+pub mod conditional;
+pub mod health;
+pub mod merge_patch;
+pub mod openapi;
+pub mod pagination;
+pub mod request_id;
 pub mod rest;
+pub mod validation;
@@ -0,0 +1,124 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use utoipa::OpenApi;
+
+use crate::{
+    api::health::HealthReport,
+    error::{AppError, FieldError},
+    redis::RedisHealth,
+    services::{
+        brand::service::BrandApi, cart::service::CartApi, category::service::CategoryApi,
+        discount::service::DiscountApi, order::service::OrderApi, product::service::ProductApi,
+        stock::service::StockApi, webhook::service::WebhookApi,
+    },
+};
+
+/// Responses shared by the routes of every service. Errors are
+/// `application/problem+json` documents.
+pub mod responses {
+    use utoipa::ToResponse;
+
+    use crate::error::AppError;
+
+    /// The body or parameters are invalid, or reference a row that doesn't exist
+    #[derive(ToResponse)]
+    #[response(content_type = "application/problem+json")]
+    pub struct BadRequest(pub AppError);
+
+    /// No row has the given id
+    #[derive(ToResponse)]
+    #[response(content_type = "application/problem+json")]
+    pub struct NotFound(pub AppError);
+
+    /// A row with the same unique key already exists
+    #[derive(ToResponse)]
+    #[response(content_type = "application/problem+json")]
+    pub struct Conflict(pub AppError);
+
+    /// The database or connection pool failed
+    #[derive(ToResponse)]
+    #[response(content_type = "application/problem+json")]
+    pub struct InternalError(pub AppError);
+
+    /// Fields of the body failed validation. `errors` lists each of them
+    #[derive(ToResponse)]
+    #[response(content_type = "application/problem+json")]
+    pub struct UnprocessableEntity(pub AppError);
+
+    /// The `If-None-Match` or `If-Modified-Since` validators are current
+    #[derive(ToResponse)]
+    pub struct NotModified;
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ecom-engine REST API",
+        description = "Catalog, cart and webhook routes served by `rest_api`."
+    ),
+    paths(crate::api::health::health),
+    components(
+        schemas(HealthReport, RedisHealth, AppError, FieldError),
+        responses(
+            responses::BadRequest,
+            responses::NotFound,
+            responses::Conflict,
+            responses::InternalError,
+            responses::UnprocessableEntity,
+            responses::NotModified
+        )
+    ),
+    tags((name = "health", description = "State of the server and its Redis connection"))
+)]
+struct ApiDoc;
+
+/// OpenAPI 3 document of every route `rest_api` serves, built from the
+/// `ApiDoc` of each service next to its `configure`.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    for service in [
+        BrandApi::openapi(),
+        CartApi::openapi(),
+        CategoryApi::openapi(),
+        DiscountApi::openapi(),
+        OrderApi::openapi(),
+        ProductApi::openapi(),
+        StockApi::openapi(),
+        WebhookApi::openapi(),
+    ] {
+        openapi.merge(service);
+    }
+    openapi
+}
+
+/// Swagger UI, loaded from a CDN, showing `/openapi.json`.
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>ecom-engine REST API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>
+"##;
+
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(openapi())
+}
+
+pub async fn docs() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(DOCS_PAGE)
+}
+
+pub fn configure_openapi(cfg: &mut web::ServiceConfig) {
+    cfg.route("/openapi.json", web::get().to(openapi_json))
+        .route("/docs", web::get().to(docs));
+}
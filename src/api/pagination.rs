@@ -0,0 +1,505 @@
+use actix_web::HttpRequest;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use diesel::{
+    dsl::sql,
+    expression::{BoxableExpression, SqlLiteral},
+    pg::Pg,
+    sql_types::{Bool, Integer, Text, Timestamp},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::form_urlencoded;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::ValidationErrors,
+    services::{
+        brand::model::Brand,
+        cart::model::CartWithOrderLines,
+        discount::model::Discount,
+        order::model::Warehouse,
+        product::model::{Product, ProductSearchHit, ProductWithAttributes},
+    },
+};
+
+pub const DEFAULT_LIMIT: i32 = 50;
+pub const MAX_LIMIT: i32 = 200;
+
+/// Paging and sorting parameters of the list endpoints. Without `offset` or
+/// `cursor` the first page is returned.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Items per page, from 1 to 200. Defaults to 50.
+    pub limit: Option<i32>,
+    /// Items to skip. Can't be combined with `cursor`.
+    pub offset: Option<i32>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Field to sort by, prefixed with `-` for descending order. Defaults to `id`.
+    pub sort: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKind {
+    Integer,
+    Text,
+    Timestamp,
+}
+
+/// A field a list can be sorted by and the column holding it.
+#[derive(Debug, Clone, Copy)]
+pub struct SortField {
+    pub name: &'static str,
+    pub column: &'static str,
+    pub kind: SortKind,
+}
+
+impl SortField {
+    pub const fn integer(name: &'static str, column: &'static str) -> Self {
+        SortField {
+            name,
+            column,
+            kind: SortKind::Integer,
+        }
+    }
+
+    pub const fn text(name: &'static str, column: &'static str) -> Self {
+        SortField {
+            name,
+            column,
+            kind: SortKind::Text,
+        }
+    }
+
+    pub const fn timestamp(name: &'static str, column: &'static str) -> Self {
+        SortField {
+            name,
+            column,
+            kind: SortKind::Timestamp,
+        }
+    }
+}
+
+/// Where the previous page ended, handed to clients as an opaque string.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    value: Value,
+    id: i32,
+}
+
+#[derive(Debug, Clone)]
+enum SortValue {
+    Integer(i32),
+    Text(String),
+    Timestamp(NaiveDateTime),
+}
+
+impl SortValue {
+    fn from_json(kind: SortKind, value: &Value) -> Option<Self> {
+        match kind {
+            SortKind::Integer => value
+                .as_i64()
+                .and_then(|value| i32::try_from(value).ok())
+                .map(SortValue::Integer),
+            SortKind::Text => value
+                .as_str()
+                .map(|value| SortValue::Text(value.to_string())),
+            SortKind::Timestamp => value
+                .as_str()
+                .and_then(|value| value.parse().ok())
+                .map(SortValue::Timestamp),
+        }
+    }
+}
+
+/// [`PageParams`] checked against the fields a list can be sorted by.
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub limit: i32,
+    pub offset: Option<i32>,
+    field: SortField,
+    id: SortField,
+    descending: bool,
+    after: Option<(SortValue, i32)>,
+}
+
+impl PageParams {
+    /// Checks the parameters. `fields` are the sortable fields of the list and
+    /// must include `id`, which breaks ties so every row has one position.
+    pub fn resolve(&self, fields: &[SortField]) -> Result<PageRequest, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        errors.at_least("limit", limit, 1);
+        errors.at_most("limit", limit, MAX_LIMIT);
+        if let Some(offset) = self.offset {
+            errors.at_least("offset", offset, 0);
+            if self.cursor.is_some() {
+                errors.add("cursor", "can't be combined with offset");
+            }
+        }
+
+        let id = *fields
+            .iter()
+            .find(|field| field.name == "id")
+            .expect("sortable fields include id");
+        let sort = self.sort.as_deref().unwrap_or("id");
+        let (name, descending) = match sort.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (sort, false),
+        };
+        let field = fields.iter().find(|field| field.name == name).copied();
+        if field.is_none() {
+            let names: Vec<&str> = fields.iter().map(|field| field.name).collect();
+            errors.one_of("sort", name, &names);
+        }
+
+        let after = match (&self.cursor, field) {
+            (Some(cursor), Some(field)) => match decode(cursor) {
+                Some(cursor) if cursor.sort != sort => {
+                    errors.add("cursor", "was issued for a different sort");
+                    None
+                }
+                Some(cursor) => match SortValue::from_json(field.kind, &cursor.value) {
+                    Some(value) => Some((value, cursor.id)),
+                    None => {
+                        errors.add("cursor", "is not a valid cursor");
+                        None
+                    }
+                },
+                None => {
+                    errors.add("cursor", "is not a valid cursor");
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        match field {
+            Some(field) if errors.is_empty() => Ok(PageRequest {
+                limit,
+                offset: self.offset,
+                field,
+                id,
+                descending,
+                after,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// `ILIKE` pattern matching values that contain `text`.
+pub fn contains(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn decode(cursor: &str) -> Option<Cursor> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+impl PageRequest {
+    fn sort(&self) -> String {
+        if self.descending {
+            format!("-{}", self.field.name)
+        } else {
+            self.field.name.to_string()
+        }
+    }
+
+    /// `ORDER BY` of the page: the sort field, then `id`.
+    pub fn order(&self) -> SqlLiteral<Bool> {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        sql::<Bool>(&format!(
+            "{} {}, {} {}",
+            self.field.column, direction, self.id.column, direction
+        ))
+    }
+
+    /// Rows to load: one more than the limit, telling [`PageRequest::page`]
+    /// whether another page follows.
+    pub fn load_limit(&self) -> i64 {
+        i64::from(self.limit) + 1
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.map(i64::from).unwrap_or(0)
+    }
+
+    /// Filter to the rows after the cursor, if one was given.
+    pub fn seek<QS>(&self) -> Option<Box<dyn BoxableExpression<QS, Pg, SqlType = Bool>>> {
+        let (value, id) = self.after.clone()?;
+        let seek = format!(
+            "({}, {}) {} (",
+            self.field.column,
+            self.id.column,
+            if self.descending { "<" } else { ">" }
+        );
+        Some(match value {
+            SortValue::Integer(value) => Box::new(
+                sql::<Bool>(&seek)
+                    .bind::<Integer, _>(value)
+                    .sql(", ")
+                    .bind::<Integer, _>(id)
+                    .sql(")"),
+            ),
+            SortValue::Text(value) => Box::new(
+                sql::<Bool>(&seek)
+                    .bind::<Text, _>(value)
+                    .sql(", ")
+                    .bind::<Integer, _>(id)
+                    .sql(")"),
+            ),
+            SortValue::Timestamp(value) => Box::new(
+                sql::<Bool>(&seek)
+                    .bind::<Timestamp, _>(value)
+                    .sql(", ")
+                    .bind::<Integer, _>(id)
+                    .sql(")"),
+            ),
+        })
+    }
+
+    /// The page of `rows`, loaded sorted by [`PageRequest::order`] and
+    /// limited by the other methods. `row` picks the part of an item holding the
+    /// sort field and `id`, which the next cursor is made from.
+    pub fn page<T, R>(
+        &self,
+        request: &HttpRequest,
+        mut rows: Vec<T>,
+        total: i64,
+        row: impl Fn(&T) -> &R,
+    ) -> Page<T>
+    where
+        R: Serialize,
+    {
+        let has_more = rows.len() > self.limit as usize;
+        rows.truncate(self.limit as usize);
+        let next_cursor = rows
+            .last()
+            .filter(|_| has_more)
+            .and_then(|last| serde_json::to_value(row(last)).ok())
+            .and_then(|last| {
+                let cursor = Cursor {
+                    sort: self.sort(),
+                    value: last.get(self.field.name)?.clone(),
+                    id: last
+                        .get("id")?
+                        .as_i64()
+                        .and_then(|id| i32::try_from(id).ok())?,
+                };
+                let json = serde_json::to_vec(&cursor).ok()?;
+                Some(URL_SAFE_NO_PAD.encode(json))
+            });
+        let next = next_cursor.as_ref().map(|cursor| match self.offset {
+            Some(offset) => link(request, "offset", &(offset + self.limit).to_string()),
+            None => link(request, "cursor", cursor),
+        });
+        Page {
+            items: rows,
+            total,
+            limit: self.limit,
+            offset: self.offset,
+            next_cursor,
+            next,
+        }
+    }
+}
+
+/// The requested URL with `offset` or `cursor` set to `value`.
+fn link(request: &HttpRequest, param: &str, value: &str) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    for (name, existing) in form_urlencoded::parse(request.query_string().as_bytes()) {
+        if name != "offset" && name != "cursor" {
+            query.append_pair(&name, &existing);
+        }
+    }
+    query.append_pair(param, value);
+    format!("{}?{}", request.path(), query.finish())
+}
+
+/// One page of a list.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    BrandPage = Page<Brand>,
+    CartPage = Page<CartWithOrderLines>,
+    DiscountPage = Page<Discount>,
+    ProductPage = Page<ProductWithAttributes>,
+    ProductSearchPage = Page<ProductSearchHit>,
+    RawProductPage = Page<Product>,
+    WarehousePage = Page<Warehouse>
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Items matching the filters, across every page.
+    pub total: i64,
+    pub limit: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i32>,
+    /// Pass as `cursor` to get the next page. Absent on the last page.
+    pub next_cursor: Option<String>,
+    /// Link to the next page with the same filters and sort.
+    pub next: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// The page of `rows` of a list paged only by offset, loaded with
+    /// `limit + 1` rows starting at `offset`.
+    pub fn by_offset(
+        request: &HttpRequest,
+        mut rows: Vec<T>,
+        total: i64,
+        limit: i32,
+        offset: i32,
+    ) -> Self {
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        Page {
+            items: rows,
+            total,
+            limit,
+            offset: Some(offset),
+            next_cursor: None,
+            next: has_more.then(|| link(request, "offset", &(offset + limit).to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test::TestRequest, web};
+    use serde_json::json;
+
+    use super::*;
+
+    const FIELDS: &[SortField] = &[
+        SortField::integer("id", "items.id"),
+        SortField::text("name", "items.name"),
+        SortField::integer("price", "items.price"),
+    ];
+
+    fn params(query: &str) -> PageParams {
+        web::Query::<PageParams>::from_query(query)
+            .unwrap()
+            .into_inner()
+    }
+
+    fn fields(errors: &ValidationErrors) -> Vec<&str> {
+        errors
+            .errors
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn defaults_to_the_first_page_by_id() {
+        let page = params("").resolve(FIELDS).unwrap();
+        assert_eq!((page.limit, page.offset), (DEFAULT_LIMIT, None));
+        assert_eq!(page.sort(), "id");
+        assert!(page.after.is_none());
+    }
+
+    #[test]
+    fn parses_descending_sorts() {
+        let page = params("sort=-price&limit=10").resolve(FIELDS).unwrap();
+        assert_eq!(page.field.name, "price");
+        assert!(page.descending);
+        assert_eq!(page.sort(), "-price");
+        assert_eq!(page.load_limit(), 11);
+    }
+
+    #[test]
+    fn rejects_bad_parameters() {
+        for (query, field) in [
+            ("limit=0", "limit"),
+            ("limit=201", "limit"),
+            ("offset=-1", "offset"),
+            ("sort=colour", "sort"),
+            ("sort=-colour", "sort"),
+            ("offset=10&cursor=abc", "cursor"),
+            ("cursor=not-a-cursor", "cursor"),
+        ] {
+            let errors = params(query).resolve(FIELDS).unwrap_err();
+            assert!(fields(&errors).contains(&field), "{}", query);
+        }
+    }
+
+    #[test]
+    fn next_cursor_continues_after_the_last_row() {
+        let request = TestRequest::get()
+            .uri("/items?sort=-name&limit=2&brand_id=3")
+            .to_http_request();
+        let first = params("sort=-name&limit=2").resolve(FIELDS).unwrap();
+        let rows = vec![
+            json!({ "id": 4, "name": "Zebra" }),
+            json!({ "id": 9, "name": "Yak" }),
+            json!({ "id": 2, "name": "Xerus" }),
+        ];
+
+        let page = first.page(&request, rows, 10, |row| row);
+
+        assert_eq!(page.items.len(), 2);
+        let cursor = page.next_cursor.unwrap();
+        let next = page.next.unwrap();
+        assert!(next.starts_with("/items?sort=-name&limit=2&brand_id=3&cursor="));
+
+        let second = PageParams {
+            cursor: Some(cursor.clone()),
+            ..params("sort=-name&limit=2")
+        }
+        .resolve(FIELDS)
+        .unwrap();
+        match second.after {
+            Some((SortValue::Text(name), id)) => assert_eq!((name.as_str(), id), ("Yak", 9)),
+            after => panic!("unexpected cursor position {:?}", after),
+        }
+
+        let errors = PageParams {
+            cursor: Some(cursor),
+            ..params("sort=name&limit=2")
+        }
+        .resolve(FIELDS)
+        .unwrap_err();
+        assert_eq!(errors.errors[0].message, "was issued for a different sort");
+    }
+
+    #[test]
+    fn last_page_has_no_cursor() {
+        let request = TestRequest::get().uri("/items").to_http_request();
+        let page = params("limit=2").resolve(FIELDS).unwrap().page(
+            &request,
+            vec![json!({ "id": 1 }), json!({ "id": 2 })],
+            2,
+            |row| row,
+        );
+        assert!(page.next_cursor.is_none());
+        assert!(page.next.is_none());
+    }
+
+    #[test]
+    fn offset_pages_link_to_the_next_offset() {
+        let request = TestRequest::get()
+            .uri("/items?offset=4&limit=2")
+            .to_http_request();
+        let page = params("offset=4&limit=2").resolve(FIELDS).unwrap().page(
+            &request,
+            vec![json!({ "id": 5 }), json!({ "id": 6 }), json!({ "id": 7 })],
+            9,
+            |row| row,
+        );
+        assert_eq!(page.next.as_deref(), Some("/items?limit=2&offset=6"));
+    }
+
+    #[test]
+    fn contains_escapes_like_wildcards() {
+        assert_eq!(contains(r"50%_off\"), r"%50\%\_off\\%");
+    }
+}
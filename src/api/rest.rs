@@ -1,11 +1,14 @@
 use actix_web::http::header::{
     HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
-    ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE,
+    ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH,
 };
 use actix_web::middleware::DefaultHeaders;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use diesel::{r2d2::ConnectionManager, PgConnection};
 use r2d2::Pool;
 
+use crate::error::{message, problem, AppError};
 use crate::postgres::ConnectionPool;
 
 pub const DB_CON_RETRY_ATTEMPTS: u8 = 3;
@@ -48,8 +51,16 @@ pub fn local_dev_cors() -> actix_cors::Cors {
             ACCESS_CONTROL_ALLOW_METHODS,
             ACCESS_CONTROL_ALLOW_ORIGIN,
             CONTENT_LENGTH,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
         ])
         .allowed_header("x-cache-status")
+        .allowed_header(crate::api::request_id::REQUEST_ID_HEADER)
+        .expose_headers(vec![
+            crate::cache::CACHE_STATUS_HEADER,
+            ETAG.as_str(),
+            crate::api::request_id::REQUEST_ID_HEADER,
+        ])
         .max_age(3600)
 }
 
@@ -80,3 +91,48 @@ pub async fn resolve_connection_pool(url: &str) -> ConnectionPool {
     };
     pool
 }
+
+/// The field a serde error names, as in ``missing field `name` ``.
+fn named_field(error: &str) -> Option<String> {
+    let start = error.find("field `")? + "field `".len();
+    let end = error[start..].find('`')?;
+    Some(error[start..start + end].to_string())
+}
+
+/// A problem for a body, path or query that couldn't be extracted.
+fn malformed_request(status: StatusCode, error: String) -> actix_web::Error {
+    let problem_type = match status {
+        StatusCode::NOT_FOUND => problem::NOT_FOUND,
+        _ => problem::BAD_REQUEST,
+    };
+    let title = status.canonical_reason().unwrap_or(message::BAD_REQUEST);
+    let mut problem = AppError::new(status, problem_type, title);
+    problem.field = named_field(&error);
+    problem.with_detail(error).into()
+}
+
+/// Reports malformed JSON bodies, paths and queries as problem documents.
+pub fn configure_extractors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        web::JsonConfig::default()
+            .error_handler(|err, _| malformed_request(err.status_code(), err.to_string())),
+    )
+    .app_data(
+        web::PathConfig::default()
+            .error_handler(|err, _| malformed_request(err.status_code(), err.to_string())),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|err, _| malformed_request(err.status_code(), err.to_string())),
+    );
+}
+
+/// Default service answering requests no route matches.
+pub async fn route_not_found(request: HttpRequest) -> HttpResponse {
+    AppError::not_found(format!(
+        "No route matches {} {}",
+        request.method(),
+        request.path()
+    ))
+    .into()
+}
@@ -0,0 +1,61 @@
+use std::ops::Deref;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+
+use crate::error::ValidationErrors;
+
+/// Rules the fields of a request payload must follow, declared next to the
+/// model and checked by [`Valid`] before the handler runs.
+pub trait Validate {
+    /// Adds an error to `errors` for each field that breaks a rule.
+    fn validate(&self, errors: &mut ValidationErrors);
+
+    fn validation_errors(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        self.validate(&mut errors);
+        errors
+    }
+}
+
+/// A JSON body that passed [`Validate`]. Extraction fails with a `422`
+/// problem listing every invalid field, so invalid payloads never reach the
+/// handler.
+#[derive(Debug)]
+pub struct Valid<T>(pub T);
+
+impl<T> Valid<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Valid<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for Valid<T>
+where
+    T: DeserializeOwned + Validate + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(request, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            let errors = value.validation_errors();
+            if errors.is_empty() {
+                Ok(Valid(value))
+            } else {
+                Err(errors.into())
+            }
+        })
+    }
+}
@@ -2,13 +2,28 @@ use std::sync::Arc;
 
 use actix_web::{web, App, HttpServer};
 use ecom_engine::{
-    api::rest::local_dev_headers,
+    api::{
+        health::configure_health,
+        request_id::{RequestId, REQUEST_ID_HEADER},
+        rest::{configure_extractors, local_dev_headers, resolve_connection_pool, route_not_found},
+    },
     cfg,
     logger::logger::DETAILED_FORMAT,
-    stream::{create_add_update_route, StreamChannel, UpdateProcessor},
+    outbox::{run_outbox_relay, OutboxConfig},
+    redis::{RedisManager, RedisOptions},
+    services::webhook::dispatch::{run_webhook_dispatcher, DispatchConfig},
+    stream::{
+        backend::backend_from_env,
+        batch::BatchConfig,
+        create_add_update_route,
+        dead_letter::create_dead_letter_routes,
+        event_log::{create_event_log_routes, EventLogConfig},
+        limits::{IngestLimits, API_KEY_HEADER},
+        mode::modes_from_env,
+        StreamChannel, UpdateProcessor,
+    },
     CONFIG_FILE_PATH,
 };
-use tokio::sync::{mpsc, Notify};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -17,20 +32,48 @@ async fn main() -> std::io::Result<()> {
     env_logger::init();
     let env_config: cfg::Env = cfg::Config::from_file(CONFIG_FILE_PATH).into();
 
-    let redis_url = Arc::new(env_config.redis_url);
-    let notify = Arc::new(Notify::new());
+    let batch_config = BatchConfig::from_env(&env_config);
+    let modes = modes_from_env(&env_config);
+    let limits = IngestLimits::from_env(&env_config);
+    let redis_options = RedisOptions::from_env(&env_config);
+    let redis = match RedisManager::new(&env_config.redis_url, redis_options) {
+        Ok(redis) => redis,
+        Err(err) => {
+            log::error!("Invalid Redis URL: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let backend = backend_from_env(&env_config, redis.clone());
+    let outbox_config = OutboxConfig::from_env(&env_config);
+    let event_log = EventLogConfig::from_env(&env_config);
+    let dispatch_config = DispatchConfig::from_env(&env_config);
+    let pool = resolve_connection_pool(&env_config.db_url).await;
 
-    let (tx, rx) = mpsc::channel(100);
-
-    let processor = Arc::new(UpdateProcessor::new(redis_url.to_string()).await);
+    let processor = match UpdateProcessor::new(
+        backend,
+        pool.clone(),
+        batch_config,
+        modes,
+        limits,
+        event_log,
+    )
+    .await
+    {
+        Ok(processor) => Arc::new(processor),
+        Err(err) => {
+            log::error!("Failed to start the update processor: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     let processor_clone = Arc::clone(&processor);
 
-    let product_update_channel_key: String = StreamChannel::ProductUpdates.into();
+    tokio::spawn(run_outbox_relay(pool.clone(), redis.clone(), outbox_config));
+    tokio::spawn(run_webhook_dispatcher(pool, dispatch_config));
 
     tokio::spawn(async move {
         processor_clone
-            .process_updates(product_update_channel_key, rx)
+            .process_updates(StreamChannel::ALL.to_vec())
             .await;
     });
 
@@ -43,14 +86,24 @@ async fn main() -> std::io::Result<()> {
                     .send_wildcard()
                     .allow_any_origin()
                     .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-                    .allowed_headers(vec!["Content-Type", "Authorization"])
+                    .allowed_headers(vec![
+                        "Content-Type",
+                        "Authorization",
+                        API_KEY_HEADER,
+                        REQUEST_ID_HEADER,
+                    ])
+                    .expose_headers(vec![REQUEST_ID_HEADER])
                     .max_age(3600),
             )
-            .app_data(web::Data::new(notify.clone()))
-            .app_data(web::Data::new(redis_url.clone()))
-            .app_data(web::Data::new(tx.clone()))
+            .wrap(RequestId)
+            .app_data(web::Data::new(redis.clone()))
             .app_data(web::Data::new(processor.clone()))
             .configure(create_add_update_route)
+            .configure(create_dead_letter_routes)
+            .configure(create_event_log_routes)
+            .configure(configure_health)
+            .configure(configure_extractors)
+            .default_service(web::route().to(route_not_found))
     })
     .bind("0.0.0.0:3030")?
     .run()
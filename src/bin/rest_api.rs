@@ -2,13 +2,21 @@ use actix_web::{web, App};
 use ecom_engine::api::rest::{resolve_connection_pool, DEFAULT_PORT};
 use ecom_engine::logger::logger::DETAILED_FORMAT;
 use ecom_engine::{
-    api::rest::{local_dev_cors, local_dev_headers},
+    api::health::configure_health,
+    api::openapi::configure_openapi,
+    api::request_id::{RequestId, REQUEST_ID_HEADER},
+    api::rest::{configure_extractors, local_dev_cors, local_dev_headers, route_not_found},
+    cache::{Cache, CacheTtls, CACHE_STATUS_HEADER},
+    invalidation::run_cache_invalidation_listener,
+    redis::{RedisManager, RedisOptions},
     services::brand::service::configure as brand,
     services::cart::service::configure as cart,
+    services::category::service::configure as category,
     services::discount::service::configure as discount,
     services::order::service::configure as order,
     services::product::service::configure as product,
     services::stock::service::configure as stock,
+    services::webhook::{dispatch::DispatchConfig, service::configure as webhook},
 };
 
 #[actix_web::main]
@@ -21,6 +29,20 @@ async fn main() -> Result<(), std::io::Error> {
     let host_clone = env.api_host.clone();
     let port_clone = env.api_port.clone();
     let pool = resolve_connection_pool(&env.db_url).await;
+    let redis = match RedisManager::new(&env.redis_url, RedisOptions::from_env(&env)) {
+        Ok(redis) => redis,
+        Err(err) => {
+            log::error!("Invalid Redis URL: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let cache = web::Data::new(Cache::new(redis.clone(), CacheTtls::from_env(&env)));
+    tokio::spawn(run_cache_invalidation_listener(
+        env.db_url.clone(),
+        cache.get_ref().clone(),
+    ));
+    let redis = web::Data::new(redis);
+    let webhook_config = web::Data::new(DispatchConfig::from_env(&env));
 
     actix_web::HttpServer::new(move || {
         let logger = actix_web::middleware::Logger::new(DETAILED_FORMAT);
@@ -30,8 +52,15 @@ async fn main() -> Result<(), std::io::Error> {
             "localhost" | "0.0.0.0" | "127.0.0.1" => local_dev_cors(),
             _ => actix_cors::Cors::default()
                 .allowed_origin(&env.api_host)
-                .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-                .allowed_headers(vec!["Content-Type", "Authorization"])
+                .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
+                .allowed_headers(vec![
+                    "Content-Type",
+                    "Authorization",
+                    "If-None-Match",
+                    "If-Modified-Since",
+                    REQUEST_ID_HEADER,
+                ])
+                .expose_headers(vec![CACHE_STATUS_HEADER, "ETag", REQUEST_ID_HEADER])
                 .max_age(3600),
         };
         let headers = match env.api_host.as_str() {
@@ -43,15 +72,28 @@ async fn main() -> Result<(), std::io::Error> {
             .wrap(cors)
             .wrap(headers)
             .wrap(logger)
+            .wrap(RequestId)
             .configure(brand)
             .configure(product)
             .configure(cart)
+            .configure(category)
             .configure(discount)
             .configure(order)
             .configure(stock)
+            .configure(webhook)
+            .configure(configure_health)
+            .configure(configure_openapi)
+            .configure(configure_extractors)
+            .default_service(web::route().to(route_not_found))
             .app_data(pool_app_data)
+            .app_data(cache.clone())
+            .app_data(redis.clone())
+            .app_data(webhook_config.clone())
     })
-    .bind((host_clone, port_clone.parse::<u16>().unwrap_or(DEFAULT_PORT)))?
+    .bind((
+        host_clone,
+        port_clone.parse::<u16>().unwrap_or(DEFAULT_PORT),
+    ))?
     .run()
     .await
 }
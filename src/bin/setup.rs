@@ -1,9 +1,12 @@
 use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Select};
+use dialoguer::{theme::ColorfulTheme, Input, Select};
 use ecom_engine::docker::DockerConfig;
+use ecom_engine::stream::bulk::{BulkReport, LineStatus};
+use reqwest::blocking::Body;
+use reqwest::header::CONTENT_TYPE;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::process::Command;
 
@@ -13,19 +16,29 @@ use std::process::Command;
 struct Cli {
     #[arg(short, long, default_value = "menu")]
     command: String,
+    /// JSONL file of updates for `bulk_update`
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+    /// Address of the data stream server for `bulk_update`
+    #[arg(long, default_value = "127.0.0.1:3030")]
+    stream_address: String,
+    /// Only validate the updates in `bulk_update`
+    #[arg(long)]
+    dry_run: bool,
 }
 
 fn main() {
     let cli = Cli::parse();
 
     if cli.command == "menu" {
-        menu()
+        menu(&cli)
     } else {
-        process_command(cli.command)
+        process_command(&cli)
     }
 }
 
-fn process_command(command: String) {
+fn process_command(cli: &Cli) {
+    let command = cli.command.as_str();
     if command == "show_compose" {
         load_docker_config().print_docker_compose();
     } else if command == "show_dockerfiles" {
@@ -40,12 +53,17 @@ fn process_command(command: String) {
         docker_compose_up();
     } else if command == "compose_build" {
         docker_compose_build();
+    } else if command == "bulk_update" {
+        match &cli.file {
+            Some(file) => bulk_update(file, &cli.stream_address, cli.dry_run),
+            None => eprintln!("Error: bulk_update requires --file"),
+        }
     } else {
         eprintln!("Error: invalid command: {}", command);
     }
 }
 
-fn menu() {
+fn menu(cli: &Cli) {
     let options = &[
         "Show compose",
         "Show dockerfiles",
@@ -54,6 +72,7 @@ fn menu() {
         "Compose up build",
         "Compose up",
         "Compose build",
+        "Bulk update from file",
         "Exit",
     ];
 
@@ -71,7 +90,16 @@ fn menu() {
         4 => docker_compose_up_build(),
         5 => docker_compose_up(),
         6 => docker_compose_build(),
-        7 => exit(0),
+        7 => {
+            match Input::<String>::with_theme(&ColorfulTheme::default())
+                .with_prompt("JSONL file")
+                .interact_text()
+            {
+                Ok(file) => bulk_update(Path::new(&file), &cli.stream_address, cli.dry_run),
+                Err(e) => eprintln!("Failed to read the file name: {}", e),
+            }
+        }
+        8 => exit(0),
         _ => println!("Unknown command received"),
     }
 }
@@ -147,3 +175,50 @@ fn docker_compose_build() {
         eprintln!("Failed to build Docker Compose");
     }
 }
+
+fn bulk_update(file: &Path, stream_address: &str, dry_run: bool) {
+    println!("Sending {} to {}", file.display(), stream_address);
+
+    let report = match post_bulk_update(file, stream_address, dry_run) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Failed to send {}: {}", file.display(), e);
+            return;
+        }
+    };
+
+    for result in &report.results {
+        if let LineStatus::Rejected { errors } = &result.status {
+            for error in errors {
+                eprintln!("Line {}: {} {}", result.line, error.field, error.message);
+            }
+        }
+    }
+    println!("{} accepted, {} rejected", report.accepted, report.rejected);
+}
+
+/// Streams the file to the data stream's `/add_update/bulk` endpoint.
+fn post_bulk_update(
+    file: &Path,
+    stream_address: &str,
+    dry_run: bool,
+) -> Result<BulkReport, Box<dyn std::error::Error>> {
+    let file = fs::File::open(file)?;
+    let length = file.metadata()?.len();
+
+    let response = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()?
+        .post(format!("http://{}/add_update/bulk", stream_address))
+        .query(&[("dry_run", dry_run)])
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::sized(file, length))
+        .send()?;
+
+    let status = response.status();
+    let body = response.text()?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, body).into());
+    }
+    Ok(serde_json::from_str(&body)?)
+}
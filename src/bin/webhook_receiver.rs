@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use clap::Parser;
+use ecom_engine::services::webhook::dispatch::{
+    verify, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER,
+};
+
+/// Stand-in webhook receiver for local development. Prints every delivery
+/// and whether its signature is valid.
+#[derive(Parser)]
+#[command(name = "Webhook receiver")]
+#[command(about = "Receives and verifies webhook deliveries locally", long_about = None)]
+struct Cli {
+    #[arg(short, long, default_value_t = 3040)]
+    port: u16,
+    /// Secret of the subscription, to verify signatures with
+    #[arg(short, long)]
+    secret: Option<String>,
+    /// Respond with 500 to the first N deliveries, to exercise retries
+    #[arg(long, default_value_t = 0)]
+    fail: u32,
+}
+
+struct Receiver {
+    secret: Option<String>,
+    failures_left: AtomicU32,
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> &'a str {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-")
+}
+
+async fn receive(
+    request: HttpRequest,
+    body: web::Bytes,
+    receiver: web::Data<Receiver>,
+) -> HttpResponse {
+    let signature = match &receiver.secret {
+        Some(secret) if verify(secret, &body, header(&request, SIGNATURE_HEADER)) => "valid",
+        Some(_) => "INVALID",
+        None => "not checked",
+    };
+    println!(
+        "delivery {} ({}), signature {}: {}",
+        header(&request, DELIVERY_HEADER),
+        header(&request, EVENT_HEADER),
+        signature,
+        String::from_utf8_lossy(&body)
+    );
+
+    let failing = receiver
+        .failures_left
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+            left.checked_sub(1)
+        })
+        .is_ok();
+    if failing {
+        HttpResponse::InternalServerError().finish()
+    } else if signature == "INVALID" {
+        HttpResponse::Unauthorized().finish()
+    } else {
+        HttpResponse::NoContent().finish()
+    }
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    let receiver = web::Data::new(Receiver {
+        secret: cli.secret,
+        failures_left: AtomicU32::new(cli.fail),
+    });
+    println!("Receiving webhooks on http://127.0.0.1:{}/", cli.port);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(receiver.clone())
+            .default_service(web::post().to(receive))
+    })
+    .bind(("0.0.0.0", cli.port))?
+    .run()
+    .await
+}
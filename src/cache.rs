@@ -0,0 +1,359 @@
+use std::{future::Future, time::Duration};
+
+use actix_web::{
+    body::{self, BoxBody},
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use log::{info, warn};
+use redis::{AsyncCommands, RedisResult};
+
+use crate::{
+    api::conditional,
+    cfg::Env,
+    error::AppError,
+    redis::{delete_cached_data_matching, get_cached_data, set_cached_data, RedisManager},
+};
+
+/// Set on cached responses to `HIT` or `MISS`.
+pub const CACHE_STATUS_HEADER: &str = "X-Cache-Status";
+pub const CACHE_HIT: &str = "HIT";
+pub const CACHE_MISS: &str = "MISS";
+
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long the validators of a response are remembered after it was last
+/// served, so `Last-Modified` survives cache entries expiring.
+const VALIDATORS_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// What a cached response holds, which decides how long it is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Product,
+    Brand,
+    Discount,
+    Category,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    pub kind: CacheKind,
+    pub key: String,
+    /// Key of the list this is a page of.
+    pub list: Option<String>,
+}
+
+impl CacheKey {
+    /// The key of one page of a list, for the query string that selected it.
+    /// Invalidating the list's key invalidates every page.
+    pub fn with_query(self, query: &str) -> CacheKey {
+        if query.is_empty() {
+            return self;
+        }
+        CacheKey {
+            key: format!("{}?{}", self.key, query),
+            list: Some(self.key.clone()),
+            ..self
+        }
+    }
+}
+
+/// Key of the set holding the keys of the cached pages of `list`.
+fn pages_key(list: &str) -> String {
+    format!("{}:pages", list)
+}
+
+/// Cache keys of the catalog responses. A write invalidates the keys of
+/// everything it changes, including the lists that embed the row.
+pub mod keys {
+    use super::{CacheKey, CacheKind};
+
+    /// Matches every cached response.
+    pub const ALL: &str = "cache:*";
+
+    fn key(kind: CacheKind, key: String) -> CacheKey {
+        CacheKey {
+            kind,
+            key,
+            list: None,
+        }
+    }
+
+    pub fn products() -> CacheKey {
+        key(CacheKind::Product, "cache:products".to_string())
+    }
+
+    pub fn product(id: i32) -> CacheKey {
+        key(CacheKind::Product, format!("cache:product:{}", id))
+    }
+
+    pub fn brands() -> CacheKey {
+        key(CacheKind::Brand, "cache:brands".to_string())
+    }
+
+    pub fn brand(id: i32) -> CacheKey {
+        key(CacheKind::Brand, format!("cache:brand:{}", id))
+    }
+
+    pub fn discounts() -> CacheKey {
+        key(CacheKind::Discount, "cache:discounts".to_string())
+    }
+
+    pub fn discount(id: i32) -> CacheKey {
+        key(CacheKind::Discount, format!("cache:discount:{}", id))
+    }
+
+    pub fn categories() -> CacheKey {
+        key(CacheKind::Category, "cache:categories".to_string())
+    }
+
+    pub fn category(id: i32) -> CacheKey {
+        key(CacheKind::Category, format!("cache:category:{}", id))
+    }
+}
+
+/// How long each kind of response is cached. Each falls back to
+/// `REDIS_CACHE_TTL`, and that to [`DEFAULT_CACHE_TTL`].
+#[derive(Debug, Clone)]
+pub struct CacheTtls {
+    pub products: Duration,
+    pub brands: Duration,
+    pub discounts: Duration,
+    pub categories: Duration,
+}
+
+impl Default for CacheTtls {
+    fn default() -> Self {
+        CacheTtls {
+            products: DEFAULT_CACHE_TTL,
+            brands: DEFAULT_CACHE_TTL,
+            discounts: DEFAULT_CACHE_TTL,
+            categories: DEFAULT_CACHE_TTL,
+        }
+    }
+}
+
+impl CacheTtls {
+    pub fn from_env(env: &Env) -> Self {
+        let parse = |ttl: &Option<String>| {
+            ttl.as_deref()
+                .and_then(|ttl| ttl.parse().ok())
+                .filter(|ttl| *ttl > 0)
+                .map(Duration::from_secs)
+        };
+        let default = parse(&env.redis_cache_ttl).unwrap_or(DEFAULT_CACHE_TTL);
+        CacheTtls {
+            products: parse(&env.redis_cache_ttl_products).unwrap_or(default),
+            brands: parse(&env.redis_cache_ttl_brands).unwrap_or(default),
+            discounts: parse(&env.redis_cache_ttl_discounts).unwrap_or(default),
+            categories: parse(&env.redis_cache_ttl_categories).unwrap_or(default),
+        }
+    }
+
+    pub fn ttl(&self, kind: CacheKind) -> Duration {
+        match kind {
+            CacheKind::Product => self.products,
+            CacheKind::Brand => self.brands,
+            CacheKind::Discount => self.discounts,
+            CacheKind::Category => self.categories,
+        }
+    }
+}
+
+/// Read-through cache of JSON responses in Redis. Redis being unavailable
+/// never fails a request; the response is then served from Postgres.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    redis: RedisManager,
+    ttls: CacheTtls,
+}
+
+impl Cache {
+    pub fn new(redis: RedisManager, ttls: CacheTtls) -> Self {
+        Cache { redis, ttls }
+    }
+
+    /// Serves the response cached under `key`, or awaits `load` and caches
+    /// its body if it is a `200 OK`.
+    pub async fn read_through<F>(&self, cache_key: &CacheKey, load: F) -> HttpResponse
+    where
+        F: Future<Output = HttpResponse>,
+    {
+        let (ttl, key) = (self.ttls.ttl(cache_key.kind), cache_key.key.as_str());
+        match get_cached_data(key, &self.redis).await {
+            Ok(Some(cached)) => {
+                return HttpResponse::Ok()
+                    .content_type(header::ContentType::json())
+                    .insert_header((CACHE_STATUS_HEADER, CACHE_HIT))
+                    .body(cached)
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read {} from the cache: {}", key, e),
+        }
+
+        let response = load.await;
+        if response.status() != StatusCode::OK {
+            return response;
+        }
+        let (mut response, response_body) = response.into_parts();
+        let bytes = match body::to_bytes(response_body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return AppError::internal(format!(
+                    "Failed to read the response for {}: {}",
+                    key, e
+                ))
+                .into();
+            }
+        };
+        match std::str::from_utf8(&bytes) {
+            Ok(data) => match set_cached_data(key, data, ttl, &self.redis).await {
+                Ok(()) => {
+                    if let Some(list) = &cache_key.list {
+                        if let Err(e) = self.track_page(list, key, ttl).await {
+                            warn!("Failed to track {} as a page of {}: {}", key, list, e);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to cache {}: {}", key, e),
+            },
+            Err(e) => warn!("Not caching {}, the response is not UTF-8: {}", key, e),
+        }
+        response.headers_mut().insert(
+            header::HeaderName::from_static("x-cache-status"),
+            HeaderValue::from_static(CACHE_MISS),
+        );
+        response.set_body(BoxBody::new(bytes))
+    }
+
+    /// Like [`Cache::read_through`], and adds `ETag` and `Last-Modified` to a
+    /// `200 OK`, answering `304 Not Modified` to conditional requests that
+    /// match them.
+    pub async fn serve<F>(&self, request: &HttpRequest, key: &CacheKey, load: F) -> HttpResponse
+    where
+        F: Future<Output = HttpResponse>,
+    {
+        let response = self.read_through(key, load).await;
+        if response.status() != StatusCode::OK {
+            return response;
+        }
+        let (response, response_body) = response.into_parts();
+        let bytes = match body::to_bytes(response_body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return AppError::internal(format!(
+                    "Failed to read the response for {}: {}",
+                    key.key, e
+                ))
+                .into();
+            }
+        };
+        let etag = conditional::etag(&bytes);
+        let last_modified = match self.last_modified(key, &etag).await {
+            Ok(last_modified) => Some(last_modified),
+            Err(e) => {
+                warn!("Failed to read the validators of {}: {}", key.key, e);
+                None
+            }
+        };
+        conditional::respond(request, response, bytes, &etag, last_modified)
+    }
+
+    /// When `etag` was first served for `key`. Kept in Redis so that every
+    /// replica reports the same `Last-Modified`.
+    async fn last_modified(&self, key: &CacheKey, etag: &str) -> RedisResult<DateTime<Utc>> {
+        let validators_key = format!("validators:{}", key.key);
+        let mut conn = self.redis.connection().await?;
+        let (known_etag, since): (Option<String>, Option<i64>) =
+            conn.hget(&validators_key, &["etag", "since"]).await?;
+        let since = match (
+            known_etag,
+            since.and_then(|since| Utc.timestamp_opt(since, 0).single()),
+        ) {
+            (Some(known_etag), Some(since)) if known_etag == etag => since,
+            _ => {
+                let now = Utc::now();
+                let _: () = conn
+                    .hset_multiple(
+                        &validators_key,
+                        &[
+                            ("etag", etag.to_string()),
+                            ("since", now.timestamp().to_string()),
+                        ],
+                    )
+                    .await?;
+                now
+            }
+        };
+        let _: () = conn
+            .expire(&validators_key, VALIDATORS_TTL.as_secs() as i64)
+            .await?;
+        Ok(since)
+    }
+
+    /// Adds `page` to the pages of `list`, which are kept as long as the
+    /// newest of them.
+    async fn track_page(&self, list: &str, page: &str, ttl: Duration) -> RedisResult<()> {
+        let pages_key = pages_key(list);
+        let mut conn = self.redis.connection().await?;
+        redis::pipe()
+            .atomic()
+            .sadd(&pages_key, page)
+            .ignore()
+            .expire(&pages_key, ttl.as_secs().max(1) as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+    }
+
+    /// Deletes `keys` from the cache, with every page cached under them.
+    pub async fn invalidate(&self, keys: &[CacheKey]) {
+        if let Err(e) = self.delete_with_pages(keys).await {
+            let keys: Vec<&str> = keys.iter().map(|key| key.key.as_str()).collect();
+            warn!("Failed to invalidate {:?}: {}", keys, e);
+        }
+    }
+
+    async fn delete_with_pages(&self, keys: &[CacheKey]) -> RedisResult<()> {
+        let mut conn = self.redis.connection().await?;
+        let pages_keys: Vec<String> = keys.iter().map(|key| pages_key(&key.key)).collect();
+        let mut pipe = redis::pipe();
+        for pages_key in &pages_keys {
+            pipe.smembers(pages_key);
+        }
+        let pages: Vec<Vec<String>> = pipe.query_async(&mut conn).await?;
+
+        let stale: Vec<String> = keys
+            .iter()
+            .map(|key| key.key.clone())
+            .chain(pages_keys)
+            .chain(pages.into_iter().flatten())
+            .collect();
+        conn.del(stale).await
+    }
+
+    /// Deletes every cached response, for when changes may have been missed.
+    pub async fn invalidate_all(&self) {
+        match delete_cached_data_matching(keys::ALL, &self.redis).await {
+            Ok(deleted) => info!("Invalidated {} cached responses", deleted),
+            Err(e) => warn!("Failed to invalidate the cache: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_remember_their_list() {
+        let page = keys::products().with_query("limit=10&sort=-price");
+        assert_eq!(page.key, "cache:products?limit=10&sort=-price");
+        assert_eq!(page.list.as_deref(), Some("cache:products"));
+        assert_eq!(keys::products().with_query(""), keys::products());
+    }
+}
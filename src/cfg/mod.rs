@@ -1,6 +1,5 @@
 use std::env;
 
-
 pub fn fetch_env_var(key: &str) -> String {
     env::var(key).unwrap_or_else(|e| {
         panic!("Failed to fetch environment variable: '{}'", e);
@@ -15,6 +14,10 @@ pub struct Config {
     pub rest_api: RestApiConfig,
     pub redis: RedisConfig,
     pub postgres: PostgresConfig,
+    #[serde(default)]
+    pub stream: StreamConfig,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -24,10 +27,37 @@ pub struct RestApiConfig {
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
 pub struct RedisConfig {
     pub redis_url: String,
     pub redis_port: String,
     pub redis_host: String,
+    pub connect_timeout: String,
+    pub response_timeout: String,
+    pub reconnect_interval: String,
+    pub cache_ttl: String,
+    pub cache_ttl_products: String,
+    pub cache_ttl_brands: String,
+    pub cache_ttl_discounts: String,
+    pub cache_ttl_categories: String,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            redis_url: "REDIS_URL".to_string(),
+            redis_port: "REDIS_PORT".to_string(),
+            redis_host: "REDIS_HOST".to_string(),
+            connect_timeout: "REDIS_CONNECT_TIMEOUT".to_string(),
+            response_timeout: "REDIS_RESPONSE_TIMEOUT".to_string(),
+            reconnect_interval: "REDIS_RECONNECT_INTERVAL".to_string(),
+            cache_ttl: "REDIS_CACHE_TTL".to_string(),
+            cache_ttl_products: "REDIS_CACHE_TTL_PRODUCTS".to_string(),
+            cache_ttl_brands: "REDIS_CACHE_TTL_BRANDS".to_string(),
+            cache_ttl_discounts: "REDIS_CACHE_TTL_DISCOUNTS".to_string(),
+            cache_ttl_categories: "REDIS_CACHE_TTL_CATEGORIES".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -39,6 +69,70 @@ pub struct PostgresConfig {
     pub db_name: String,
 }
 
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub chunk_size: String,
+    pub batch_semantics: String,
+    pub modes: String,
+    pub high_water_mark: String,
+    pub retry_after: String,
+    pub rate_limit: String,
+    pub api_keys: String,
+    pub log_max_len: String,
+    pub log_retention: String,
+    pub backend: String,
+    pub outbox_interval: String,
+    pub outbox_batch_size: String,
+    pub outbox_retention: String,
+    pub events_max_len: String,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            chunk_size: "STREAM_CHUNK_SIZE".to_string(),
+            batch_semantics: "STREAM_BATCH_SEMANTICS".to_string(),
+            modes: "STREAM_MODES".to_string(),
+            high_water_mark: "STREAM_HIGH_WATER_MARK".to_string(),
+            retry_after: "STREAM_RETRY_AFTER".to_string(),
+            rate_limit: "STREAM_RATE_LIMIT".to_string(),
+            api_keys: "STREAM_API_KEYS".to_string(),
+            log_max_len: "STREAM_LOG_MAX_LEN".to_string(),
+            log_retention: "STREAM_LOG_RETENTION".to_string(),
+            backend: "STREAM_BACKEND".to_string(),
+            outbox_interval: "STREAM_OUTBOX_INTERVAL".to_string(),
+            outbox_batch_size: "STREAM_OUTBOX_BATCH_SIZE".to_string(),
+            outbox_retention: "STREAM_OUTBOX_RETENTION".to_string(),
+            events_max_len: "STREAM_EVENTS_MAX_LEN".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub interval: String,
+    pub timeout: String,
+    pub retry_base: String,
+    pub max_attempts: String,
+    pub disable_after: String,
+    pub allow_private_hosts: String,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            interval: "WEBHOOK_INTERVAL".to_string(),
+            timeout: "WEBHOOK_TIMEOUT".to_string(),
+            retry_base: "WEBHOOK_RETRY_BASE".to_string(),
+            max_attempts: "WEBHOOK_MAX_ATTEMPTS".to_string(),
+            disable_after: "WEBHOOK_DISABLE_AFTER".to_string(),
+            allow_private_hosts: "WEBHOOK_ALLOW_PRIVATE_HOSTS".to_string(),
+        }
+    }
+}
+
 impl Config {
     pub fn from_file(file_path: &str) -> Self {
         let config_content = std::fs::read_to_string(file_path).unwrap_or_else(|err| {
@@ -64,11 +158,39 @@ pub struct Env {
     pub redis_url: String,
     pub redis_port: String,
     pub redis_host: String,
+    pub redis_connect_timeout: Option<String>,
+    pub redis_response_timeout: Option<String>,
+    pub redis_reconnect_interval: Option<String>,
+    pub redis_cache_ttl: Option<String>,
+    pub redis_cache_ttl_products: Option<String>,
+    pub redis_cache_ttl_brands: Option<String>,
+    pub redis_cache_ttl_discounts: Option<String>,
+    pub redis_cache_ttl_categories: Option<String>,
     pub db_url: String,
     pub db_secret: String,
     pub db_password: String,
     pub db_user: String,
     pub db_name: String,
+    pub stream_chunk_size: Option<String>,
+    pub stream_batch_semantics: Option<String>,
+    pub stream_modes: Option<String>,
+    pub stream_high_water_mark: Option<String>,
+    pub stream_retry_after: Option<String>,
+    pub stream_rate_limit: Option<String>,
+    pub stream_api_keys: Option<String>,
+    pub stream_log_max_len: Option<String>,
+    pub stream_log_retention: Option<String>,
+    pub stream_backend: Option<String>,
+    pub stream_outbox_interval: Option<String>,
+    pub stream_outbox_batch_size: Option<String>,
+    pub stream_outbox_retention: Option<String>,
+    pub stream_events_max_len: Option<String>,
+    pub webhook_interval: Option<String>,
+    pub webhook_timeout: Option<String>,
+    pub webhook_retry_base: Option<String>,
+    pub webhook_max_attempts: Option<String>,
+    pub webhook_disable_after: Option<String>,
+    pub webhook_allow_private_hosts: Option<String>,
 }
 
 impl Env {
@@ -79,11 +201,45 @@ impl Env {
         let redis_url = Self::fetch_env_var(&config.redis.redis_url);
         let redis_port = Self::fetch_env_var(&config.redis.redis_port);
         let redis_host = Self::fetch_env_var(&config.redis.redis_host);
+        let redis_connect_timeout = Self::fetch_optional_env_var(&config.redis.connect_timeout);
+        let redis_response_timeout = Self::fetch_optional_env_var(&config.redis.response_timeout);
+        let redis_reconnect_interval =
+            Self::fetch_optional_env_var(&config.redis.reconnect_interval);
+        let redis_cache_ttl = Self::fetch_optional_env_var(&config.redis.cache_ttl);
+        let redis_cache_ttl_products =
+            Self::fetch_optional_env_var(&config.redis.cache_ttl_products);
+        let redis_cache_ttl_brands = Self::fetch_optional_env_var(&config.redis.cache_ttl_brands);
+        let redis_cache_ttl_discounts =
+            Self::fetch_optional_env_var(&config.redis.cache_ttl_discounts);
+        let redis_cache_ttl_categories =
+            Self::fetch_optional_env_var(&config.redis.cache_ttl_categories);
         let db_url = Self::fetch_env_var(&config.postgres.db_url);
         let db_secret = Self::fetch_env_var(&config.postgres.db_secret);
         let db_password = Self::fetch_env_var(&config.postgres.db_password);
         let db_user = Self::fetch_env_var(&config.postgres.db_user);
         let db_name = Self::fetch_env_var(&config.postgres.db_name);
+        let stream_chunk_size = Self::fetch_optional_env_var(&config.stream.chunk_size);
+        let stream_batch_semantics = Self::fetch_optional_env_var(&config.stream.batch_semantics);
+        let stream_modes = Self::fetch_optional_env_var(&config.stream.modes);
+        let stream_high_water_mark = Self::fetch_optional_env_var(&config.stream.high_water_mark);
+        let stream_retry_after = Self::fetch_optional_env_var(&config.stream.retry_after);
+        let stream_rate_limit = Self::fetch_optional_env_var(&config.stream.rate_limit);
+        let stream_api_keys = Self::fetch_optional_env_var(&config.stream.api_keys);
+        let stream_log_max_len = Self::fetch_optional_env_var(&config.stream.log_max_len);
+        let stream_log_retention = Self::fetch_optional_env_var(&config.stream.log_retention);
+        let stream_backend = Self::fetch_optional_env_var(&config.stream.backend);
+        let stream_outbox_interval = Self::fetch_optional_env_var(&config.stream.outbox_interval);
+        let stream_outbox_batch_size =
+            Self::fetch_optional_env_var(&config.stream.outbox_batch_size);
+        let stream_outbox_retention = Self::fetch_optional_env_var(&config.stream.outbox_retention);
+        let stream_events_max_len = Self::fetch_optional_env_var(&config.stream.events_max_len);
+        let webhook_interval = Self::fetch_optional_env_var(&config.webhook.interval);
+        let webhook_timeout = Self::fetch_optional_env_var(&config.webhook.timeout);
+        let webhook_retry_base = Self::fetch_optional_env_var(&config.webhook.retry_base);
+        let webhook_max_attempts = Self::fetch_optional_env_var(&config.webhook.max_attempts);
+        let webhook_disable_after = Self::fetch_optional_env_var(&config.webhook.disable_after);
+        let webhook_allow_private_hosts =
+            Self::fetch_optional_env_var(&config.webhook.allow_private_hosts);
 
         Env {
             api_host,
@@ -91,11 +247,39 @@ impl Env {
             redis_url,
             redis_port,
             redis_host,
+            redis_connect_timeout,
+            redis_response_timeout,
+            redis_reconnect_interval,
+            redis_cache_ttl,
+            redis_cache_ttl_products,
+            redis_cache_ttl_brands,
+            redis_cache_ttl_discounts,
+            redis_cache_ttl_categories,
             db_url,
             db_secret,
             db_password,
             db_user,
             db_name,
+            stream_chunk_size,
+            stream_batch_semantics,
+            stream_modes,
+            stream_high_water_mark,
+            stream_retry_after,
+            stream_rate_limit,
+            stream_api_keys,
+            stream_log_max_len,
+            stream_log_retention,
+            stream_backend,
+            stream_outbox_interval,
+            stream_outbox_batch_size,
+            stream_outbox_retention,
+            stream_events_max_len,
+            webhook_interval,
+            webhook_timeout,
+            webhook_retry_base,
+            webhook_max_attempts,
+            webhook_disable_after,
+            webhook_allow_private_hosts,
         }
     }
 
@@ -104,6 +288,10 @@ impl Env {
             panic!("Failed to fetch environment variable '{}': {}", key, e);
         })
     }
+
+    fn fetch_optional_env_var(key: &str) -> Option<String> {
+        env::var(key).ok()
+    }
 }
 
 impl From<Config> for Env {
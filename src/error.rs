@@ -1,9 +1,14 @@
-use actix_web::{HttpResponse, ResponseError};
-use diesel::result::Error as DieselError;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::error;
 use r2d2::Error as R2d2Error;
 use redis::RedisError;
+use serde::{Deserialize, Serialize};
 use std::{ffi::NulError, fmt};
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::api::request_id;
 
 pub mod message {
     // Http request error messages
@@ -15,10 +20,15 @@ pub mod message {
     pub const BAD_REQUEST: &str = "Bad Request";
     pub const TIME_OUT: &str = "Connection Timed Out";
     pub const NOT_FOUND: &str = "Not Found";
+    pub const TOO_MANY_REQUESTS: &str = "Too Many Requests";
+    pub const PAYLOAD_TOO_LARGE: &str = "Payload Too Large";
+    pub const VALIDATION_ERROR: &str = "Validation Error";
 
     // Database error messages
     pub const UNIQUE_VIOLATION: &str = "Unique Violation";
     pub const FOREIGN_KEY_VIOLATION: &str = "Foreign Key Violation";
+    pub const NOT_NULL_VIOLATION: &str = "Not Null Violation";
+    pub const CHECK_VIOLATION: &str = "Check Violation";
     pub const DATABASE_ERROR: &str = "Database Error";
     pub const SERIALIZATION_ERROR: &str = "Serialization Error";
     pub const QUERY_BUILDER_ERROR: &str = "Query Builder Error";
@@ -52,6 +62,163 @@ pub mod message {
     pub const REDIS_CLUSTER_CONNECTION_NOT_FOUND: &str = INTERNAL_SERVER_ERROR;
 }
 
+pub mod problem {
+    // Values of the `type` member of problem documents
+    pub const BAD_REQUEST: &str = "/problems/bad-request";
+    pub const NOT_FOUND: &str = "/problems/not-found";
+    pub const VALIDATION: &str = "/problems/validation";
+    pub const UNIQUE_VIOLATION: &str = "/problems/unique-violation";
+    pub const FOREIGN_KEY_VIOLATION: &str = "/problems/foreign-key-violation";
+    pub const NOT_NULL_VIOLATION: &str = "/problems/not-null-violation";
+    pub const CHECK_VIOLATION: &str = "/problems/check-violation";
+    pub const RATE_LIMITED: &str = "/problems/rate-limited";
+    pub const PAYLOAD_TOO_LARGE: &str = "/problems/payload-too-large";
+    pub const DATABASE: &str = "/problems/database";
+    pub const CONNECTION_POOL: &str = "/problems/connection-pool";
+    pub const REDIS: &str = "/problems/redis";
+    pub const INTERNAL: &str = "/problems/internal";
+}
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// An RFC 9457 problem document. Every error the APIs return renders as one,
+/// with `Content-Type: application/problem+json`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AppError {
+    /// One of the `/problems/*` references in [`problem`].
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Request field the problem is about.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Database constraint that rejected the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    /// Every invalid field, for `/problems/validation`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// `X-Request-Id` of the request that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// What went wrong on the server. Logged, never sent.
+    #[serde(skip)]
+    pub cause: Option<String>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, problem_type: &str, title: &str) -> Self {
+        AppError {
+            problem_type: problem_type.to_string(),
+            title: title.to_string(),
+            status: status.as_u16(),
+            detail: None,
+            field: None,
+            constraint: None,
+            errors: Vec::new(),
+            request_id: None,
+            cause: None,
+        }
+    }
+
+    pub fn bad_request(detail: impl Into<String>) -> Self {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            problem::BAD_REQUEST,
+            message::BAD_REQUEST,
+        )
+        .with_detail(detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        AppError::new(
+            StatusCode::NOT_FOUND,
+            problem::NOT_FOUND,
+            message::NOT_FOUND,
+        )
+        .with_detail(detail)
+    }
+
+    pub fn internal(cause: impl Into<String>) -> Self {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            problem::INTERNAL,
+            message::INTERNAL_SERVER_ERROR,
+        )
+        .with_cause(cause)
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn with_constraint(mut self, constraint: impl Into<String>) -> Self {
+        self.constraint = Some(constraint.into());
+        self
+    }
+
+    pub fn with_cause(mut self, cause: impl Into<String>) -> Self {
+        self.cause = Some(cause.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.detail, &self.cause) {
+            (Some(detail), _) => write!(f, "{}: {}", self.title, detail),
+            (None, Some(cause)) => write!(f, "{}: {}", self.title, cause),
+            (None, None) => write!(f, "{}", self.title),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    /// Renders the problem with the id of the request being handled.
+    fn error_response(&self) -> HttpResponse {
+        let mut problem = self.clone();
+        problem.request_id = request_id::current();
+        if self.status().is_server_error() {
+            error!(
+                "{} [request {}]",
+                self,
+                problem.request_id.as_deref().unwrap_or("-")
+            );
+        }
+        match serde_json::to_string(&problem) {
+            Ok(body) => HttpResponse::build(self.status())
+                .content_type(PROBLEM_CONTENT_TYPE)
+                .body(body),
+            Err(_) => HttpResponse::build(self.status()).finish(),
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        self.status()
+    }
+}
+
+impl From<AppError> for HttpResponse {
+    fn from(error: AppError) -> Self {
+        error.error_response()
+    }
+}
+
 #[derive(Debug, Error)]
 pub struct EnvVarError {
     pub var_name: String,
@@ -68,9 +235,15 @@ impl fmt::Display for EnvVarError {
     }
 }
 
+impl From<&EnvVarError> for AppError {
+    fn from(error: &EnvVarError) -> Self {
+        AppError::internal(error.to_string())
+    }
+}
+
 impl ResponseError for EnvVarError {
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::InternalServerError().body(format!("EnvVar Error: {}", self))
+        AppError::from(self).error_response()
     }
 
     fn status_code(&self) -> actix_web::http::StatusCode {
@@ -88,42 +261,95 @@ impl Into<HttpResponse> for EnvVarError {
 #[error("Database Error: {0}")]
 pub struct DatabaseErrorWrapper(pub DieselError);
 
-impl ResponseError for DatabaseErrorWrapper {
-    fn error_response(&self) -> HttpResponse {
-        match &self.0 {
-            DieselError::NotFound => HttpResponse::NotFound().body(message::NOT_FOUND),
-            DieselError::DatabaseError(kind, _info) => match kind {
-                diesel::result::DatabaseErrorKind::UniqueViolation => {
-                    HttpResponse::Conflict().body(message::UNIQUE_VIOLATION)
-                }
-                diesel::result::DatabaseErrorKind::ForeignKeyViolation => {
-                    HttpResponse::BadRequest().body(message::FOREIGN_KEY_VIOLATION)
-                }
-                _ => HttpResponse::InternalServerError().body(message::INTERNAL_SERVER_ERROR),
-            },
-            DieselError::SerializationError(err) => HttpResponse::InternalServerError()
-                .body(format!("{}: {}", message::SERIALIZATION_ERROR, err)),
-            DieselError::QueryBuilderError(err) => HttpResponse::BadRequest().body(format!(
-                "{}: {}",
+/// The column named in a Postgres `Key (column)=(value) ...` detail.
+fn key_column(details: &str) -> Option<String> {
+    let columns = details.strip_prefix("Key (")?;
+    let end = columns.find(")=")?;
+    Some(columns[..end].to_string())
+}
+
+impl From<&DatabaseErrorWrapper> for AppError {
+    fn from(error: &DatabaseErrorWrapper) -> Self {
+        match &error.0 {
+            DieselError::NotFound => AppError::not_found("The requested row does not exist"),
+            DieselError::DatabaseError(kind, info) => {
+                let (status, problem_type, title) = match kind {
+                    DatabaseErrorKind::UniqueViolation => (
+                        StatusCode::CONFLICT,
+                        problem::UNIQUE_VIOLATION,
+                        message::UNIQUE_VIOLATION,
+                    ),
+                    DatabaseErrorKind::ForeignKeyViolation => (
+                        StatusCode::BAD_REQUEST,
+                        problem::FOREIGN_KEY_VIOLATION,
+                        message::FOREIGN_KEY_VIOLATION,
+                    ),
+                    DatabaseErrorKind::NotNullViolation => (
+                        StatusCode::BAD_REQUEST,
+                        problem::NOT_NULL_VIOLATION,
+                        message::NOT_NULL_VIOLATION,
+                    ),
+                    DatabaseErrorKind::CheckViolation => (
+                        StatusCode::BAD_REQUEST,
+                        problem::CHECK_VIOLATION,
+                        message::CHECK_VIOLATION,
+                    ),
+                    _ => {
+                        return AppError::new(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            problem::DATABASE,
+                            message::DATABASE_ERROR,
+                        )
+                        .with_cause(info.message())
+                    }
+                };
+                let mut problem = AppError::new(status, problem_type, title)
+                    .with_detail(info.details().unwrap_or(info.message()));
+                problem.constraint = info.constraint_name().map(str::to_string);
+                problem.field = info
+                    .column_name()
+                    .map(str::to_string)
+                    .or_else(|| info.details().and_then(key_column));
+                problem
+            }
+            DieselError::QueryBuilderError(err) => AppError::new(
+                StatusCode::BAD_REQUEST,
+                problem::BAD_REQUEST,
                 message::QUERY_BUILDER_ERROR,
-                err
-            )),
-            _ => HttpResponse::InternalServerError().body(message::INTERNAL_SERVER_ERROR),
+            )
+            .with_detail(err.to_string()),
+            DieselError::SerializationError(err) => AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                problem::DATABASE,
+                message::SERIALIZATION_ERROR,
+            )
+            .with_cause(err.to_string()),
+            err => AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                problem::DATABASE,
+                message::DATABASE_ERROR,
+            )
+            .with_cause(err.to_string()),
         }
     }
+}
 
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        match &self.0 {
-            DieselError::NotFound => actix_web::http::StatusCode::NOT_FOUND,
-            DieselError::DatabaseError(_, _) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            DieselError::SerializationError(_) => {
-                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-            }
-            DieselError::QueryBuilderError(_) => actix_web::http::StatusCode::BAD_REQUEST,
-            _ => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-        }
+impl ResponseError for DatabaseErrorWrapper {
+    fn error_response(&self) -> HttpResponse {
+        AppError::from(self).error_response()
+    }
+
+    fn status_code(&self) -> StatusCode {
+        AppError::from(self).status()
     }
 }
+
+impl From<DieselError> for DatabaseErrorWrapper {
+    fn from(error: DieselError) -> Self {
+        DatabaseErrorWrapper(error)
+    }
+}
+
 impl Into<HttpResponse> for DatabaseErrorWrapper {
     fn into(self) -> HttpResponse {
         self.error_response()
@@ -134,9 +360,20 @@ impl Into<HttpResponse> for DatabaseErrorWrapper {
 #[error("Connection Pool Error: {0}")]
 pub struct ConnectionPoolErrorWrapper(pub R2d2Error);
 
+impl From<&ConnectionPoolErrorWrapper> for AppError {
+    fn from(error: &ConnectionPoolErrorWrapper) -> Self {
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            problem::CONNECTION_POOL,
+            message::CONNECTION_POOL_ERROR,
+        )
+        .with_cause(error.0.to_string())
+    }
+}
+
 impl ResponseError for ConnectionPoolErrorWrapper {
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::InternalServerError().body(message::CONNECTION_POOL_ERROR)
+        AppError::from(self).error_response()
     }
 
     fn status_code(&self) -> actix_web::http::StatusCode {
@@ -144,84 +381,98 @@ impl ResponseError for ConnectionPoolErrorWrapper {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum StreamBackendError {
+    #[error(transparent)]
+    Redis(#[from] RedisErrorWrapper),
+    #[error("Serialization Error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl From<RedisError> for StreamBackendError {
+    fn from(error: RedisError) -> Self {
+        StreamBackendError::Redis(RedisErrorWrapper(error))
+    }
+}
+
+impl From<&StreamBackendError> for AppError {
+    fn from(error: &StreamBackendError) -> Self {
+        match error {
+            StreamBackendError::Redis(err) => AppError::from(err),
+            StreamBackendError::Serialization(err) => AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                problem::INTERNAL,
+                message::SERIALIZATION_ERROR,
+            )
+            .with_cause(err.to_string()),
+        }
+    }
+}
+
+impl ResponseError for StreamBackendError {
+    fn error_response(&self) -> HttpResponse {
+        AppError::from(self).error_response()
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            StreamBackendError::Redis(err) => err.status_code(),
+            StreamBackendError::Serialization(_) => {
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("Redis Error: {0}")]
 pub struct RedisErrorWrapper(pub RedisError);
 
-impl ResponseError for RedisErrorWrapper {
-    fn error_response(&self) -> HttpResponse {
+impl RedisErrorWrapper {
+    /// Title of the problem a Redis error of this kind is reported as.
+    fn message(&self) -> &'static str {
         match self.0.kind() {
-            redis::ErrorKind::IoError => {
-                HttpResponse::InternalServerError().body(message::REDIS_IO_ERROR)
-            }
-            redis::ErrorKind::ClientError => {
-                HttpResponse::InternalServerError().body(message::REDIS_CLIENT_ERROR)
-            }
-            redis::ErrorKind::AuthenticationFailed => {
-                HttpResponse::Unauthorized().body(message::REDIS_AUTHENTICATION_FAILED)
-            }
-            redis::ErrorKind::TypeError => {
-                HttpResponse::BadRequest().body(message::REDIS_TYPE_ERROR)
-            }
-            redis::ErrorKind::ExecAbortError => {
-                HttpResponse::InternalServerError().body(message::REDIS_EXEC_ABORT_ERROR)
-            }
-            redis::ErrorKind::BusyLoadingError => {
-                HttpResponse::ServiceUnavailable().body(message::REDIS_BUSY_LOADING_ERROR)
-            }
-            redis::ErrorKind::NoScriptError => {
-                HttpResponse::InternalServerError().body(message::REDIS_NO_SCRIPT_ERROR)
-            }
-            redis::ErrorKind::Moved => {
-                HttpResponse::InternalServerError().body(message::REDIS_MOVED_ERROR)
-            }
-            redis::ErrorKind::Ask => {
-                HttpResponse::InternalServerError().body(message::REDIS_ASK_ERROR)
-            }
-            redis::ErrorKind::TryAgain => {
-                HttpResponse::ServiceUnavailable().body(message::REDIS_TRY_AGAIN)
-            }
-            redis::ErrorKind::ClusterDown => {
-                HttpResponse::ServiceUnavailable().body(message::REDIS_CLUSTER_DOWN)
-            }
-            redis::ErrorKind::CrossSlot => {
-                HttpResponse::BadRequest().body(message::REDIS_CROSS_SLOT_ERROR)
-            }
-            redis::ErrorKind::MasterDown => {
-                HttpResponse::ServiceUnavailable().body(message::REDIS_MASTER_DOWN)
-            }
-            redis::ErrorKind::ReadOnly => {
-                HttpResponse::ServiceUnavailable().body(message::REDIS_READ_ONLY_ERROR)
-            }
-            redis::ErrorKind::ExtensionError => {
-                HttpResponse::InternalServerError().body(message::REDIS_EXTENSION_ERROR)
-            }
-            redis::ErrorKind::ResponseError => {
-                HttpResponse::InternalServerError().body(message::REDIS_RESPONSE_ERROR)
-            }
-            redis::ErrorKind::ParseError => {
-                HttpResponse::InternalServerError().body(message::REDIS_PARSE_ERROR)
-            }
-            redis::ErrorKind::InvalidClientConfig => {
-                HttpResponse::InternalServerError().body(message::REDIS_INVALID_CLIENT_CONFIG)
-            }
-            redis::ErrorKind::MasterNameNotFoundBySentinel => {
-                HttpResponse::InternalServerError().body(message::REDIS_MASTER_NAME_NOT_FOUND)
-            }
-            redis::ErrorKind::NoValidReplicasFoundBySentinel => {
-                HttpResponse::InternalServerError().body(message::REDIS_NO_VALID_REPLICAS)
-            }
-            redis::ErrorKind::EmptySentinelList => {
-                HttpResponse::InternalServerError().body(message::REDIS_EMPTY_SENTINEL_LIST)
-            }
-            redis::ErrorKind::NotBusy => {
-                HttpResponse::InternalServerError().body(message::REDIS_NOT_BUSY)
+            redis::ErrorKind::IoError => message::REDIS_IO_ERROR,
+            redis::ErrorKind::ClientError => message::REDIS_CLIENT_ERROR,
+            redis::ErrorKind::AuthenticationFailed => message::REDIS_AUTHENTICATION_FAILED,
+            redis::ErrorKind::TypeError => message::REDIS_TYPE_ERROR,
+            redis::ErrorKind::ExecAbortError => message::REDIS_EXEC_ABORT_ERROR,
+            redis::ErrorKind::BusyLoadingError => message::REDIS_BUSY_LOADING_ERROR,
+            redis::ErrorKind::NoScriptError => message::REDIS_NO_SCRIPT_ERROR,
+            redis::ErrorKind::Moved => message::REDIS_MOVED_ERROR,
+            redis::ErrorKind::Ask => message::REDIS_ASK_ERROR,
+            redis::ErrorKind::TryAgain => message::REDIS_TRY_AGAIN,
+            redis::ErrorKind::ClusterDown => message::REDIS_CLUSTER_DOWN,
+            redis::ErrorKind::CrossSlot => message::REDIS_CROSS_SLOT_ERROR,
+            redis::ErrorKind::MasterDown => message::REDIS_MASTER_DOWN,
+            redis::ErrorKind::ReadOnly => message::REDIS_READ_ONLY_ERROR,
+            redis::ErrorKind::ExtensionError => message::REDIS_EXTENSION_ERROR,
+            redis::ErrorKind::ResponseError => message::REDIS_RESPONSE_ERROR,
+            redis::ErrorKind::ParseError => message::REDIS_PARSE_ERROR,
+            redis::ErrorKind::InvalidClientConfig => message::REDIS_INVALID_CLIENT_CONFIG,
+            redis::ErrorKind::MasterNameNotFoundBySentinel => message::REDIS_MASTER_NAME_NOT_FOUND,
+            redis::ErrorKind::NoValidReplicasFoundBySentinel => message::REDIS_NO_VALID_REPLICAS,
+            redis::ErrorKind::EmptySentinelList => message::REDIS_EMPTY_SENTINEL_LIST,
+            redis::ErrorKind::NotBusy => message::REDIS_NOT_BUSY,
+            redis::ErrorKind::ClusterConnectionNotFound => {
+                message::REDIS_CLUSTER_CONNECTION_NOT_FOUND
             }
-            redis::ErrorKind::ClusterConnectionNotFound => HttpResponse::InternalServerError()
-                .body(message::REDIS_CLUSTER_CONNECTION_NOT_FOUND),
-            _ => HttpResponse::InternalServerError().body(message::INTERNAL_SERVER_ERROR),
+            _ => message::INTERNAL_SERVER_ERROR,
         }
     }
+}
+
+impl From<&RedisErrorWrapper> for AppError {
+    fn from(error: &RedisErrorWrapper) -> Self {
+        AppError::new(error.status_code(), problem::REDIS, error.message())
+            .with_cause(error.0.to_string())
+    }
+}
+
+impl ResponseError for RedisErrorWrapper {
+    fn error_response(&self) -> HttpResponse {
+        AppError::from(self).error_response()
+    }
 
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self.0.kind() {
@@ -277,3 +528,125 @@ impl From<ConnectionPoolErrorWrapper> for DatabaseErrorWrapper {
         ))
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Field-level problems found in a request payload, rendered as a `422`
+/// `/problems/validation` problem listing the offending fields.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn not_blank(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        }
+    }
+
+    pub fn at_least(&mut self, field: &str, value: i32, min: i32) {
+        if value < min {
+            self.add(field, &format!("must be at least {}", min));
+        }
+    }
+
+    pub fn at_most(&mut self, field: &str, value: i32, max: i32) {
+        if value > max {
+            self.add(field, &format!("must be at most {}", max));
+        }
+    }
+
+    pub fn exists(&mut self, field: &str, exists: bool) {
+        if !exists {
+            self.add(field, "does not exist");
+        }
+    }
+
+    pub fn required<T>(&mut self, field: &str, value: &Option<T>) {
+        if value.is_none() {
+            self.add(field, "is required");
+        }
+    }
+
+    pub fn one_of(&mut self, field: &str, value: &str, allowed: &[&str]) {
+        if !allowed.contains(&value) {
+            self.add(field, &format!("must be one of: {}", allowed.join(", ")));
+        }
+    }
+
+    pub fn after<T: PartialOrd>(&mut self, field: &str, value: &T, other_field: &str, other: &T) {
+        if value <= other {
+            self.add(field, &format!("must be after {}", other_field));
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self
+            .errors
+            .iter()
+            .map(|error| format!("{} {}", error.field, error.message))
+            .collect();
+        write!(f, "Validation Error: {}", fields.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl From<&ValidationErrors> for AppError {
+    fn from(errors: &ValidationErrors) -> Self {
+        let fields: Vec<String> = errors
+            .errors
+            .iter()
+            .map(|error| format!("{} {}", error.field, error.message))
+            .collect();
+        let mut problem = AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            problem::VALIDATION,
+            message::VALIDATION_ERROR,
+        )
+        .with_detail(fields.join(", "));
+        if let [error] = errors.errors.as_slice() {
+            problem.field = Some(error.field.clone());
+        }
+        problem.errors = errors.errors.clone();
+        problem
+    }
+}
+
+impl ResponseError for ValidationErrors {
+    fn error_response(&self) -> HttpResponse {
+        AppError::from(self).error_response()
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+impl From<ValidationErrors> for HttpResponse {
+    fn from(errors: ValidationErrors) -> Self {
+        errors.error_response()
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use log::{error, info, warn};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, Config, Notification};
+
+use crate::{
+    cache::{keys, Cache, CacheKey},
+    postgres_tls::{tls_config, MakeRustlsConnect},
+};
+
+/// Channel the `notify_cache_invalidation` trigger notifies on.
+pub const INVALIDATION_CHANNEL: &str = "cache_invalidation";
+pub const DEFAULT_RELISTEN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A committed change to a catalog table, as sent by the
+/// `notify_cache_invalidation` trigger.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogChange {
+    pub table: String,
+    /// Id of the changed row, or of the discount or product it belongs to.
+    pub id: Option<i32>,
+}
+
+impl CatalogChange {
+    /// Cached responses that can embed the changed row. Every product list,
+    /// including the lists of one brand or category, embeds brands, discounts,
+    /// stock and attributes, so any change makes them stale.
+    pub fn stale_keys(&self) -> Vec<CacheKey> {
+        let mut stale = vec![keys::products()];
+        match (self.table.as_str(), self.id) {
+            // Stock and attribute links are sent with the id of their product.
+            ("products" | "stock_quantities" | "product_attributes", Some(id)) => {
+                stale.push(keys::product(id))
+            }
+            ("brands", Some(id)) => stale.extend([keys::brands(), keys::brand(id)]),
+            ("brands", None) => stale.push(keys::brands()),
+            ("categories", Some(id)) => stale.extend([keys::categories(), keys::category(id)]),
+            ("categories", None) => stale.push(keys::categories()),
+            // Links to brands, categories and products are sent with the id of
+            // their discount.
+            (
+                "discounts" | "discount_brands" | "discount_categories" | "discount_products",
+                Some(id),
+            ) => stale.extend([keys::discounts(), keys::discount(id)]),
+            (
+                "discounts" | "discount_brands" | "discount_categories" | "discount_products",
+                None,
+            ) => stale.push(keys::discounts()),
+            // An attribute can belong to any product, and is only embedded in
+            // the product lists.
+            _ => {}
+        }
+        stale
+    }
+}
+
+fn stale_keys(notification: &Notification) -> Vec<CacheKey> {
+    match serde_json::from_str::<CatalogChange>(notification.payload()) {
+        Ok(change) => change.stale_keys(),
+        Err(e) => {
+            warn!(
+                "Ignoring invalid cache invalidation {:?}: {}",
+                notification.payload(),
+                e
+            );
+            vec![]
+        }
+    }
+}
+
+/// Listens on [`INVALIDATION_CHANNEL`] and evicts the cached responses each
+/// change makes stale, whichever process made it. Notifications that arrive
+/// together are evicted in one round trip.
+async fn listen(
+    config: &Config,
+    tls: MakeRustlsConnect,
+    cache: &Cache,
+) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = config.connect(tls).await?;
+    let (sender, mut notifications) = mpsc::unbounded_channel();
+    let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+    let connection = tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            if let AsyncMessage::Notification(notification) = message? {
+                if sender.send(notification).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok::<(), tokio_postgres::Error>(())
+    });
+
+    client
+        .batch_execute(&format!("LISTEN {}", INVALIDATION_CHANNEL))
+        .await?;
+    info!("Listening for cache invalidations");
+    // Changes made while nobody was listening were never notified.
+    cache.invalidate_all().await;
+
+    while let Some(notification) = notifications.recv().await {
+        let mut stale = stale_keys(&notification);
+        while let Ok(notification) = notifications.try_recv() {
+            for key in stale_keys(&notification) {
+                if !stale.contains(&key) {
+                    stale.push(key);
+                }
+            }
+        }
+        cache.invalidate(&stale).await;
+    }
+
+    drop(client);
+    match connection.await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Cache invalidation listener did not complete: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Runs [`listen`] until the process exits, listening again
+/// `DEFAULT_RELISTEN_INTERVAL` after the connection is lost. Connects with TLS
+/// as `sslmode` in `database_url` asks; a URL it can't use is logged and the
+/// listener doesn't start.
+pub async fn run_cache_invalidation_listener(database_url: String, cache: Cache) {
+    let (config, tls) = match tls_config(&database_url) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Cache invalidation listener not started: {}", e);
+            return;
+        }
+    };
+    loop {
+        match listen(&config, tls.clone(), &cache).await {
+            Ok(()) => warn!("Cache invalidation listener connection closed"),
+            Err(e) => match e.as_db_error() {
+                Some(db_error) => error!("Cache invalidation listener failed: {}", db_error),
+                None => error!("Cache invalidation listener failed: {}", e),
+            },
+        }
+        tokio::time::sleep(DEFAULT_RELISTEN_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(table: &str, id: i32) -> CatalogChange {
+        CatalogChange {
+            table: table.to_string(),
+            id: Some(id),
+        }
+    }
+
+    #[test]
+    fn stock_and_attribute_links_invalidate_their_product() {
+        for table in ["stock_quantities", "product_attributes"] {
+            assert_eq!(
+                change(table, 7).stale_keys(),
+                vec![keys::products(), keys::product(7)]
+            );
+        }
+    }
+
+    #[test]
+    fn discount_links_invalidate_their_discount() {
+        for table in [
+            "discount_brands",
+            "discount_categories",
+            "discount_products",
+        ] {
+            assert_eq!(
+                change(table, 3).stale_keys(),
+                vec![keys::products(), keys::discounts(), keys::discount(3)]
+            );
+        }
+    }
+
+    #[test]
+    fn attributes_invalidate_the_product_lists() {
+        assert_eq!(change("attributes", 5).stale_keys(), vec![keys::products()]);
+    }
+}
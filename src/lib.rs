@@ -1,18 +1,24 @@
 pub mod api;
+pub mod cache;
 pub mod cfg;
 pub mod docker;
 pub mod error;
 pub mod http;
+pub mod invalidation;
 pub mod logger;
+pub mod outbox;
 pub mod postgres;
+pub mod postgres_tls;
 pub mod redis;
 pub mod schema;
 pub mod services;
 pub mod stream;
 
 use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 pub struct ResourceIdentifierRequest {
     pub id: i32,
 }
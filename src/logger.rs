@@ -10,5 +10,5 @@ pub const CONFIG_FILE_PATH: &str = "config.json";
 pub mod logger {
     pub const DEFAULT_FORMAT: &str = "%a \"%r\" [%s] [%T] [%b] \"%{Referer}i\" \"%{User-Agent}i\" ";
     pub const DETAILED_FORMAT: &str =
-        "%a \"%r\" [%s] [%D ms] [%b] \"%{Referer}i\" \"%{User-Agent}i\" ";
+        "%a \"%r\" [%s] [%D ms] [%b] \"%{Referer}i\" \"%{User-Agent}i\" [%{x-request-id}i] ";
 }
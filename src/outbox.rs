@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, result::Error as DieselError, PgConnection};
+use log::{error, info};
+use redis::RedisError;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cfg::Env,
+    error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper, RedisErrorWrapper},
+    postgres::ConnectionPool,
+    redis::RedisManager,
+    schema::outbox_events,
+    services::webhook::dispatch::enqueue_event_deliveries,
+};
+
+/// Redis stream that domain events are published to.
+pub const DOMAIN_EVENTS_KEY: &str = "domain_events";
+pub const DEFAULT_PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_PUBLISH_BATCH_SIZE: i64 = 500;
+pub const DEFAULT_EVENTS_MAX_LEN: usize = 1_000_000;
+pub const DEFAULT_OUTBOX_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A change recorded by the outbox triggers, e.g. `ProductUpdated`,
+/// `PriceChanged`, `StockChanged` or `CartCheckedOut`. `payload` holds the
+/// row before and after the change as `old` and `new`.
+#[derive(Debug, Clone, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = outbox_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub payload: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    pub published_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxConfig {
+    /// Wait between checks for unpublished events.
+    pub interval: Duration,
+    /// Events published per transaction.
+    pub batch_size: i64,
+    /// Approximate length the domain event stream is trimmed to.
+    pub max_len: usize,
+    /// How long published events are kept in the outbox table.
+    pub retention: Duration,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        OutboxConfig {
+            interval: DEFAULT_PUBLISH_INTERVAL,
+            batch_size: DEFAULT_PUBLISH_BATCH_SIZE,
+            max_len: DEFAULT_EVENTS_MAX_LEN,
+            retention: DEFAULT_OUTBOX_RETENTION,
+        }
+    }
+}
+
+impl OutboxConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let default = OutboxConfig::default();
+        OutboxConfig {
+            interval: env
+                .stream_outbox_interval
+                .as_deref()
+                .and_then(|interval| interval.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.interval),
+            batch_size: env
+                .stream_outbox_batch_size
+                .as_deref()
+                .and_then(|batch_size| batch_size.parse().ok())
+                .filter(|batch_size| *batch_size > 0)
+                .unwrap_or(default.batch_size),
+            max_len: env
+                .stream_events_max_len
+                .as_deref()
+                .and_then(|max_len| max_len.parse().ok())
+                .unwrap_or(default.max_len),
+            retention: env
+                .stream_outbox_retention
+                .as_deref()
+                .and_then(|retention| retention.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.retention),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OutboxError {
+    #[error(transparent)]
+    Database(#[from] DatabaseErrorWrapper),
+    #[error(transparent)]
+    ConnectionPool(#[from] ConnectionPoolErrorWrapper),
+    #[error(transparent)]
+    Redis(#[from] RedisErrorWrapper),
+}
+
+impl From<DieselError> for OutboxError {
+    fn from(error: DieselError) -> Self {
+        OutboxError::Database(DatabaseErrorWrapper(error))
+    }
+}
+
+impl From<RedisError> for OutboxError {
+    fn from(error: RedisError) -> Self {
+        OutboxError::Redis(RedisErrorWrapper(error))
+    }
+}
+
+/// Publishes up to `config.batch_size` of the oldest unpublished events and
+/// marks them published, returning how many there were. Rows are locked with
+/// `SKIP LOCKED`, so several publishers can share one outbox. An event is
+/// published again if the transaction fails after it was sent, so consumers
+/// should skip event ids they have already seen. Webhook deliveries for the
+/// events are queued in the same transaction. Runs on a blocking thread of the
+/// Tokio runtime, which it uses to send the events through `redis`.
+pub fn publish_pending(
+    con: &mut PgConnection,
+    redis: &RedisManager,
+    config: &OutboxConfig,
+) -> Result<usize, OutboxError> {
+    con.transaction(|con| {
+        let events: Vec<OutboxEvent> = outbox_events::table
+            .filter(outbox_events::published_at.is_null())
+            .order(outbox_events::id)
+            .limit(config.batch_size)
+            .for_update()
+            .skip_locked()
+            .select(OutboxEvent::as_select())
+            .load(con)?;
+        if events.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        for event in &events {
+            pipe.cmd("XADD")
+                .arg(DOMAIN_EVENTS_KEY)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(config.max_len)
+                .arg("*")
+                .arg("id")
+                .arg(event.id)
+                .arg("type")
+                .arg(&event.event_type)
+                .arg("aggregate_type")
+                .arg(&event.aggregate_type)
+                .arg("aggregate_id")
+                .arg(&event.aggregate_id)
+                .arg("occurred_at")
+                .arg(event.created_at.and_utc().to_rfc3339())
+                .arg("payload")
+                .arg(event.payload.to_string())
+                .ignore();
+        }
+        enqueue_event_deliveries(con, &events)?;
+        tokio::runtime::Handle::current().block_on(async {
+            let mut redis = redis.connection().await?;
+            pipe.query_async::<_, ()>(&mut redis).await
+        })?;
+
+        let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+        diesel::update(outbox_events::table.filter(outbox_events::id.eq_any(&ids)))
+            .set(outbox_events::published_at.eq(diesel::dsl::now))
+            .execute(con)?;
+        Ok(events.len())
+    })
+}
+
+/// Deletes events published longer ago than `config.retention`.
+pub fn delete_published(
+    con: &mut PgConnection,
+    config: &OutboxConfig,
+) -> Result<usize, OutboxError> {
+    let cutoff = chrono::Duration::from_std(config.retention)
+        .ok()
+        .and_then(|retention| Utc::now().naive_utc().checked_sub_signed(retention))
+        .unwrap_or(NaiveDateTime::MIN);
+    Ok(
+        diesel::delete(outbox_events::table.filter(outbox_events::published_at.lt(cutoff)))
+            .execute(con)?,
+    )
+}
+
+fn publish_all_pending(
+    pool: &ConnectionPool,
+    redis: &RedisManager,
+    config: &OutboxConfig,
+) -> Result<usize, OutboxError> {
+    let mut con = pool.get().map_err(ConnectionPoolErrorWrapper)?;
+    let mut published = 0;
+    loop {
+        let count = publish_pending(&mut con, redis, config)?;
+        published += count;
+        if (count as i64) < config.batch_size {
+            break;
+        }
+    }
+    delete_published(&mut con, config)?;
+    Ok(published)
+}
+
+/// Publishes outbox events to [`DOMAIN_EVENTS_KEY`] every `config.interval`
+/// until the process exits, over the shared Redis connection of `redis`.
+pub async fn run_outbox_relay(pool: ConnectionPool, redis: RedisManager, config: OutboxConfig) {
+    loop {
+        let (pool, redis, task_config) = (pool.clone(), redis.clone(), config.clone());
+        let result =
+            tokio::task::spawn_blocking(move || publish_all_pending(&pool, &redis, &task_config))
+                .await;
+        match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(published)) => info!("Published {} domain events", published),
+            Ok(Err(e)) => error!("Failed to publish domain events: {}", e),
+            Err(e) => error!("Outbox relay did not complete: {:?}", e),
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
@@ -0,0 +1,455 @@
+//! TLS for `tokio_postgres` connections. diesel connects through libpq, which
+//! reads `sslmode` itself; `tokio_postgres` needs a connector, and only
+//! understands `disable`, `prefer` and `require`.
+
+use std::{
+    env, fmt, io,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::{
+    config::SslMode,
+    tls::{ChannelBinding, MakeTlsConnect, TlsConnect, TlsStream},
+    Config,
+};
+use tokio_rustls::{
+    client,
+    rustls::{
+        client::{
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+            WebPkiServerVerifier,
+        },
+        crypto::{self, CryptoProvider},
+        pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+        CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    },
+    TlsConnector,
+};
+
+#[derive(Debug, Error)]
+pub enum TlsConfigError {
+    #[error("invalid database URL: {0}")]
+    Url(#[from] tokio_postgres::Error),
+    #[error("unsupported sslmode {0:?}")]
+    SslMode(String),
+    #[error("could not read sslrootcert {path:?}: {message}")]
+    RootCert { path: PathBuf, message: String },
+    #[error("could not set up TLS: {0}")]
+    Tls(#[from] tokio_rustls::rustls::Error),
+}
+
+/// How the server certificate is checked, after libpq's `sslmode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verify {
+    /// `prefer` and `require` encrypt without checking the certificate.
+    Nothing,
+    /// `verify-ca` checks that a trusted root signed the certificate.
+    Ca,
+    /// `verify-full` also checks that it was issued for the host.
+    Full,
+}
+
+/// Roots the server certificate is checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RootCerts {
+    /// `sslrootcert=system`: the public web roots.
+    System,
+    /// A PEM file, `~/.postgresql/root.crt` unless `sslrootcert` names another.
+    File(PathBuf),
+}
+
+impl RootCerts {
+    fn new(sslrootcert: Option<String>) -> Self {
+        match sslrootcert.as_deref() {
+            Some("system") => RootCerts::System,
+            Some(path) => RootCerts::File(PathBuf::from(path)),
+            None => RootCerts::File(
+                PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".postgresql/root.crt"),
+            ),
+        }
+    }
+
+    fn store(&self) -> Result<RootCertStore, TlsConfigError> {
+        let path = match self {
+            RootCerts::System => {
+                return Ok(RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                })
+            }
+            RootCerts::File(path) => path,
+        };
+
+        let root_cert_error = |message: String| TlsConfigError::RootCert {
+            path: path.clone(),
+            message,
+        };
+        let mut roots = RootCertStore::empty();
+        for cert in
+            CertificateDer::pem_file_iter(path).map_err(|e| root_cert_error(e.to_string()))?
+        {
+            let cert = cert.map_err(|e| root_cert_error(e.to_string()))?;
+            roots
+                .add(cert)
+                .map_err(|e| root_cert_error(e.to_string()))?;
+        }
+        if roots.is_empty() {
+            return Err(root_cert_error("no certificates found".to_string()));
+        }
+        Ok(roots)
+    }
+}
+
+/// Reads `database_url` the way libpq does: `sslmode` may also be
+/// `verify-ca` or `verify-full`, and `sslrootcert` names a PEM file of roots
+/// to trust. Like libpq, `require` checks the certificate authority when a
+/// root certificate file exists.
+pub fn tls_config(database_url: &str) -> Result<(Config, MakeRustlsConnect), TlsConfigError> {
+    let mut ssl_mode = None;
+    let mut root_cert = None;
+    let config_url = match url::Url::parse(database_url) {
+        Ok(mut url) if matches!(url.scheme(), "postgres" | "postgresql") => {
+            let mut params = Vec::new();
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "sslmode" => ssl_mode = Some(value.into_owned()),
+                    "sslrootcert" => root_cert = Some(value.into_owned()),
+                    _ => params.push((key.into_owned(), value.into_owned())),
+                }
+            }
+            url.set_query(None);
+            if !params.is_empty() {
+                url.query_pairs_mut().extend_pairs(params);
+            }
+            url.to_string()
+        }
+        // Key-value connection strings are passed on as they are.
+        _ => database_url.to_string(),
+    };
+
+    let mut config = config_url.parse::<Config>()?;
+    let root_certs = RootCerts::new(root_cert);
+    let verify = match ssl_mode.as_deref() {
+        None => Verify::Nothing,
+        Some("disable") => {
+            config.ssl_mode(SslMode::Disable);
+            Verify::Nothing
+        }
+        Some("prefer") => {
+            config.ssl_mode(SslMode::Prefer);
+            Verify::Nothing
+        }
+        Some("require") => {
+            config.ssl_mode(SslMode::Require);
+            match &root_certs {
+                RootCerts::File(path) if !path.exists() => Verify::Nothing,
+                _ => Verify::Ca,
+            }
+        }
+        Some("verify-ca") => {
+            config.ssl_mode(SslMode::Require);
+            Verify::Ca
+        }
+        Some("verify-full") => {
+            config.ssl_mode(SslMode::Require);
+            Verify::Full
+        }
+        Some(mode) => return Err(TlsConfigError::SslMode(mode.to_string())),
+    };
+
+    let connector = MakeRustlsConnect::new(verify, &root_certs)?;
+    Ok((config, connector))
+}
+
+/// Makes `tokio_postgres` connections over rustls.
+#[derive(Clone)]
+pub struct MakeRustlsConnect {
+    config: Arc<ClientConfig>,
+}
+
+impl MakeRustlsConnect {
+    fn new(verify: Verify, root_certs: &RootCerts) -> Result<Self, TlsConfigError> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let config = match verify {
+            Verify::Nothing => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider))),
+            Verify::Ca | Verify::Full => {
+                let roots = Arc::new(root_certs.store()?);
+                let verifier = WebPkiServerVerifier::builder_with_provider(roots, provider)
+                    .build()
+                    .map_err(|e| tokio_rustls::rustls::Error::General(e.to_string()))?;
+                if verify == Verify::Ca {
+                    builder
+                        .dangerous()
+                        .with_custom_certificate_verifier(Arc::new(IgnoreHostName(verifier)))
+                } else {
+                    builder.with_webpki_verifier(verifier)
+                }
+            }
+        }
+        .with_no_client_auth();
+
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl<S> MakeTlsConnect<S> for MakeRustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type TlsConnect = RustlsConnect;
+    type Error = io::Error;
+
+    fn make_tls_connect(&mut self, domain: &str) -> Result<RustlsConnect, io::Error> {
+        // Unix sockets have no host name. tokio_postgres never starts TLS
+        // without one, but still asks for a connector.
+        let server_name = ServerName::try_from(domain.to_string()).ok();
+        Ok(RustlsConnect {
+            config: self.config.clone(),
+            server_name,
+        })
+    }
+}
+
+pub struct RustlsConnect {
+    config: Arc<ClientConfig>,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl<S> TlsConnect<S> for RustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<RustlsStream<S>>>;
+
+    fn connect(self, stream: S) -> Self::Future {
+        Box::pin(async move {
+            let server_name = self.server_name.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "invalid TLS server name")
+            })?;
+            let stream = TlsConnector::from(self.config)
+                .connect(server_name, stream)
+                .await?;
+            Ok(RustlsStream(stream))
+        })
+    }
+}
+
+pub struct RustlsStream<S>(client::TlsStream<S>);
+
+impl<S> TlsStream for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn channel_binding(&self) -> ChannelBinding {
+        ChannelBinding::none()
+    }
+}
+
+impl<S> AsyncRead for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// `sslmode=prefer` and `require`: the connection is encrypted, but the
+/// certificate is not checked. Handshake signatures still are.
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl fmt::Debug for AcceptAnyCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AcceptAnyCertificate")
+    }
+}
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// `sslmode=verify-ca`: the certificate must chain to a trusted root, but may
+/// be issued for any host.
+#[derive(Debug)]
+struct IgnoreHostName(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for IgnoreHostName {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        // The chain is checked before the host name, so a wrong name means the
+        // chain is trusted.
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(tokio_rustls::rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssl_mode(database_url: &str) -> SslMode {
+        tls_config(database_url).unwrap().0.get_ssl_mode()
+    }
+
+    #[test]
+    fn reads_every_libpq_sslmode() {
+        let url = "postgres://postgres@db.example.com/ecom";
+        assert_eq!(ssl_mode(url), SslMode::Prefer);
+        assert_eq!(
+            ssl_mode(&format!("{}?sslmode=disable", url)),
+            SslMode::Disable
+        );
+        assert_eq!(
+            ssl_mode(&format!("{}?sslmode=require", url)),
+            SslMode::Require
+        );
+        assert_eq!(
+            ssl_mode(&format!("{}?sslmode=verify-ca&sslrootcert=system", url)),
+            SslMode::Require
+        );
+        assert_eq!(
+            ssl_mode(&format!("{}?sslmode=verify-full&sslrootcert=system", url)),
+            SslMode::Require
+        );
+        assert!(matches!(
+            tls_config(&format!("{}?sslmode=sometimes", url)),
+            Err(TlsConfigError::SslMode(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_the_other_parameters() {
+        let (config, _) =
+            tls_config("postgres://postgres@localhost:5433/ecom?sslmode=verify-full&sslrootcert=system&host=/tmp")
+                .unwrap();
+        assert_eq!(config.get_ports(), [5433]);
+        assert!(config.get_hosts().iter().any(|host| matches!(
+            host,
+            tokio_postgres::config::Host::Unix(path) if path.to_str() == Some("/tmp")
+        )));
+    }
+
+    #[test]
+    fn rejects_a_missing_root_certificate() {
+        assert!(matches!(
+            tls_config("postgres://postgres@db.example.com/ecom?sslmode=verify-full&sslrootcert=/nonexistent.crt"),
+            Err(TlsConfigError::RootCert { .. })
+        ));
+    }
+}
@@ -1,29 +1,335 @@
-use redis::AsyncCommands;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-pub async fn multiplexed_async_connection(
-    redis_url: String,
-) -> redis::RedisResult<redis::aio::MultiplexedConnection> {
-    let client = redis::Client::open(redis_url)?;
-    client.get_multiplexed_async_connection().await
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use redis::{
+    aio::{ConnectionLike, MultiplexedConnection},
+    AsyncCommands, Cmd, ErrorKind, Pipeline, RedisError, RedisFuture, RedisResult, Value,
+};
+use serde::Serialize;
+use tokio::sync::Mutex as AsyncMutex;
+use utoipa::ToSchema;
+
+use crate::cfg::Env;
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedisOptions {
+    /// Time allowed for opening the connection.
+    pub connect_timeout: Duration,
+    /// Time allowed for the reply to each command.
+    pub response_timeout: Duration,
+    /// Minimum wait between attempts to reconnect. Calls made in between fail
+    /// immediately instead of waiting for another connect timeout.
+    pub reconnect_interval: Duration,
+}
+
+impl Default for RedisOptions {
+    fn default() -> Self {
+        RedisOptions {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            reconnect_interval: DEFAULT_RECONNECT_INTERVAL,
+        }
+    }
+}
+
+impl RedisOptions {
+    pub fn from_env(env: &Env) -> Self {
+        let default = RedisOptions::default();
+        RedisOptions {
+            connect_timeout: env
+                .redis_connect_timeout
+                .as_deref()
+                .and_then(|timeout| timeout.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.connect_timeout),
+            response_timeout: env
+                .redis_response_timeout
+                .as_deref()
+                .and_then(|timeout| timeout.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.response_timeout),
+            reconnect_interval: env
+                .redis_reconnect_interval
+                .as_deref()
+                .and_then(|interval| interval.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.reconnect_interval),
+        }
+    }
+}
+
+/// State of the managed connection, as reported by `GET /health`.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RedisHealth {
+    pub connected: bool,
+    /// Round trip of a `PING`, measured when the health is requested.
+    pub latency_ms: Option<f64>,
+    /// Connections opened since the process started, reconnects included.
+    pub connects: u64,
+    /// Failed connection attempts and connections dropped after an error.
+    pub failures: u64,
+    pub connected_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct Slot {
+    connection: Option<MultiplexedConnection>,
+    /// Bumped on every connect, so errors on a replaced connection don't drop
+    /// its successor.
+    generation: u64,
+    last_attempt: Option<Instant>,
+}
+
+struct Shared {
+    client: redis::Client,
+    options: RedisOptions,
+    slot: AsyncMutex<Slot>,
+    health: Mutex<RedisHealth>,
+}
+
+/// One multiplexed Redis connection shared by every caller in the process.
+/// It is opened on first use and reopened after it fails, at most once per
+/// `reconnect_interval`. Cloning is cheap and shares the connection.
+#[derive(Clone)]
+pub struct RedisManager {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for RedisManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisManager")
+            .field("options", &self.shared.options)
+            .finish()
+    }
+}
+
+impl RedisManager {
+    /// Only checks `redis_url`; nothing is connected until first use.
+    pub fn new(redis_url: &str, options: RedisOptions) -> RedisResult<Self> {
+        Ok(RedisManager {
+            shared: Arc::new(Shared {
+                client: redis::Client::open(redis_url)?,
+                options,
+                slot: AsyncMutex::new(Slot::default()),
+                health: Mutex::new(RedisHealth::default()),
+            }),
+        })
+    }
+
+    pub fn options(&self) -> &RedisOptions {
+        &self.shared.options
+    }
+
+    /// A handle on the shared connection, connecting first if there is none.
+    pub async fn connection(&self) -> RedisResult<ManagedConnection> {
+        let mut slot = self.shared.slot.lock().await;
+        if let Some(connection) = &slot.connection {
+            return Ok(ManagedConnection {
+                inner: connection.clone(),
+                generation: slot.generation,
+                manager: self.clone(),
+            });
+        }
+
+        let options = &self.shared.options;
+        if let Some(last_attempt) = slot.last_attempt {
+            if last_attempt.elapsed() < options.reconnect_interval {
+                let last_error = self.lock_health().last_error.clone();
+                return Err(RedisError::from((
+                    ErrorKind::IoError,
+                    "Redis is unavailable",
+                    last_error.unwrap_or_default(),
+                )));
+            }
+        }
+
+        slot.last_attempt = Some(Instant::now());
+        match self
+            .shared
+            .client
+            .get_multiplexed_async_connection_with_timeouts(
+                options.response_timeout,
+                options.connect_timeout,
+            )
+            .await
+        {
+            Ok(connection) => {
+                slot.generation += 1;
+                slot.connection = Some(connection.clone());
+                let mut health = self.lock_health();
+                health.connected = true;
+                health.connects += 1;
+                health.connected_at = Some(Utc::now());
+                info!("Connected to Redis");
+                Ok(ManagedConnection {
+                    inner: connection,
+                    generation: slot.generation,
+                    manager: self.clone(),
+                })
+            }
+            Err(e) => {
+                self.record_failure(&e);
+                warn!("Failed to connect to Redis: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Pings Redis and reports the state of the connection.
+    pub async fn health(&self) -> RedisHealth {
+        let started = Instant::now();
+        let ping = async {
+            let mut con = self.connection().await?;
+            redis::cmd("PING").query_async::<_, String>(&mut con).await
+        }
+        .await;
+        let mut health = self.lock_health().clone();
+        if ping.is_ok() {
+            health.latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+        }
+        health
+    }
+
+    async fn drop_connection(&self, generation: u64, error: &RedisError) {
+        let mut slot = self.shared.slot.lock().await;
+        if slot.generation == generation && slot.connection.take().is_some() {
+            warn!("Dropped the Redis connection after an error: {}", error);
+            self.record_failure(error);
+        }
+    }
+
+    fn record_failure(&self, error: &RedisError) {
+        let mut health = self.lock_health();
+        health.connected = false;
+        health.failures += 1;
+        health.last_error = Some(error.to_string());
+        health.last_error_at = Some(Utc::now());
+    }
+
+    fn lock_health(&self) -> std::sync::MutexGuard<'_, RedisHealth> {
+        self.shared
+            .health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 }
-pub async fn get_cached_data(key: &str, redis_url: &str) -> redis::RedisResult<Option<String>> {
-    let mut conn = multiplexed_async_connection(redis_url.to_string()).await?;
+
+/// Errors after which the connection is not reused.
+fn is_connection_error(error: &RedisError) -> bool {
+    error.is_io_error()
+        || error.is_connection_dropped()
+        || error.is_connection_refusal()
+        || error.is_timeout()
+}
+
+/// The shared connection as handed out by [`RedisManager::connection`].
+/// Reports connection errors back to the manager, so the next call
+/// reconnects.
+#[derive(Clone)]
+pub struct ManagedConnection {
+    inner: MultiplexedConnection,
+    generation: u64,
+    manager: RedisManager,
+}
+
+impl ManagedConnection {
+    async fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result {
+            if is_connection_error(e) {
+                self.manager.drop_connection(self.generation, e).await;
+            }
+        }
+        result
+    }
+}
+
+impl ConnectionLike for ManagedConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = self.inner.req_packed_command(cmd).await;
+            self.check(result).await
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = self.inner.req_packed_commands(cmd, offset, count).await;
+            self.check(result).await
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.inner.get_db()
+    }
+}
+
+pub async fn get_cached_data(key: &str, redis: &RedisManager) -> RedisResult<Option<String>> {
+    let mut conn = redis.connection().await?;
     let cached_data: Option<String> = conn.get(key).await?;
     Ok(cached_data)
 }
 
-pub async fn delete_cached_data(key: &str, redis_url: &str) -> redis::RedisResult<Option<String>> {
-    let mut conn = multiplexed_async_connection(redis_url.to_string()).await?;
-    let cached_data: Option<String> = conn.del(key).await?;
-    Ok(cached_data)
+pub async fn delete_cached_data(keys: &[&str], redis: &RedisManager) -> RedisResult<usize> {
+    let mut conn = redis.connection().await?;
+    conn.del(keys).await
 }
 
-pub async fn set_cached_data(key: &str, data: &str, redis_url: &str) -> redis::RedisResult<()> {
-    let mut conn = multiplexed_async_connection(redis_url.to_string()).await?;
-    let _: () = conn.set_ex(key, data, 60).await?;
+/// Deletes every key matching `pattern`, found with `SCAN` so Redis isn't
+/// blocked on large keyspaces.
+pub async fn delete_cached_data_matching(
+    pattern: &str,
+    redis: &RedisManager,
+) -> RedisResult<usize> {
+    let mut conn = redis.connection().await?;
+    let keys: Vec<String> = {
+        let mut iter = conn.scan_match::<_, String>(pattern).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        keys
+    };
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    conn.del(keys).await
+}
+
+pub async fn set_cached_data(
+    key: &str,
+    data: &str,
+    ttl: Duration,
+    redis: &RedisManager,
+) -> RedisResult<()> {
+    let mut conn = redis.connection().await?;
+    let _: () = conn.set_ex(key, data, ttl.as_secs().max(1)).await?;
     Ok(())
 }
-pub fn get_connection(connection_addr: &str) -> redis::RedisResult<redis::Connection> {
+
+/// A blocking connection with the timeouts of `options`, for code that runs
+/// on blocking threads.
+pub fn get_connection(
+    connection_addr: &str,
+    options: &RedisOptions,
+) -> redis::RedisResult<redis::Connection> {
     let client = redis::Client::open(connection_addr)?;
-    client.get_connection()
+    let connection = client.get_connection_with_timeout(options.connect_timeout)?;
+    connection.set_read_timeout(Some(options.response_timeout))?;
+    connection.set_write_timeout(Some(options.response_timeout))?;
+    Ok(connection)
 }
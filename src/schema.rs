@@ -64,6 +64,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    outbox_events (id) {
+        id -> Int8,
+        event_type -> Varchar,
+        aggregate_type -> Varchar,
+        aggregate_id -> Varchar,
+        payload -> Jsonb,
+        created_at -> Timestamp,
+        published_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     products (id) {
         id -> Int4,
@@ -73,6 +85,15 @@ diesel::table! {
         brand_id -> Nullable<Int4>,
         price -> Int4,
         tax_rate -> Int4,
+        sku -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    applied_events (producer_id, event_id) {
+        producer_id -> Varchar,
+        event_id -> Varchar,
+        applied_at -> Timestamp,
     }
 }
 
@@ -106,6 +127,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int8,
+        subscription_id -> Int4,
+        event_id -> Nullable<Int8>,
+        event_type -> Varchar,
+        body -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_status_code -> Nullable<Int4>,
+        last_error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    webhook_subscriptions (id) {
+        id -> Int4,
+        url -> Varchar,
+        event_types -> Array<Varchar>,
+        secret -> Varchar,
+        active -> Bool,
+        consecutive_failures -> Int4,
+        created_at -> Timestamp,
+        disabled_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::joinable!(discount_brands -> brands (brand_id));
 diesel::joinable!(discount_brands -> discounts (discount_id));
 diesel::joinable!(discount_categories -> categories (category_id));
@@ -121,8 +172,10 @@ diesel::joinable!(product_attributes -> products (product_id));
 diesel::joinable!(product_attributes -> attributes (attribute_id));
 diesel::joinable!(stock_quantities -> products (product_id));
 diesel::joinable!(stock_quantities -> warehouses (warehouse_id));
+diesel::joinable!(webhook_deliveries -> webhook_subscriptions (subscription_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    applied_events,
     attributes,
     brands,
     carts,
@@ -132,8 +185,11 @@ diesel::allow_tables_to_appear_in_same_query!(
     discount_categories,
     discount_products,
     order_lines,
+    outbox_events,
     products,
     product_attributes,
     stock_quantities,
     warehouses,
+    webhook_deliveries,
+    webhook_subscriptions,
 );
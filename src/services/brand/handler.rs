@@ -1,7 +1,15 @@
-use actix_web::{web, HttpResponse, Responder};
-use diesel::PgConnection;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use diesel::{Connection, PgConnection};
+use serde_json::{Map, Value};
 
 use crate::{
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        pagination::PageParams,
+        validation::Valid,
+    },
+    cache::{keys, Cache},
     error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
     services::{
         brand::query::insert_discount_brand_query, discount::model::relations::DiscountBrand,
@@ -10,13 +18,24 @@ use crate::{
 };
 
 use super::{
-    model::{Brand, NewBrand},
+    model::{Brand, BrandFilter, NewBrand, BRAND_SORT_FIELDS},
     query::{
         delete_brand_query, insert_brand_query, load_brand_products_query, load_brand_query,
-        select_brand_query, set_brand_query,
+        save_brand_query, select_brand_for_update_query, select_brand_query, set_brand_query,
     },
 };
 
+/// Lists the products of a brand, each paired with the brand.
+#[utoipa::path(
+    get,
+    path = "/brand/{id}/products",
+    tag = "brand",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "Products of the brand", body = [(Product, Brand)]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_brand_products(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
     path: web::Path<ResourceIdentifierRequest>,
@@ -31,44 +50,101 @@ pub async fn list_brand_products(
     }
 }
 
+/// Deletes a brand. Its products keep no brand.
+#[utoipa::path(
+    delete,
+    path = "/brand/{id}",
+    tag = "brand",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The brand no longer exists"),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_brand(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     path: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
     let params = path.into_inner();
     match pool.get() {
         Ok(mut conn) => match delete_brand_query(&mut conn, params.id) {
-            Ok(_) => HttpResponse::Ok().finish(),
+            Ok(_) => {
+                cache
+                    .invalidate(&[keys::brands(), keys::brand(params.id), keys::products()])
+                    .await;
+                HttpResponse::Ok().finish()
+            }
             Err(e) => DatabaseErrorWrapper(e).into(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).into(),
     }
 }
 
+/// Gets a brand.
+#[utoipa::path(
+    get,
+    path = "/brand/{id}",
+    tag = "brand",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The brand", body = Brand),
+        (status = 304, response = responses::NotModified),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_brand(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     path: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
     let params = path.into_inner();
-    match pool.get() {
-        Ok(mut conn) => match select_brand_query(&mut conn, params.id).await {
-            Ok(brand) => HttpResponse::Ok().json(brand),
+    cache
+        .serve(&request, &keys::brand(params.id), async {
+            match pool.get() {
+                Ok(mut conn) => match select_brand_query(&mut conn, params.id).await {
+                    Ok(brand) => HttpResponse::Ok().json(brand),
 
-            Err(e) => DatabaseErrorWrapper(e).into(),
-        },
-        Err(e) => ConnectionPoolErrorWrapper(e).into(),
-    }
+                    Err(e) => DatabaseErrorWrapper(e).into(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).into(),
+            }
+        })
+        .await
 }
 
+/// Updates a brand.
+#[utoipa::path(
+    put,
+    path = "/brand",
+    tag = "brand",
+    request_body = Brand,
+    responses(
+        (status = 200, description = "The updated brand", body = Brand),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_brand(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
-    payload: web::Json<Brand>,
+    cache: web::Data<Cache>,
+    payload: Valid<Brand>,
 ) -> impl Responder {
     let params = payload.into_inner();
+    let brand_id = params.id;
     match pool.get() {
-        Ok(mut conn) => match select_brand_query(&mut conn, params.id).await {
-            Ok(brand) => match set_brand_query(&mut conn, brand) {
-                Ok(updated_brand) => HttpResponse::Ok().json(updated_brand),
+        Ok(mut conn) => match select_brand_query(&mut conn, brand_id).await {
+            Ok(_) => match set_brand_query(&mut conn, params) {
+                Ok(updated_brand) => {
+                    cache
+                        .invalidate(&[keys::brands(), keys::brand(brand_id)])
+                        .await;
+                    HttpResponse::Ok().json(updated_brand)
+                }
                 Err(e) => DatabaseErrorWrapper(e).into(),
             },
             Err(e) => DatabaseErrorWrapper(e).into(),
@@ -76,9 +152,71 @@ pub async fn update_brand(
         Err(e) => ConnectionPoolErrorWrapper(e).into(),
     }
 }
+
+/// Changes the fields of a brand given in a JSON Merge Patch.
+#[utoipa::path(
+    patch,
+    path = "/brand/{id}",
+    tag = "brand",
+    params(ResourceIdentifierRequest),
+    request_body(
+        content = Brand,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change. `null` clears `description`"
+    ),
+    responses(
+        (status = 200, description = "The updated brand", body = Brand),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_brand(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    path: web::Path<ResourceIdentifierRequest>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    let brand_id = path.into_inner().id;
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let brand = select_brand_for_update_query(conn, brand_id)?;
+                let brand = merge_patch::apply(&brand, &patch, &["id"])?;
+                Ok::<_, PatchError>(save_brand_query(conn, brand)?)
+            });
+            match patched {
+                Ok(updated_brand) => {
+                    cache
+                        .invalidate(&[keys::brands(), keys::brand(brand_id)])
+                        .await;
+                    HttpResponse::Ok().json(updated_brand)
+                }
+                Err(e) => e.into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Creates a brand.
+#[utoipa::path(
+    post,
+    path = "/brand",
+    tag = "brand",
+    request_body = NewBrand,
+    responses(
+        (status = 200, description = "The created brand", body = Brand),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_brand(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
-    payload: web::Json<NewBrand>,
+    cache: web::Data<Cache>,
+    payload: Valid<NewBrand>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match pool.get() {
@@ -89,33 +227,81 @@ pub async fn create_brand(
                 description: params.description,
             },
         ) {
-            Ok(brand) => HttpResponse::Ok().json(brand),
+            Ok(brand) => {
+                cache.invalidate(&[keys::brands()]).await;
+                HttpResponse::Ok().json(brand)
+            }
             Err(e) => DatabaseErrorWrapper(e).into(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).into(),
     }
 }
 
+/// Lists brands, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/brand",
+    tag = "brand",
+    params(PageParams, BrandFilter),
+    responses(
+        (status = 200, description = "A page of brands", body = BrandPage),
+        (status = 304, response = responses::NotModified),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_brands(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    params: web::Query<PageParams>,
+    filter: web::Query<BrandFilter>,
 ) -> impl Responder {
-    match pool.get() {
-        Ok(mut conn) => match load_brand_query(&mut conn) {
-            Ok(brands) => HttpResponse::Ok().json(brands),
-            Err(e) => DatabaseErrorWrapper(e).into(),
-        },
-        Err(e) => ConnectionPoolErrorWrapper(e).into(),
-    }
+    let page = match params.resolve(BRAND_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return HttpResponse::from(e),
+    };
+    let key = keys::brands().with_query(request.query_string());
+    cache
+        .serve(&request, &key, async {
+            match pool.get() {
+                Ok(mut conn) => match load_brand_query(&mut conn, &filter, &page) {
+                    Ok((brands, total)) => {
+                        HttpResponse::Ok().json(page.page(&request, brands, total, |brand| brand))
+                    }
+                    Err(e) => DatabaseErrorWrapper(e).into(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).into(),
+            }
+        })
+        .await
 }
+/// Applies a discount to the products of a brand.
+#[utoipa::path(
+    post,
+    path = "/discount/brand",
+    tag = "discount",
+    request_body = DiscountBrand,
+    responses(
+        (status = 200, description = "The created link", body = DiscountBrand),
+        (status = 400, response = responses::BadRequest),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_discount_brand(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     discount_brand: web::Json<DiscountBrand>,
 ) -> impl Responder {
     println!("Received JSON: {:?}", discount_brand);
     let new_discount_brand = discount_brand.into_inner();
     match pool.get() {
         Ok(mut conn) => match insert_discount_brand_query(&mut conn, new_discount_brand) {
-            Ok(discount_brand) => HttpResponse::Ok().json(discount_brand),
+            Ok(discount_brand) => {
+                cache.invalidate(&[keys::products()]).await;
+                HttpResponse::Ok().json(discount_brand)
+            }
             Err(e) => DatabaseErrorWrapper(e).into(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).into(),
@@ -1,6 +1,11 @@
 use crate::schema::brands;
+use crate::{
+    api::{pagination::SortField, validation::Validate},
+    error::ValidationErrors,
+};
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(
     Queryable,
@@ -12,6 +17,8 @@ use serde::{Deserialize, Serialize};
     Deserialize,
     Insertable,
     AsChangeset,
+    Clone,
+    ToSchema,
 )]
 #[diesel(table_name = brands)]
 pub struct Brand {
@@ -20,13 +27,25 @@ pub struct Brand {
     pub description: Option<String>,
 }
 
-#[derive(Insertable, Serialize, Deserialize, Clone)]
+impl Validate for Brand {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.not_blank("name", &self.name);
+    }
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[diesel(table_name = brands)]
 pub struct NewBrand {
     pub name: String,
     pub description: Option<String>,
 }
 
+impl Validate for NewBrand {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.not_blank("name", &self.name);
+    }
+}
+
 // API Requests for Brand
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreateBrandRequest {
@@ -34,6 +53,20 @@ pub struct CreateBrandRequest {
     pub description: Option<String>,
 }
 
+/// Fields brands can be sorted by.
+pub const BRAND_SORT_FIELDS: &[SortField] = &[
+    SortField::integer("id", "brands.id"),
+    SortField::text("name", "brands.name"),
+];
+
+/// Narrows a brand list.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BrandFilter {
+    /// Text the name contains, ignoring case.
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UpdateBrandRequest {
     pub id: i32,
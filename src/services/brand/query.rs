@@ -1,6 +1,10 @@
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use diesel::{
+    pg::Pg, Connection, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
 
 use crate::{
+    api::pagination::{self, PageRequest},
     postgres::PooledConnection,
     services::{
         discount::model::{relations::DiscountBrand, Discount},
@@ -8,7 +12,7 @@ use crate::{
     },
 };
 
-use super::model::{Brand, NewBrand};
+use super::model::{Brand, BrandFilter, NewBrand};
 
 pub fn load_brand_products_query(
     connection: &mut PooledConnection,
@@ -22,10 +26,13 @@ pub fn load_brand_products_query(
 }
 
 // Insert a new brand
-pub fn insert_brand_query(
-    connection: &mut PooledConnection,
+pub fn insert_brand_query<C>(
+    connection: &mut C,
     new_brand: NewBrand,
-) -> Result<Brand, diesel::result::Error> {
+) -> Result<Brand, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::brands::table)
         .values(&new_brand)
         .returning(Brand::as_select())
@@ -33,15 +40,30 @@ pub fn insert_brand_query(
 }
 
 // Update an existing brand
-pub fn set_brand_query(
-    connection: &mut PooledConnection,
+pub fn set_brand_query<C>(
+    connection: &mut C,
     updated_brand: Brand,
-) -> Result<Brand, diesel::result::Error> {
+) -> Result<Brand, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::update(crate::schema::brands::table.find(updated_brand.id))
         .set(&updated_brand)
         .get_result::<Brand>(connection)
 }
 
+// Write every column of a brand. Unlike set_brand_query, None clears a column
+pub fn save_brand_query<C>(connection: &mut C, brand: Brand) -> Result<Brand, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    use crate::schema::brands::dsl::*;
+
+    diesel::update(brands.find(brand.id))
+        .set((name.eq(brand.name), description.eq(brand.description)))
+        .get_result::<Brand>(connection)
+}
+
 pub async fn select_brand_query(
     connection: &mut PooledConnection,
     brand_id: i32,
@@ -52,19 +74,55 @@ pub async fn select_brand_query(
         .first::<Brand>(connection)
 }
 
-// Delete a brand by ID
-pub fn delete_brand_query(
+/// Reads a brand and locks its row until the transaction ends.
+pub fn select_brand_for_update_query(
     connection: &mut PooledConnection,
+    brand_id: i32,
+) -> Result<Brand, diesel::result::Error> {
+    crate::schema::brands::table
+        .select(Brand::as_select())
+        .filter(crate::schema::brands::id.eq(brand_id))
+        .for_update()
+        .first::<Brand>(connection)
+}
+
+// Delete a brand by ID
+pub fn delete_brand_query<C>(
+    connection: &mut C,
     id: i32,
-) -> Result<usize, diesel::result::Error> {
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::delete(crate::schema::brands::table.find(id)).execute(connection)
 }
 
 // Load all brands
+fn filtered_brands_query(filter: &BrandFilter) -> crate::schema::brands::BoxedQuery<'static, Pg> {
+    let mut query = crate::schema::brands::table.into_boxed();
+    if let Some(name) = &filter.name {
+        query = query.filter(crate::schema::brands::name.ilike(pagination::contains(name)));
+    }
+    query
+}
+
+/// A page of the brands matching `filter`, and how many match.
 pub fn load_brand_query(
     connection: &mut PooledConnection,
-) -> Result<Vec<Brand>, diesel::result::Error> {
-    crate::schema::brands::table.load::<Brand>(connection)
+    filter: &BrandFilter,
+    page: &PageRequest,
+) -> Result<(Vec<Brand>, i64), diesel::result::Error> {
+    let total = filtered_brands_query(filter)
+        .count()
+        .get_result::<i64>(connection)?;
+    let mut query = filtered_brands_query(filter)
+        .order(page.order())
+        .limit(page.load_limit())
+        .offset(page.offset());
+    if let Some(seek) = page.seek() {
+        query = query.filter(seek);
+    }
+    Ok((query.load::<Brand>(connection)?, total))
 }
 
 pub fn fetch_brand_discounts(
@@ -1,8 +1,12 @@
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, patch, post, put, scope};
+use utoipa::OpenApi;
 
 use super::handler::{
-    create_brand, delete_brand, get_brand, list_brand_products, list_brands, update_brand,
+    create_brand, delete_brand, get_brand, list_brand_products, list_brands, patch_brand,
+    update_brand,
 };
+use super::model::{Brand, NewBrand};
+use crate::api::pagination::BrandPage;
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -12,6 +16,23 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", put().to(update_brand))
             .route("/{id}", get().to(get_brand))
             .route("/{id}", delete().to(delete_brand))
+            .route("/{id}", patch().to(patch_brand))
             .route("/{id}/products", get().to(list_brand_products)),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_brand,
+        super::handler::list_brands,
+        super::handler::update_brand,
+        super::handler::get_brand,
+        super::handler::delete_brand,
+        super::handler::patch_brand,
+        super::handler::list_brand_products
+    ),
+    components(schemas(Brand, NewBrand, BrandPage)),
+    tags((name = "brand", description = "Brands that products are sold under"))
+)]
+pub struct BrandApi;
@@ -1,19 +1,35 @@
-use actix_web::{web, HttpResponse, Responder, ResponseError};
-use diesel::PgConnection;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel::{Connection, PgConnection};
+use serde_json::{Map, Value};
 
 use crate::{
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        pagination::PageParams,
+    },
     error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
     ResourceIdentifierRequest,
 };
 
 use super::{
-    model::Cart,
+    model::{Cart, CartFilter, CART_SORT_FIELDS},
     query::{
-        delete_cart_query, insert_cart_query, list_carts_with_orderlines_query, select_cart_query,
-        set_cart_query,
+        delete_cart_query, insert_cart_query, list_carts_with_orderlines_query,
+        select_cart_for_update_query, select_cart_query, set_cart_query,
     },
 };
 
+/// Creates an empty cart.
+#[utoipa::path(
+    post,
+    path = "/cart",
+    tag = "cart",
+    responses(
+        (status = 200, description = "The created cart", body = Cart),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_cart(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
 ) -> impl Responder {
@@ -26,6 +42,19 @@ pub async fn create_cart(
     }
 }
 
+/// Updates whether a cart is active. A cart is checked out when it stops being active.
+#[utoipa::path(
+    put,
+    path = "/cart",
+    tag = "cart",
+    request_body = Cart,
+    responses(
+        (status = 200, description = "The updated cart", body = Cart),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_cart(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
     payload: web::Json<Cart>,
@@ -49,6 +78,58 @@ pub async fn update_cart(
     }
 }
 
+/// Changes the fields of a cart given in a JSON Merge Patch.
+#[utoipa::path(
+    patch,
+    path = "/cart/{id}",
+    tag = "cart",
+    params(ResourceIdentifierRequest),
+    request_body(
+        content = Cart,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change"
+    ),
+    responses(
+        (status = 200, description = "The updated cart", body = Cart),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_cart(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let cart = select_cart_for_update_query(conn, path.into_inner().id)?;
+                let cart = merge_patch::apply(&cart, &patch, &["id"])?;
+                Ok::<_, PatchError>(set_cart_query(conn, cart)?)
+            });
+            match patched {
+                Ok(updated_cart) => HttpResponse::Ok().json(updated_cart),
+                Err(e) => e.into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
+    }
+}
+
+/// Gets a cart.
+#[utoipa::path(
+    get,
+    path = "/cart/{id}",
+    tag = "cart",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The cart", body = Cart),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_cart(
     path: web::Path<ResourceIdentifierRequest>,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
@@ -62,18 +143,50 @@ pub async fn get_cart(
     }
 }
 
+/// Lists carts with their order lines, totals and discounts, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/cart",
+    tag = "cart",
+    params(PageParams, CartFilter),
+    responses(
+        (status = 200, description = "A page of carts", body = CartPage),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_carts_with_orderlines(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    params: web::Query<PageParams>,
+    filter: web::Query<CartFilter>,
 ) -> impl Responder {
+    let page = match params.resolve(CART_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return e.error_response(),
+    };
     match pool.get() {
-        Ok(mut conn) => match list_carts_with_orderlines_query(&mut conn).await {
-            Ok(cart) => HttpResponse::Ok().json(cart),
+        Ok(mut conn) => match list_carts_with_orderlines_query(&mut conn, &filter, &page).await {
+            Ok((carts, total)) => {
+                HttpResponse::Ok().json(page.page(&request, carts, total, |cart| &cart.cart))
+            }
             Err(e) => DatabaseErrorWrapper(e).into(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
     }
 }
 
+/// Deletes a cart and its order lines.
+#[utoipa::path(
+    delete,
+    path = "/cart/{id}",
+    tag = "cart",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "Number of carts deleted", body = usize),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_cart(
     path: web::Path<ResourceIdentifierRequest>,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
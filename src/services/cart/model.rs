@@ -1,6 +1,12 @@
-use crate::{schema::carts, services::order::model::OrderLineInCart};
+use crate::{
+    api::{pagination::SortField, validation::Validate},
+    error::ValidationErrors,
+    schema::carts,
+    services::order::model::OrderLineInCart,
+};
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(
     Queryable,
@@ -13,6 +19,7 @@ use serde::{Deserialize, Serialize};
     Insertable,
     Clone,
     AsChangeset,
+    ToSchema,
 )]
 #[diesel(table_name = carts)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -21,8 +28,23 @@ pub struct Cart {
     pub is_active: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Any `is_active` is valid.
+impl Validate for Cart {
+    fn validate(&self, _errors: &mut ValidationErrors) {}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct CartWithOrderLines {
     pub cart: Cart,
     pub order_lines: Vec<OrderLineInCart>,
 }
+
+/// Fields carts can be sorted by.
+pub const CART_SORT_FIELDS: &[SortField] = &[SortField::integer("id", "carts.id")];
+
+/// Narrows a cart list.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CartFilter {
+    pub is_active: Option<bool>,
+}
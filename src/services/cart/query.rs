@@ -1,15 +1,38 @@
+use crate::api::pagination::PageRequest;
 use crate::postgres::PooledConnection;
 use crate::services::discount::utils::sort_discounts_by_start_date_desc;
 use crate::services::order::query::load_orderlines_query;
 use crate::services::order::utils::map_orderlines_to_carts;
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::{QueryDsl, RunQueryDsl};
 
-use super::model::{Cart, CartWithOrderLines};
+use super::model::{Cart, CartFilter, CartWithOrderLines};
 
-fn load_carts_query(connection: &mut PooledConnection) -> Result<Vec<Cart>, diesel::result::Error> {
-    use crate::schema::carts::dsl::*;
-    carts.load::<Cart>(connection)
+fn filtered_carts_query(filter: &CartFilter) -> crate::schema::carts::BoxedQuery<'static, Pg> {
+    let mut query = crate::schema::carts::table.into_boxed();
+    if let Some(is_active) = filter.is_active {
+        query = query.filter(crate::schema::carts::is_active.eq(is_active));
+    }
+    query
+}
+
+fn load_carts_query(
+    connection: &mut PooledConnection,
+    filter: &CartFilter,
+    page: &PageRequest,
+) -> Result<(Vec<Cart>, i64), diesel::result::Error> {
+    let total = filtered_carts_query(filter)
+        .count()
+        .get_result::<i64>(connection)?;
+    let mut query = filtered_carts_query(filter)
+        .order(page.order())
+        .limit(page.load_limit())
+        .offset(page.offset());
+    if let Some(seek) = page.seek() {
+        query = query.filter(seek);
+    }
+    Ok((query.load::<Cart>(connection)?, total))
 }
 
 pub fn insert_cart_query(connection: &mut PooledConnection) -> Result<Cart, diesel::result::Error> {
@@ -37,6 +60,17 @@ pub fn select_cart_query(
         .filter(crate::schema::carts::id.eq(discount_id))
         .first::<Cart>(connection)
 }
+/// Reads a cart and locks its row until the transaction ends.
+pub fn select_cart_for_update_query(
+    connection: &mut PooledConnection,
+    cart_id: i32,
+) -> Result<Cart, diesel::result::Error> {
+    crate::schema::carts::table
+        .select(Cart::as_select())
+        .filter(crate::schema::carts::id.eq(cart_id))
+        .for_update()
+        .first::<Cart>(connection)
+}
 pub fn delete_cart_query(
     connection: &mut PooledConnection,
     cart_id: i32,
@@ -44,16 +78,20 @@ pub fn delete_cart_query(
     diesel::delete(crate::schema::carts::table.filter(crate::schema::carts::id.eq(cart_id)))
         .execute(connection)
 }
+/// A page of the carts matching `filter` with their order lines, in page
+/// order, and how many carts match.
 pub async fn list_carts_with_orderlines_query(
     connection: &mut PooledConnection,
-) -> Result<Vec<CartWithOrderLines>, diesel::result::Error> {
-    let cart_vector = load_carts_query(connection)?;
+    filter: &CartFilter,
+    page: &PageRequest,
+) -> Result<(Vec<CartWithOrderLines>, i64), diesel::result::Error> {
+    let (cart_vector, total) = load_carts_query(connection, filter, page)?;
+    let cart_ids: Vec<i32> = cart_vector.iter().map(|cart| cart.id).collect();
     let orderline_in_cart_vector =
-        load_orderlines_query(connection, sort_discounts_by_start_date_desc).unwrap();
-    Ok(map_orderlines_to_carts(
-        cart_vector,
-        orderline_in_cart_vector,
-    ))
+        load_orderlines_query(connection, &cart_ids, sort_discounts_by_start_date_desc)?;
+    let mut carts = map_orderlines_to_carts(cart_vector, orderline_in_cart_vector);
+    carts.sort_by_key(|cart| cart_ids.iter().position(|id| *id == cart.cart.id));
+    Ok((carts, total))
 }
 
 // fn load_carts_query(connection: &mut PooledConnection) -> Result<Vec<Cart>, AppError> {
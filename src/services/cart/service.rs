@@ -1,7 +1,10 @@
-use actix_web::web::{delete, get, post, put};
+use actix_web::web::{delete, get, patch, post, put};
+use utoipa::OpenApi;
 
 use super::handler::list_carts_with_orderlines;
-use super::handler::{create_cart, delete_cart, get_cart, update_cart};
+use super::handler::{create_cart, delete_cart, get_cart, patch_cart, update_cart};
+use super::model::{Cart, CartWithOrderLines};
+use crate::api::pagination::CartPage;
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -10,6 +13,22 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", get().to(list_carts_with_orderlines))
             .route("", put().to(update_cart))
             .route("/{id}", get().to(get_cart))
-            .route("/{id}", delete().to(delete_cart)),
+            .route("/{id}", delete().to(delete_cart))
+            .route("/{id}", patch().to(patch_cart)),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_cart,
+        super::handler::list_carts_with_orderlines,
+        super::handler::update_cart,
+        super::handler::get_cart,
+        super::handler::delete_cart,
+        super::handler::patch_cart
+    ),
+    components(schemas(Cart, CartWithOrderLines, CartPage)),
+    tags((name = "cart", description = "Carts and their checkout"))
+)]
+pub struct CartApi;
@@ -0,0 +1,112 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use diesel::PgConnection;
+
+use crate::{
+    api::openapi::responses,
+    cache::{keys, Cache},
+    error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
+    services::discount::model::relations::DiscountCategory,
+    ResourceIdentifierRequest,
+};
+
+use super::query::{insert_discount_category_query, load_categories_query, select_category_query};
+
+/// Lists categories.
+#[utoipa::path(
+    get,
+    path = "/category",
+    tag = "category",
+    responses(
+        (status = 200, description = "Every category", body = [Category]),
+        (status = 304, response = responses::NotModified),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn list_categories(
+    request: HttpRequest,
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+) -> impl Responder {
+    cache
+        .serve(&request, &keys::categories(), async {
+            match pool.get() {
+                Ok(mut conn) => match load_categories_query(&mut conn) {
+                    Ok(categories) => HttpResponse::Ok().json(categories),
+                    Err(e) => DatabaseErrorWrapper(e).into(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).into(),
+            }
+        })
+        .await
+}
+
+/// Gets a category.
+#[utoipa::path(
+    get,
+    path = "/category/{id}",
+    tag = "category",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The category", body = Category),
+        (status = 304, response = responses::NotModified),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn get_category(
+    request: HttpRequest,
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    path: web::Path<ResourceIdentifierRequest>,
+) -> impl Responder {
+    let params = path.into_inner();
+    cache
+        .serve(&request, &keys::category(params.id), async {
+            match pool.get() {
+                Ok(mut conn) => match select_category_query(&mut conn, params.id) {
+                    Ok(category) => HttpResponse::Ok().json(category),
+                    Err(e) => DatabaseErrorWrapper(e).into(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).into(),
+            }
+        })
+        .await
+}
+
+/// Applies a discount to the products of a category.
+#[utoipa::path(
+    post,
+    path = "/discount/category",
+    tag = "discount",
+    request_body = DiscountCategory,
+    responses(
+        (status = 200, description = "The created link", body = DiscountCategory),
+        (status = 400, response = responses::BadRequest),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn create_discount_category(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    discount_category: web::Json<DiscountCategory>,
+) -> impl Responder {
+    match pool.get() {
+        Ok(mut conn) => {
+            match insert_discount_category_query(&mut conn, discount_category.into_inner()) {
+                Ok(discount_category) => {
+                    cache
+                        .invalidate(&[
+                            keys::products(),
+                            keys::categories(),
+                            keys::category(discount_category.category_id),
+                        ])
+                        .await;
+                    HttpResponse::Ok().json(discount_category)
+                }
+                Err(e) => DatabaseErrorWrapper(e).into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
@@ -1,3 +1,4 @@
 pub mod model;
 pub mod service;
-pub mod query;
\ No newline at end of file
+pub mod query;
+pub mod handler;
\ No newline at end of file
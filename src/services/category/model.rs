@@ -1,6 +1,7 @@
 use crate::schema::categories;
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(
     Queryable,
@@ -12,6 +13,8 @@ use serde::{Deserialize, Serialize};
     Deserialize,
     Insertable,
     AsChangeset,
+    Clone,
+    ToSchema,
 )]
 #[diesel(table_name = categories)]
 pub struct Category {
@@ -44,7 +47,7 @@ impl<'a> NewCategory<'a> {
 }
 
 // API Requests for Category
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CreateCategoryRequest {
     pub name: String,
     pub description: String,
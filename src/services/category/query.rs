@@ -1,5 +1,5 @@
 use crate::postgres::PooledConnection;
-use crate::services::discount::model::Discount;
+use crate::services::discount::model::{relations::DiscountCategory, Discount};
 use crate::services::product::model::Product;
 use diesel::prelude::*;
 
@@ -33,29 +33,38 @@ pub fn inner_join_products_with_categories(
         .load::<(Product, Category)>(connection)
 }
 
-pub fn insert_product_query_pooled_conn(
-    connection: &mut PooledConnection,
+pub fn insert_product_query_pooled_conn<C>(
+    connection: &mut C,
     new_category: NewCategory,
-) -> Result<Category, diesel::result::Error> {
+) -> Result<Category, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::categories::table)
         .values(&new_category)
         .returning(crate::schema::categories::all_columns)
         .get_result::<Category>(connection)
 }
 
-pub fn set_product_query(
-    connection: &mut PooledConnection,
+pub fn set_product_query<C>(
+    connection: &mut C,
     updated_category: Category,
-) -> Result<Category, diesel::result::Error> {
+) -> Result<Category, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::update(crate::schema::categories::table.find(updated_category.id))
         .set(&updated_category)
         .get_result::<Category>(connection)
 }
 
-pub fn delete_product_query(
-    connection: &mut PooledConnection,
+pub fn delete_product_query<C>(
+    connection: &mut C,
     category_id: i32,
-) -> Result<usize, diesel::result::Error> {
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::delete(
         crate::schema::categories::table.filter(crate::schema::categories::id.eq(category_id)),
     )
@@ -83,3 +92,12 @@ pub fn fetch_category_discounts(
         .select(crate::schema::discounts::all_columns)
         .load::<Discount>(connection)
 }
+
+pub fn insert_discount_category_query(
+    connection: &mut PooledConnection,
+    new_discount: DiscountCategory,
+) -> Result<DiscountCategory, diesel::result::Error> {
+    diesel::insert_into(crate::schema::discount_categories::table)
+        .values(new_discount)
+        .get_result::<DiscountCategory>(connection)
+}
@@ -0,0 +1,24 @@
+use actix_web::web::{get, scope};
+use utoipa::OpenApi;
+
+use super::handler::{get_category, list_categories};
+use super::model::Category;
+
+pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(
+        scope("/category")
+            .route("", get().to(list_categories))
+            .route("/{id}", get().to(get_category)),
+    );
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::list_categories,
+        super::handler::get_category
+    ),
+    components(schemas(Category)),
+    tags((name = "category", description = "Categories products are filed under"))
+)]
+pub struct CategoryApi;
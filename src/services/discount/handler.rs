@@ -1,55 +1,132 @@
-use actix_web::{web, HttpResponse, Responder, ResponseError};
-use diesel::PgConnection;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel::{Connection, PgConnection};
+use serde_json::{Map, Value};
 
 use crate::{
     services::discount::query::{insert_discount_product_query, insert_discount_query},
-    error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        pagination::PageParams,
+        validation::Valid,
+    },
+    cache::{keys, Cache},
+    error::{AppError, ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
     ResourceIdentifierRequest,
 };
 
 use super::{
-    model::{relations::DiscountProduct, Discount, NewDiscount},
+    model::{
+        relations::DiscountProduct, Discount, DiscountFilter, NewDiscount, DISCOUNT_SORT_FIELDS,
+    },
     query::{
-        delete_discount_query, load_discounts_query, select_discount_query, set_discount_query,
+        delete_discount_query, load_discounts_query, select_discount_for_update_query,
+        select_discount_query, set_discount_query,
     },
 };
 
+/// Gets a discount.
+#[utoipa::path(
+    get,
+    path = "/discount/{id}",
+    tag = "discount",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The discount", body = Discount),
+        (status = 304, response = responses::NotModified),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_discount(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     payload: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
-    match pool.get() {
-        Ok(mut conn) => match select_discount_query(&mut conn, payload.into_inner().id) {
-            Ok(discount) => HttpResponse::Ok().json(discount),
-            Err(e) => DatabaseErrorWrapper(e).error_response(),
-        },
-        Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
-    }
+    let id = payload.into_inner().id;
+    cache
+        .serve(&request, &keys::discount(id), async {
+            match pool.get() {
+                Ok(mut conn) => match select_discount_query(&mut conn, id) {
+                    Ok(discount) => HttpResponse::Ok().json(discount),
+                    Err(e) => DatabaseErrorWrapper(e).error_response(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
+            }
+        })
+        .await
 }
 
+/// Lists discounts, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/discount",
+    tag = "discount",
+    params(PageParams, DiscountFilter),
+    responses(
+        (status = 200, description = "A page of discounts", body = DiscountPage),
+        (status = 304, response = responses::NotModified),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_discounts(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    params: web::Query<PageParams>,
+    filter: web::Query<DiscountFilter>,
 ) -> impl Responder {
-    match pool.get() {
-        Ok(mut conn) => match load_discounts_query(&mut conn) {
-            Ok(discounts) => HttpResponse::Ok().json(discounts),
-            Err(e) => DatabaseErrorWrapper(e).error_response(),
-        },
-        Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
-    }
+    let page = match params.resolve(DISCOUNT_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return e.error_response(),
+    };
+    let key = keys::discounts().with_query(request.query_string());
+    cache
+        .serve(&request, &key, async {
+            match pool.get() {
+                Ok(mut conn) => match load_discounts_query(&mut conn, &filter, &page) {
+                    Ok((discounts, total)) => {
+                        HttpResponse::Ok()
+                            .json(page.page(&request, discounts, total, |discount| discount))
+                    }
+                    Err(e) => DatabaseErrorWrapper(e).error_response(),
+                },
+                Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
+            }
+        })
+        .await
 }
 
+/// Deletes a discount.
+#[utoipa::path(
+    delete,
+    path = "/discount/{id}",
+    tag = "discount",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The discount was deleted"),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_discount(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     payload: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
+    let id = payload.into_inner().id;
     match pool.get() {
-        Ok(mut conn) => match delete_discount_query(&mut conn, payload.into_inner().id) {
+        Ok(mut conn) => match delete_discount_query(&mut conn, id) {
             Ok(deleted_count) => {
                 if deleted_count > 0 {
+                    cache
+                        .invalidate(&[keys::discounts(), keys::discount(id), keys::products()])
+                        .await;
                     HttpResponse::Ok().finish()
                 } else {
-                    HttpResponse::NotFound().body("Discount not found")
+                    AppError::not_found(format!("No discount has id {}", id)).into()
                 }
             }
             Err(e) => DatabaseErrorWrapper(e).error_response(),
@@ -58,38 +135,87 @@ pub async fn delete_discount(
     }
 }
 
+/// Applies a discount to a product.
+#[utoipa::path(
+    post,
+    path = "/discount/product",
+    tag = "discount",
+    request_body = DiscountProduct,
+    responses(
+        (status = 200, description = "The created link", body = DiscountProduct),
+        (status = 400, response = responses::BadRequest),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_discount_product(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
     discount_product: web::Json<DiscountProduct>,
 ) -> impl Responder {
     println!("Received JSON: {:?}", discount_product);
     let new_discount_product = discount_product.into_inner();
     match pool.get() {
         Ok(mut conn) => match insert_discount_product_query(&mut conn, new_discount_product) {
-            Ok(discount_product) => HttpResponse::Ok().json(discount_product),
+            Ok(discount_product) => {
+                cache.invalidate(&[keys::products()]).await;
+                HttpResponse::Ok().json(discount_product)
+            }
             Err(e) => DatabaseErrorWrapper(e).error_response(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
     }
 }
+/// Creates a discount.
+#[utoipa::path(
+    post,
+    path = "/discount",
+    tag = "discount",
+    request_body = NewDiscount,
+    responses(
+        (status = 200, description = "The created discount", body = Discount),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_discount(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
-    discount_product: web::Json<NewDiscount>,
+    cache: web::Data<Cache>,
+    discount_product: Valid<NewDiscount>,
 ) -> impl Responder {
     println!("Received JSON: {:?}", discount_product);
     let new_discount_product = discount_product.into_inner();
     match pool.get() {
         Ok(mut conn) => match insert_discount_query(&mut conn, new_discount_product) {
-            Ok(discount_product) => HttpResponse::Ok().json(discount_product),
+            Ok(discount_product) => {
+                cache.invalidate(&[keys::discounts()]).await;
+                HttpResponse::Ok().json(discount_product)
+            }
             Err(e) => DatabaseErrorWrapper(e).error_response(),
         },
         Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
     }
 }
 
+/// Updates a discount.
+#[utoipa::path(
+    put,
+    path = "/discount",
+    tag = "discount",
+    request_body = Discount,
+    responses(
+        (status = 200, description = "The updated discount", body = Discount),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_discount(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
-    payload: web::Json<Discount>,
+    cache: web::Data<Cache>,
+    payload: Valid<Discount>,
 ) -> impl Responder {
     let params: Discount = payload.into_inner();
     match pool.get() {
@@ -106,7 +232,16 @@ pub async fn update_discount(
                     min_quantity: params.min_quantity,
                 },
             ) {
-                Ok(updated_discount) => HttpResponse::Ok().json(updated_discount),
+                Ok(updated_discount) => {
+                    cache
+                        .invalidate(&[
+                            keys::discounts(),
+                            keys::discount(updated_discount.id),
+                            keys::products(),
+                        ])
+                        .await;
+                    HttpResponse::Ok().json(updated_discount)
+                }
                 Err(e) => DatabaseErrorWrapper(e).error_response(),
             },
             Err(e) => DatabaseErrorWrapper(e).error_response(),
@@ -114,3 +249,54 @@ pub async fn update_discount(
         Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
     }
 }
+
+/// Changes the fields of a discount given in a JSON Merge Patch.
+#[utoipa::path(
+    patch,
+    path = "/discount/{id}",
+    tag = "discount",
+    params(ResourceIdentifierRequest),
+    request_body(
+        content = Discount,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change"
+    ),
+    responses(
+        (status = 200, description = "The updated discount", body = Discount),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_discount(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    cache: web::Data<Cache>,
+    path: web::Path<ResourceIdentifierRequest>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    let discount_id = path.into_inner().id;
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let discount = select_discount_for_update_query(conn, discount_id)?;
+                let discount = merge_patch::apply(&discount, &patch, &["id"])?;
+                Ok::<_, PatchError>(set_discount_query(conn, discount)?)
+            });
+            match patched {
+                Ok(updated_discount) => {
+                    cache
+                        .invalidate(&[
+                            keys::discounts(),
+                            keys::discount(discount_id),
+                            keys::products(),
+                        ])
+                        .await;
+                    HttpResponse::Ok().json(updated_discount)
+                }
+                Err(e) => e.error_response(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).error_response(),
+    }
+}
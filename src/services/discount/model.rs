@@ -1,7 +1,12 @@
 use crate::schema::discounts;
+use crate::{
+    api::{pagination::SortField, validation::Validate},
+    error::ValidationErrors,
+};
 use chrono::NaiveDateTime;
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(
     Queryable,
@@ -14,6 +19,7 @@ use serde::{Deserialize, Serialize};
     Serialize,
     Deserialize,
     Clone,
+    ToSchema,
 )]
 #[diesel(table_name = discounts)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -30,8 +36,21 @@ pub struct Discount {
     pub min_quantity: i32,
 }
 
+impl Validate for Discount {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        NewDiscount {
+            name: self.name.clone(),
+            discount_type: self.discount_type.clone(),
+            value: self.value,
+            start_date: self.start_date,
+            end_date: self.end_date,
+            min_quantity: self.min_quantity,
+        }
+        .validate(errors);
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize, Insertable, Queryable, Clone)]
+#[derive(Debug, Serialize, Deserialize, Insertable, Queryable, Clone, ToSchema)]
 #[diesel(table_name = discounts)]
 pub struct NewDiscount {
     pub name: String,
@@ -42,6 +61,41 @@ pub struct NewDiscount {
     pub min_quantity: i32,
 }
 
+pub const DISCOUNT_TYPES: [&str; 2] = ["percentage", "fixed"];
+
+impl Validate for NewDiscount {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.not_blank("name", &self.name);
+        let discount_type = self.discount_type.to_lowercase();
+        errors.one_of("discount_type", &discount_type, &DISCOUNT_TYPES);
+        errors.at_least("value", self.value, 0);
+        if discount_type == "percentage" {
+            errors.at_most("value", self.value, 100);
+        }
+        errors.at_least("min_quantity", self.min_quantity, 1);
+        errors.after("end_date", &self.end_date, "start_date", &self.start_date);
+    }
+}
+
+/// Fields discounts can be sorted by.
+pub const DISCOUNT_SORT_FIELDS: &[SortField] = &[
+    SortField::integer("id", "discounts.id"),
+    SortField::text("name", "discounts.name"),
+    SortField::integer("value", "discounts.value"),
+    SortField::timestamp("start_date", "discounts.start_date"),
+    SortField::timestamp("end_date", "discounts.end_date"),
+];
+
+/// Narrows a discount list. Discounts must match every filter given.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiscountFilter {
+    /// `true` for discounts running now, `false` for those that ended or
+    /// haven't started.
+    pub active: Option<bool>,
+    pub discount_type: Option<String>,
+}
+
 pub mod relations {
     use crate::schema::{discount_brands, discount_categories, discount_products};
     use crate::services::brand::model::Brand;
@@ -50,8 +104,10 @@ pub mod relations {
     use crate::services::product::model::Product;
     use diesel::{associations::Identifiable, Associations, Insertable, Queryable, Selectable};
     use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
     #[derive(
         Identifiable, Selectable, Queryable, Associations, Debug, Serialize, Deserialize, Insertable,
+        Clone, ToSchema,
     )]
     #[diesel(belongs_to(Discount))]
     #[diesel(belongs_to(Brand))]
@@ -64,6 +120,7 @@ pub mod relations {
 
     #[derive(
         Identifiable, Selectable, Queryable, Associations, Debug, Serialize, Deserialize, Insertable,
+        Clone, ToSchema,
     )]
     #[diesel(belongs_to(Discount))]
     #[diesel(belongs_to(Category))]
@@ -76,6 +133,7 @@ pub mod relations {
 
     #[derive(
         Identifiable, Selectable, Queryable, Associations, Debug, Serialize, Deserialize, Insertable,
+        Clone, ToSchema,
     )]
     #[diesel(belongs_to(Discount))]
     #[diesel(belongs_to(Product))]
@@ -90,9 +148,11 @@ pub mod relations {
 pub mod break_down {
     use serde::{Deserialize, Serialize};
     use serde_json::{json, Value};
+    use utoipa::ToSchema;
 
-    #[derive(Debug, Serialize, Deserialize, Clone)]
+    #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
     pub struct Resolver {
+        #[schema(value_type = Vec<Object>)]
         pub steps: Vec<Value>,
     }
 
@@ -1,10 +1,14 @@
+use crate::api::pagination::PageRequest;
 use crate::postgres::PooledConnection;
 
 use super::model::relations::DiscountBrand;
 use super::model::relations::DiscountCategory;
 use super::model::relations::DiscountProduct;
 use super::model::Discount;
+use super::model::DiscountFilter;
 use super::model::NewDiscount;
+use diesel::dsl::{not, now};
+use diesel::pg::Pg;
 use diesel::prelude::*;
 
 pub fn select_discount_query(
@@ -17,61 +21,166 @@ pub fn select_discount_query(
         .first::<Discount>(connection)
 }
 
-pub fn delete_discount_query(
+/// Reads a discount and locks its row until the transaction ends.
+pub fn select_discount_for_update_query(
     connection: &mut PooledConnection,
     discount_id: i32,
-) -> Result<usize, diesel::result::Error> {
+) -> Result<Discount, diesel::result::Error> {
+    crate::schema::discounts::table
+        .select(Discount::as_select())
+        .filter(crate::schema::discounts::id.eq(discount_id))
+        .for_update()
+        .first::<Discount>(connection)
+}
+
+pub fn delete_discount_query<C>(
+    connection: &mut C,
+    discount_id: i32,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::delete(crate::schema::discounts::table.find(discount_id)).execute(connection)
 }
 
+fn filtered_discounts_query(
+    filter: &DiscountFilter,
+) -> crate::schema::discounts::BoxedQuery<'static, Pg> {
+    use crate::schema::discounts;
+
+    let mut query = discounts::table.into_boxed();
+    let running = discounts::start_date
+        .le(now)
+        .and(discounts::end_date.ge(now));
+    match filter.active {
+        Some(true) => query = query.filter(running),
+        Some(false) => query = query.filter(not(running)),
+        None => {}
+    }
+    if let Some(discount_type) = &filter.discount_type {
+        query = query.filter(discounts::discount_type.eq(discount_type.clone()));
+    }
+    query
+}
+
+/// A page of the discounts matching `filter`, and how many match.
 pub fn load_discounts_query(
     connection: &mut PooledConnection,
-) -> Result<Vec<Discount>, diesel::result::Error> {
-    crate::schema::discounts::table.load::<Discount>(connection)
+    filter: &DiscountFilter,
+    page: &PageRequest,
+) -> Result<(Vec<Discount>, i64), diesel::result::Error> {
+    let total = filtered_discounts_query(filter)
+        .count()
+        .get_result::<i64>(connection)?;
+    let mut query = filtered_discounts_query(filter)
+        .order(page.order())
+        .limit(page.load_limit())
+        .offset(page.offset());
+    if let Some(seek) = page.seek() {
+        query = query.filter(seek);
+    }
+    Ok((query.load::<Discount>(connection)?, total))
 }
 
-pub fn insert_discount_query(
-    connection: &mut PooledConnection,
+pub fn insert_discount_query<C>(
+    connection: &mut C,
     new_discount: NewDiscount,
-) -> Result<Discount, diesel::result::Error> {
+) -> Result<Discount, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::discounts::table)
         .values(&new_discount)
         .returning(crate::schema::discounts::all_columns)
         .get_result::<Discount>(connection)
 }
 
-pub fn set_discount_query(
-    connection: &mut PooledConnection,
+pub fn set_discount_query<C>(
+    connection: &mut C,
     updated_discount: Discount,
-) -> Result<Discount, diesel::result::Error> {
+) -> Result<Discount, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::update(crate::schema::discounts::table.find(updated_discount.id))
         .set(&updated_discount)
         .get_result::<Discount>(connection)
 }
 
-pub fn insert_discount_brand_query(
-    connection: &mut PooledConnection,
+pub fn insert_discount_brand_query<C>(
+    connection: &mut C,
     new_discount: DiscountBrand,
-) -> Result<DiscountBrand, diesel::result::Error> {
+) -> Result<DiscountBrand, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::discount_brands::table)
         .values(new_discount)
         .get_result::<DiscountBrand>(connection)
 }
 
-pub fn insert_discount_category_query(
-    connection: &mut PooledConnection,
+pub fn insert_discount_category_query<C>(
+    connection: &mut C,
     new_discount: DiscountCategory,
-) -> Result<DiscountCategory, diesel::result::Error> {
+) -> Result<DiscountCategory, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::discount_categories::table)
         .values(new_discount)
         .get_result::<DiscountCategory>(connection)
 }
 
-pub fn insert_discount_product_query(
-    connection: &mut PooledConnection,
+pub fn insert_discount_product_query<C>(
+    connection: &mut C,
     new_discount: DiscountProduct,
-) -> Result<DiscountProduct, diesel::result::Error> {
+) -> Result<DiscountProduct, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::insert_into(crate::schema::discount_products::table)
         .values(new_discount)
         .get_result::<DiscountProduct>(connection)
 }
+
+pub fn delete_discount_brand_query<C>(
+    connection: &mut C,
+    discount_brand: DiscountBrand,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::delete(
+        crate::schema::discount_brands::table
+            .find((discount_brand.discount_id, discount_brand.brand_id)),
+    )
+    .execute(connection)
+}
+
+pub fn delete_discount_category_query<C>(
+    connection: &mut C,
+    discount_category: DiscountCategory,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::delete(
+        crate::schema::discount_categories::table
+            .find((discount_category.discount_id, discount_category.category_id)),
+    )
+    .execute(connection)
+}
+
+pub fn delete_discount_product_query<C>(
+    connection: &mut C,
+    discount_product: DiscountProduct,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::delete(
+        crate::schema::discount_products::table
+            .find((discount_product.discount_id, discount_product.product_id)),
+    )
+    .execute(connection)
+}
@@ -1,13 +1,21 @@
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, patch, post, put, scope};
+use utoipa::OpenApi;
 
+use crate::api::pagination::DiscountPage;
 use crate::services::brand::handler::create_discount_brand;
+use crate::services::category::handler::create_discount_category;
 
 use super::handler::create_discount;
 use super::handler::create_discount_product;
 use super::handler::delete_discount;
 use super::handler::get_discount;
 use super::handler::list_discounts;
+use super::handler::patch_discount;
 use super::handler::update_discount;
+use super::model::{
+    relations::{DiscountBrand, DiscountCategory, DiscountProduct},
+    Discount, NewDiscount,
+};
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -17,7 +25,34 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", put().to(update_discount))
             .route("/{id}", get().to(get_discount))
             .route("/{id}", delete().to(delete_discount))
+            .route("/{id}", patch().to(patch_discount))
             .route("/product", post().to(create_discount_product))
-            .route("/brand", post().to(create_discount_brand)),
+            .route("/brand", post().to(create_discount_brand))
+            .route("/category", post().to(create_discount_category)),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_discount,
+        super::handler::list_discounts,
+        super::handler::update_discount,
+        super::handler::get_discount,
+        super::handler::delete_discount,
+        super::handler::patch_discount,
+        super::handler::create_discount_product,
+        crate::services::brand::handler::create_discount_brand,
+        crate::services::category::handler::create_discount_category
+    ),
+    components(schemas(
+        Discount,
+        NewDiscount,
+        DiscountBrand,
+        DiscountCategory,
+        DiscountProduct,
+        DiscountPage
+    )),
+    tags((name = "discount", description = "Discounts and the products, brands and categories they apply to"))
+)]
+pub struct DiscountApi;
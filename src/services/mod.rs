@@ -5,4 +5,5 @@ pub mod discount;
 pub mod category;
 pub mod cart;
 pub mod brand;
-pub mod stock;
\ No newline at end of file
+pub mod stock;
+pub mod webhook;
\ No newline at end of file
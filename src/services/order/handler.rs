@@ -1,8 +1,14 @@
-use actix_web::{web, Responder};
-use diesel::PgConnection;
+use actix_web::{web, HttpResponse, Responder};
+use diesel::{Connection, PgConnection};
+use serde_json::{Map, Value};
 
 use crate::{
-    error::DatabaseErrorWrapper,
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        validation::Valid,
+    },
+    error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
     postgres::{execute_query_with_args, ConnectionPool},
     ResourceIdentifierRequest,
 };
@@ -10,13 +16,28 @@ use crate::{
 use super::{
     model::{NewOrderLine, OrderLine},
     query::{
-        delete_orderline_query, insert_orderline_query, select_orderline_query, set_orderline_query,
+        delete_orderline_query, insert_orderline_query, select_orderline_for_update_query,
+        select_orderline_query, set_orderline_query,
     },
 };
 
+/// Adds a product to a cart, taking its quantity from the warehouse stock.
+#[utoipa::path(
+    post,
+    path = "/orderline",
+    tag = "orderline",
+    request_body = NewOrderLine,
+    responses(
+        (status = 200, description = "Number of order lines created", body = usize),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_orderline(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<NewOrderLine>,
+    payload: Valid<NewOrderLine>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match execute_query_with_args(
@@ -41,6 +62,18 @@ pub async fn create_orderline(
         Err(e) => e.into(),
     }
 }
+/// Gets an order line.
+#[utoipa::path(
+    get,
+    path = "/orderline/{id}",
+    tag = "orderline",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The order line", body = OrderLine),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_orderline(
     pool: web::Data<ConnectionPool>,
     payload: web::Path<ResourceIdentifierRequest>,
@@ -58,9 +91,23 @@ pub async fn get_orderline(
     }
 }
 
+/// Changes the product, warehouse or quantity of an order line.
+#[utoipa::path(
+    put,
+    path = "/orderline",
+    tag = "orderline",
+    request_body = OrderLine,
+    responses(
+        (status = 200, description = "The updated order line", body = OrderLine),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_orderline(
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
-    payload: web::Json<OrderLine>,
+    payload: Valid<OrderLine>,
 ) -> impl Responder {
     let params: OrderLine = payload.into_inner();
     match execute_query_with_args(
@@ -88,6 +135,58 @@ pub async fn update_orderline(
     }
 }
 
+/// Changes the fields of an order line given in a JSON Merge Patch. It
+/// can't be moved to another cart.
+#[utoipa::path(
+    patch,
+    path = "/orderline/{id}",
+    tag = "orderline",
+    params(ResourceIdentifierRequest),
+    request_body(
+        content = OrderLine,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change"
+    ),
+    responses(
+        (status = 200, description = "The updated order line", body = OrderLine),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_orderline(
+    pool: web::Data<ConnectionPool>,
+    path: web::Path<ResourceIdentifierRequest>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let orderline = select_orderline_for_update_query(conn, path.into_inner().id)?;
+                let orderline = merge_patch::apply(&orderline, &patch, &["id", "cart_id"])?;
+                Ok::<_, PatchError>(set_orderline_query(conn, orderline)?)
+            });
+            match patched {
+                Ok(updated_orderline) => HttpResponse::Ok().json(updated_orderline),
+                Err(e) => e.into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Removes an order line, returning its quantity to stock.
+#[utoipa::path(
+    delete,
+    path = "/orderline/{id}",
+    tag = "orderline",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "Number of order lines deleted", body = usize),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_orderline(
     pool: web::Data<ConnectionPool>,
     payload: web::Path<ResourceIdentifierRequest>,
@@ -3,6 +3,7 @@ use crate::services::product::model::Product;
 use crate::{services::cart::model::Cart, services::discount::model::Discount};
 use diesel::{AsChangeset, Associations, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 #[derive(
     Queryable,
     Selectable,
@@ -15,6 +16,7 @@ use serde::{Deserialize, Serialize};
     AsChangeset,
     Associations,
     Clone,
+    ToSchema,
 )]
 #[diesel(table_name = crate::schema::order_lines)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -28,6 +30,12 @@ pub struct OrderLine {
     pub quantity: i32,
 }
 
+impl Validate for OrderLine {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.at_least("quantity", self.quantity, 1);
+    }
+}
+
 #[derive(Debug, Queryable, Serialize, Deserialize, Clone)]
 pub struct OrderLineWithDiscounts {
     pub order_line: OrderLine,
@@ -35,7 +43,7 @@ pub struct OrderLineWithDiscounts {
     pub discounts: Vec<Discount>,
 }
 
-#[derive(Debug, Queryable, Serialize, Deserialize, Clone)]
+#[derive(Debug, Queryable, Serialize, Deserialize, Clone, ToSchema)]
 pub struct OrderLineInCart {
     pub id: i32,
     pub cart_id: i32,
@@ -86,6 +94,7 @@ fn default_warehouse_id() -> i32 {
     AsChangeset,
     Associations,
     Clone,
+    ToSchema,
 )]
 #[diesel(table_name = crate::schema::order_lines)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -98,6 +107,12 @@ pub struct NewOrderLine {
     pub warehouse_id: i32,
     pub quantity: i32,
 }
+
+impl Validate for NewOrderLine {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.at_least("quantity", self.quantity, 1);
+    }
+}
 impl NewOrderLine {
     pub fn new(cart_id: i32, product_id: i32, warehouse_id: Option<i32>, quantity: i32) -> Self {
         Self {
@@ -109,9 +124,21 @@ impl NewOrderLine {
     }
 }
 use crate::schema::warehouses;
+use crate::{
+    api::{pagination::SortField, validation::Validate},
+    error::ValidationErrors,
+};
 
 #[derive(
-    Queryable, Selectable, Identifiable, Debug, PartialEq, Serialize, Deserialize, Insertable,
+    Queryable,
+    Selectable,
+    Identifiable,
+    Debug,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    Insertable,
+    ToSchema,
 )]
 #[diesel(table_name = warehouses)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -120,8 +147,28 @@ pub struct Warehouse {
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Insertable)]
+/// Fields warehouses can be sorted by.
+pub const WAREHOUSE_SORT_FIELDS: &[SortField] = &[
+    SortField::integer("id", "warehouses.id"),
+    SortField::text("name", "warehouses.name"),
+];
+
+/// Narrows a warehouse list.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WarehouseFilter {
+    /// Text the name contains, ignoring case.
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, ToSchema)]
 #[diesel(table_name = warehouses)]
 pub struct NewWarehouse {
     pub name: String,
 }
+
+impl Validate for NewWarehouse {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.not_blank("name", &self.name);
+    }
+}
@@ -44,6 +44,17 @@ pub fn select_orderline_query(
         .filter(crate::schema::order_lines::id.eq(orderline_id))
         .first::<OrderLine>(connection)
 }
+/// Reads an order line and locks its row until the transaction ends.
+pub fn select_orderline_for_update_query(
+    connection: &mut PooledConnection,
+    orderline_id: i32,
+) -> Result<OrderLine, diesel::result::Error> {
+    crate::schema::order_lines::table
+        .select(OrderLine::as_select())
+        .filter(crate::schema::order_lines::id.eq(orderline_id))
+        .for_update()
+        .first::<OrderLine>(connection)
+}
 pub fn set_orderline_query(
     connection: &mut PooledConnection,
     updated_orderline: OrderLine,
@@ -60,10 +71,12 @@ pub fn delete_orderline_query(
 }
 pub fn load_orderlines_query(
     connection: &mut PooledConnection,
+    cart_ids: &[i32],
     discount_sort_fn: fn(&mut Vec<Discount>),
 ) -> Result<Vec<OrderLineInCart>, diesel::result::Error> {
     let order_lines_with_products: Vec<(OrderLine, Product)> = crate::schema::order_lines::table
         .inner_join(crate::schema::products::table)
+        .filter(crate::schema::order_lines::cart_id.eq_any(cart_ids))
         .select((
             crate::schema::order_lines::all_columns,
             crate::schema::products::all_columns,
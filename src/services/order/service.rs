@@ -1,9 +1,12 @@
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, patch, post, put, scope};
+use utoipa::OpenApi;
 
 use super::handler::create_orderline;
 use super::handler::delete_orderline;
 use super::handler::get_orderline;
+use super::handler::patch_orderline;
 use super::handler::update_orderline;
+use super::model::{NewOrderLine, OrderLine, OrderLineInCart};
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -11,6 +14,21 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", post().to(create_orderline))
             .route("", put().to(update_orderline))
             .route("/{id}", get().to(get_orderline))
-            .route("/{id}", delete().to(delete_orderline)),
+            .route("/{id}", delete().to(delete_orderline))
+            .route("/{id}", patch().to(patch_orderline)),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_orderline,
+        super::handler::update_orderline,
+        super::handler::get_orderline,
+        super::handler::delete_orderline,
+        super::handler::patch_orderline
+    ),
+    components(schemas(OrderLine, NewOrderLine, OrderLineInCart)),
+    tags((name = "orderline", description = "Products added to carts"))
+)]
+pub struct OrderApi;
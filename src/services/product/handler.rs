@@ -1,47 +1,117 @@
 use crate::{
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        pagination::{Page, PageParams},
+        validation::Valid,
+    },
+    cache::{keys, Cache},
+    error::{ConnectionPoolErrorWrapper, DatabaseErrorWrapper},
     postgres::{execute_query, execute_query_with_args, ConnectionPool},
     ResourceIdentifierRequest,
 };
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use diesel::Connection;
+use serde_json::{Map, Value};
 
 use super::{
-    model::{NewProduct, Product},
+    model::{NewProduct, Product, ProductFilter, ProductSearchParams, PRODUCT_SORT_FIELDS},
     query::{
         delete_product_query, inner_join_product_and_stock_query, insert_product_query_pooled_conn,
         left_join_products_with_discounts, load_products_query,
-        load_products_with_attributes_and_discounts_query, select_product_query, set_product_query,
+        load_products_with_attributes_and_discounts_query, save_product_query,
+        search_products_query, select_product_for_update_query, select_product_query,
+        set_product_query,
     },
 };
 
+/// Deletes a product.
+#[utoipa::path(
+    delete,
+    path = "/product/{id}",
+    tag = "product",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The product no longer exists"),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_product(
     pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
     path: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
     let params = path.into_inner();
     match execute_query_with_args(pool, |conn, id| delete_product_query(conn, id), params.id).await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            cache
+                .invalidate(&[keys::products(), keys::product(params.id)])
+                .await;
+            HttpResponse::Ok().finish()
+        }
         Err(e) => e.into(),
     }
 }
 
+/// Gets a product.
+#[utoipa::path(
+    get,
+    path = "/product/{id}",
+    tag = "product",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The product", body = Product),
+        (status = 304, response = responses::NotModified),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_product(
+    request: HttpRequest,
     pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
     path: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
     let params = path.into_inner();
-    match execute_query_with_args(pool, |conn, id| select_product_query(id, conn), params.id).await
-    {
-        Ok(product) => product,
-        Err(e) => e.into(),
-    }
+    cache
+        .serve(&request, &keys::product(params.id), async {
+            match execute_query_with_args(
+                pool,
+                |conn, id| select_product_query(id, conn),
+                params.id,
+            )
+            .await
+            {
+                Ok(product) => product,
+                Err(e) => e.into(),
+            }
+        })
+        .await
 }
 
+/// Updates a product.
+#[utoipa::path(
+    put,
+    path = "/product",
+    tag = "product",
+    request_body = Product,
+    responses(
+        (status = 200, description = "The updated product", body = Product),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 404, response = responses::NotFound),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_product(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<Product>,
+    cache: web::Data<Cache>,
+    payload: Valid<Product>,
 ) -> impl Responder {
     let params = payload.into_inner();
+    let product_id = params.id;
     match execute_query_with_args(
         pool,
         |conn, params| {
@@ -55,6 +125,7 @@ pub async fn update_product(
                     price: params.price,
                     tax_rate: params.tax_rate,
                     id: params.id,
+                    sku: params.sku,
                 },
                 conn,
             )
@@ -63,13 +134,82 @@ pub async fn update_product(
     )
     .await
     {
-        Ok(updated_product) => updated_product,
+        Ok(updated_product) => {
+            cache
+                .invalidate(&[keys::products(), keys::product(product_id)])
+                .await;
+            updated_product
+        }
         Err(e) => e.into(),
     }
 }
+
+/// Changes the fields of a product given in a JSON Merge Patch.
+#[utoipa::path(
+    patch,
+    path = "/product/{id}",
+    tag = "product",
+    params(ResourceIdentifierRequest),
+    request_body(
+        content = Product,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change"
+    ),
+    responses(
+        (status = 200, description = "The updated product", body = Product),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 409, response = responses::Conflict),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_product(
+    pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
+    path: web::Path<ResourceIdentifierRequest>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    let product_id = path.into_inner().id;
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let product = select_product_for_update_query(product_id, conn)?;
+                let product = merge_patch::apply(&product, &patch, &["id"])?;
+                Ok::<_, PatchError>(save_product_query(product, conn)?)
+            });
+            match patched {
+                Ok(updated_product) => {
+                    cache
+                        .invalidate(&[keys::products(), keys::product(product_id)])
+                        .await;
+                    HttpResponse::Ok().json(updated_product)
+                }
+                Err(e) => e.into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Creates a product.
+#[utoipa::path(
+    post,
+    path = "/product",
+    tag = "product",
+    request_body = NewProduct,
+    responses(
+        (status = 200, description = "The created product", body = Product),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_product(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<NewProduct>,
+    cache: web::Data<Cache>,
+    payload: Valid<NewProduct>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match execute_query_with_args(
@@ -84,6 +224,7 @@ pub async fn create_product(
                     brand_id: params.brand_id,
                     price: params.price,
                     tax_rate: params.tax_rate,
+                    sku: params.sku,
                 },
                 conn,
             )
@@ -92,15 +233,42 @@ pub async fn create_product(
     )
     .await
     {
-        Ok(updated_product) => updated_product,
+        Ok(created_product) => {
+            cache.invalidate(&[keys::products()]).await;
+            created_product
+        }
         Err(e) => e.into(),
     }
 }
 
+/// Lists products without their attributes, stock or discounts, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/product/raw",
+    tag = "product",
+    params(PageParams, ProductFilter),
+    responses(
+        (status = 200, description = "A page of products", body = RawProductPage),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_products(
+    request: HttpRequest,
     pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<diesel::PgConnection>>>,
+    params: web::Query<PageParams>,
+    filter: web::Query<ProductFilter>,
 ) -> impl Responder {
-    match execute_query(pool, load_products_query).await {
+    let page = match params.resolve(PRODUCT_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return HttpResponse::from(e),
+    };
+    match execute_query(pool, |conn| {
+        load_products_query(conn, &filter, &page)
+            .map(|(products, total)| page.page(&request, products, total, |product| product))
+    })
+    .await
+    {
         Ok(products) => products,
         Err(e) => e.into(),
     }
@@ -112,14 +280,91 @@ pub async fn list_products_with_discount(pool: web::Data<ConnectionPool>) -> imp
         Err(e) => e.into(),
     }
 }
-pub async fn list_full_products(pool: web::Data<ConnectionPool>) -> impl Responder {
-    match crate::postgres::execute_query(pool, load_products_with_attributes_and_discounts_query)
+/// Lists products with their attributes, stock and discounts, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/product",
+    tag = "product",
+    params(PageParams, ProductFilter),
+    responses(
+        (status = 200, description = "A page of products", body = ProductPage),
+        (status = 304, response = responses::NotModified),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn list_full_products(
+    request: HttpRequest,
+    pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
+    params: web::Query<PageParams>,
+    filter: web::Query<ProductFilter>,
+) -> impl Responder {
+    let page = match params.resolve(PRODUCT_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return HttpResponse::from(e),
+    };
+    let key = keys::products().with_query(request.query_string());
+    cache
+        .serve(&request, &key, async {
+            match crate::postgres::execute_query(pool, |conn| {
+                let (products, total) = load_products_query(conn, &filter, &page)?;
+                let products = load_products_with_attributes_and_discounts_query(conn, products)?;
+                Ok::<_, DatabaseErrorWrapper>(
+                    page.page(&request, products, total, |product| &product.product),
+                )
+            })
+            .await
+            {
+                Ok(products) => products,
+                Err(e) => e.into(),
+            }
+        })
         .await
+}
+/// Searches products by name, brand, category and attribute values, best
+/// matches first.
+#[utoipa::path(
+    get,
+    path = "/product/search",
+    tag = "product",
+    params(ProductSearchParams),
+    responses(
+        (status = 200, description = "A page of matching products", body = ProductSearchPage),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn search_products(
+    request: HttpRequest,
+    pool: web::Data<ConnectionPool>,
+    params: web::Query<ProductSearchParams>,
+) -> impl Responder {
+    let search = match params.resolve() {
+        Ok(search) => search,
+        Err(e) => return HttpResponse::from(e),
+    };
+    match execute_query(pool, |conn| {
+        search_products_query(conn, &search).map(|(hits, total)| {
+            Page::by_offset(&request, hits, total, search.limit, search.offset)
+        })
+    })
+    .await
     {
-        Ok(products_with_discount) => products_with_discount,
+        Ok(hits) => hits,
         Err(e) => e.into(),
     }
 }
+/// Lists products, each paired with its stock in one warehouse.
+#[utoipa::path(
+    get,
+    path = "/product/with_stock",
+    tag = "product",
+    responses(
+        (status = 200, description = "Products in stock", body = [(Product, StockQuantity)]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_products_with_stock(pool: web::Data<ConnectionPool>) -> impl Responder {
     match crate::postgres::execute_query(pool, inner_join_product_and_stock_query).await {
         Ok(products_with_stock) => products_with_stock,
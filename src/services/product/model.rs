@@ -1,8 +1,18 @@
 use crate::schema::products;
 use crate::services::brand::model::Brand;
 use crate::services::{category::model::Category, discount::model::Discount};
-use diesel::{AsChangeset, Associations, Identifiable, Insertable, Queryable, Selectable};
+use crate::{
+    api::{
+        pagination::{SortField, DEFAULT_LIMIT, MAX_LIMIT},
+        validation::Validate,
+    },
+    error::ValidationErrors,
+};
+use diesel::{
+    AsChangeset, Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable,
+};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Queryable, Debug, Clone)]
 #[diesel(table_name = attributes)]
@@ -22,7 +32,7 @@ pub struct ProductAttribute {
     pub attribute_id: i32,
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct DefaultAttributes {
     pub size: Option<String>,
     pub color: Option<String>,
@@ -32,7 +42,7 @@ pub struct DefaultAttributes {
     pub height: Option<i32>,
 }
 
-#[derive(Debug, Queryable, Serialize, Deserialize)]
+#[derive(Debug, Queryable, Serialize, Deserialize, ToSchema)]
 pub struct ProductWithAttributes {
     pub product: Product,
     pub attributes: DefaultAttributes,
@@ -50,6 +60,7 @@ pub struct ProductWithAttributes {
     Insertable,
     AsChangeset,
     Clone,
+    ToSchema,
 )]
 #[diesel(table_name = products)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -63,10 +74,24 @@ pub struct Product {
     pub brand_id: Option<i32>,
     pub price: i32,
     pub tax_rate: i32,
+    /// Key assigned by the producer of the product, e.g. a feed's SKU.
+    #[serde(default)]
+    pub sku: Option<String>,
 }
 
+impl Validate for Product {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.not_blank("name", &self.name);
+        errors.at_least("price", self.price, 0);
+        errors.at_least("tax_rate", self.tax_rate, 0);
+        errors.at_most("tax_rate", self.tax_rate, 100);
+        if let Some(sku) = &self.sku {
+            errors.not_blank("sku", sku);
+        }
+    }
+}
 
-#[derive(Insertable, Serialize, Deserialize, Clone, Debug)]
+#[derive(Insertable, Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = products)]
 pub struct NewProduct {
@@ -76,6 +101,29 @@ pub struct NewProduct {
     pub brand_id: Option<i32>,
     pub price: Option<i32>,
     pub tax_rate: Option<i32>,
+    #[serde(default)]
+    pub sku: Option<String>,
+}
+
+impl Validate for NewProduct {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.required("name", &self.name);
+        errors.required("price", &self.price);
+        errors.required("tax_rate", &self.tax_rate);
+        if let Some(name) = &self.name {
+            errors.not_blank("name", name);
+        }
+        if let Some(price) = self.price {
+            errors.at_least("price", price, 0);
+        }
+        if let Some(tax_rate) = self.tax_rate {
+            errors.at_least("tax_rate", tax_rate, 0);
+            errors.at_most("tax_rate", tax_rate, 100);
+        }
+        if let Some(sku) = &self.sku {
+            errors.not_blank("sku", sku);
+        }
+    }
 }
 
 impl NewProduct {
@@ -95,6 +143,58 @@ impl NewProduct {
             brand_id: brand_id.or(None),
             price: price.or(Some(0)),
             tax_rate: tax_rate.or(Some(0)),
+            sku: None,
+        }
+    }
+}
+
+/// Fields of a product that may be changed individually. Fields left out are
+/// not touched.
+#[derive(AsChangeset, Serialize, Deserialize, Clone, Debug, Default)]
+#[diesel(table_name = products)]
+pub struct ProductChanges {
+    pub name: Option<String>,
+    pub in_stock: Option<bool>,
+    pub category_id: Option<i32>,
+    pub brand_id: Option<i32>,
+    pub price: Option<i32>,
+    pub tax_rate: Option<i32>,
+}
+
+impl ProductChanges {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.in_stock.is_none()
+            && self.category_id.is_none()
+            && self.brand_id.is_none()
+            && self.price.is_none()
+            && self.tax_rate.is_none()
+    }
+}
+
+/// Creates the product with the given SKU, or applies `changes` to it if it
+/// already exists.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpsertProduct {
+    pub sku: String,
+    #[serde(flatten)]
+    pub changes: ProductChanges,
+}
+
+impl UpsertProduct {
+    /// The product inserted when the SKU is new, with defaults for missing fields.
+    pub fn new_product(&self) -> NewProduct {
+        let changes = self.changes.clone();
+        NewProduct {
+            sku: Some(self.sku.clone()),
+            ..NewProduct::new(
+                changes.name,
+                changes.in_stock,
+                changes.category_id,
+                changes.brand_id,
+                changes.price,
+                changes.tax_rate,
+            )
         }
     }
 }
@@ -190,6 +290,7 @@ impl ProductBuilder {
             brand_id: None,
             price: *self.price.as_ref().unwrap_or(&0),
             tax_rate: *self.tax_rate.as_ref().unwrap_or(&0),
+            sku: None,
         }
     }
 }
@@ -205,3 +306,127 @@ pub struct ProductWithDiscount {
     pub product: Product,
     pub discounts: Vec<Discount>,
 }
+
+/// Fields products can be sorted by.
+pub const PRODUCT_SORT_FIELDS: &[SortField] = &[
+    SortField::integer("id", "products.id"),
+    SortField::text("name", "products.name"),
+    SortField::integer("price", "products.price"),
+];
+
+/// Narrows a product list. Products must match every filter given.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProductFilter {
+    pub brand_id: Option<i32>,
+    pub category_id: Option<i32>,
+    /// Lowest price, inclusive.
+    pub min_price: Option<i32>,
+    /// Highest price, inclusive.
+    pub max_price: Option<i32>,
+    pub in_stock: Option<bool>,
+}
+
+/// Parameters of a product search.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProductSearchParams {
+    /// Words to look for in the name, brand, category and attribute values of
+    /// products. The last letters of a word may be left out.
+    pub q: Option<String>,
+    /// Results per page, from 1 to 200. Defaults to 50.
+    pub limit: Option<i32>,
+    /// Results to skip.
+    pub offset: Option<i32>,
+}
+
+/// [`ProductSearchParams`] that were checked, with the words as a prefix
+/// `tsquery`.
+#[derive(Debug, Clone)]
+pub struct ProductSearch {
+    pub query: String,
+    pub limit: i32,
+    pub offset: i32,
+}
+
+/// Longest search text accepted.
+pub const MAX_SEARCH_LENGTH: usize = 200;
+
+impl ProductSearchParams {
+    pub fn resolve(&self) -> Result<ProductSearch, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        errors.at_least("limit", limit, 1);
+        errors.at_most("limit", limit, MAX_LIMIT);
+        let offset = self.offset.unwrap_or(0);
+        errors.at_least("offset", offset, 0);
+
+        let q = self.q.as_deref().unwrap_or_default();
+        let words: Vec<String> = q
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| format!("{}:*", word.to_lowercase()))
+            .collect();
+        errors.required("q", &self.q);
+        if q.chars().count() > MAX_SEARCH_LENGTH {
+            errors.add(
+                "q",
+                &format!("must be at most {} characters", MAX_SEARCH_LENGTH),
+            );
+        } else if self.q.is_some() && words.is_empty() {
+            errors.add("q", "must contain a letter or digit");
+        }
+
+        if errors.is_empty() {
+            Ok(ProductSearch {
+                query: words.join(" & "),
+                limit,
+                offset,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// A product found by a search, ranked by how well it matched the search.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProductSearchHit {
+    pub product: Product,
+    /// Higher for products matching more words, and matching them in the
+    /// name rather than in attribute values.
+    pub rank: f32,
+    pub highlights: ProductHighlights,
+}
+
+/// The fields of a product that matched a search, with the matching words
+/// wrapped in `<mark>` tags. Fields that didn't match are absent.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ProductHighlights {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brand: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Attribute values, separated by spaces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<String>,
+}
+
+/// A row of a product search, before the product is loaded.
+#[derive(QueryableByName, Debug)]
+pub struct ProductSearchRow {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub id: i32,
+    #[diesel(sql_type = diesel::sql_types::Float4)]
+    pub rank: f32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub name: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub brand: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub category: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub attributes: Option<String>,
+}
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
 
+use crate::api::pagination::PageRequest;
 use crate::error::DatabaseErrorWrapper;
 use crate::postgres::PooledConnection;
 
 use crate::services::discount::model::Discount;
 use crate::services::stock::model::StockQuantity;
 
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::PgConnection;
 
 use super::model::{
-    DefaultAttributes, NewProduct, Product, ProductWithAttributes, ProductWithDiscount,
+    DefaultAttributes, NewProduct, Product, ProductFilter, ProductHighlights, ProductSearch,
+    ProductSearchHit, ProductSearchRow, ProductWithAttributes, ProductWithDiscount, UpsertProduct,
 };
 use crate::services::product::model::Attribute;
 
@@ -56,6 +59,20 @@ pub fn select_product_query(
         .map_err(DatabaseErrorWrapper)
 }
 
+/// Reads a product and locks its row until the transaction ends.
+pub fn select_product_for_update_query(
+    product_id: i32,
+    connection: &mut PooledConnection,
+) -> Result<Product, DatabaseErrorWrapper> {
+    use crate::schema::products::dsl::*;
+
+    products
+        .filter(id.eq(product_id))
+        .for_update()
+        .first::<Product>(connection)
+        .map_err(DatabaseErrorWrapper)
+}
+
 pub fn inner_join_product_and_stock_query(
     connection: &mut PooledConnection,
 ) -> Result<Vec<(Product, StockQuantity)>, DatabaseErrorWrapper> {
@@ -63,7 +80,7 @@ pub fn inner_join_product_and_stock_query(
 
     products::table
         .inner_join(stock_quantities::table)
-        .select((products::all_columns, stock_quantities::all_columns))
+        .select((products::all_columns, StockQuantity::as_select()))
         .load::<(Product, StockQuantity)>(connection)
         .map_err(DatabaseErrorWrapper)
 }
@@ -82,6 +99,28 @@ pub fn set_product_query(
     set_product_query_internal(updated_product, connection)
 }
 
+/// Writes every column of a product. Unlike [`set_product_query`], `None`
+/// clears a column.
+pub fn save_product_query(
+    product: Product,
+    connection: &mut PgConnection,
+) -> Result<Product, DatabaseErrorWrapper> {
+    use crate::schema::products::dsl::*;
+
+    diesel::update(products.find(product.id))
+        .set((
+            name.eq(product.name),
+            in_stock.eq(product.in_stock),
+            category_id.eq(product.category_id),
+            brand_id.eq(product.brand_id),
+            price.eq(product.price),
+            tax_rate.eq(product.tax_rate),
+            sku.eq(product.sku),
+        ))
+        .get_result::<Product>(connection)
+        .map_err(DatabaseErrorWrapper)
+}
+
 pub fn set_product_query_internal<C>(
     updated_product: Product,
     connection: &mut C,
@@ -97,10 +136,52 @@ where
         .map_err(DatabaseErrorWrapper)
 }
 
+/// Inserts the product or updates the fields given in `upsert.changes` on the
+/// product that already has its SKU.
+pub fn upsert_product_by_sku_query<C>(
+    upsert: UpsertProduct,
+    connection: &mut C,
+) -> Result<Product, DatabaseErrorWrapper>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    use crate::schema::products;
+
+    let insert = diesel::insert_into(products::table)
+        .values(upsert.new_product())
+        .on_conflict(products::sku);
+
+    if upsert.changes.is_empty() {
+        // An empty changeset can't be used in DO UPDATE, so leave an existing
+        // product as it is and return it.
+        insert.do_nothing().execute(connection)?;
+        products::table
+            .filter(products::sku.eq(&upsert.sku))
+            .first::<Product>(connection)
+            .map_err(DatabaseErrorWrapper)
+    } else {
+        insert
+            .do_update()
+            .set(&upsert.changes)
+            .get_result::<Product>(connection)
+            .map_err(DatabaseErrorWrapper)
+    }
+}
+
 pub fn delete_product_query(
     connection: &mut PooledConnection,
     product_id: i32,
 ) -> Result<usize, DatabaseErrorWrapper> {
+    delete_product_query_internal(connection, product_id)
+}
+
+pub fn delete_product_query_internal<C>(
+    connection: &mut C,
+    product_id: i32,
+) -> Result<usize, DatabaseErrorWrapper>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     use crate::schema::products::dsl::products;
 
     diesel::delete(products.find(product_id))
@@ -108,14 +189,131 @@ pub fn delete_product_query(
         .map_err(DatabaseErrorWrapper)
 }
 
+fn filtered_products_query(
+    filter: &ProductFilter,
+) -> crate::schema::products::BoxedQuery<'static, Pg> {
+    use crate::schema::products;
+
+    let mut query = products::table.into_boxed();
+    if let Some(brand_id) = filter.brand_id {
+        query = query.filter(products::brand_id.eq(brand_id));
+    }
+    if let Some(category_id) = filter.category_id {
+        query = query.filter(products::category_id.eq(category_id));
+    }
+    if let Some(min_price) = filter.min_price {
+        query = query.filter(products::price.ge(min_price));
+    }
+    if let Some(max_price) = filter.max_price {
+        query = query.filter(products::price.le(max_price));
+    }
+    if let Some(in_stock) = filter.in_stock {
+        query = query.filter(products::in_stock.eq(in_stock));
+    }
+    query
+}
+
+/// A page of the products matching `filter`, and how many match.
 pub fn load_products_query(
     connection: &mut PooledConnection,
-) -> Result<Vec<Product>, DatabaseErrorWrapper> {
-    use crate::schema::products::dsl::products;
+    filter: &ProductFilter,
+    page: &PageRequest,
+) -> Result<(Vec<Product>, i64), DatabaseErrorWrapper> {
+    let total = filtered_products_query(filter)
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(DatabaseErrorWrapper)?;
+    let mut query = filtered_products_query(filter)
+        .order(page.order())
+        .limit(page.load_limit())
+        .offset(page.offset());
+    if let Some(seek) = page.seek() {
+        query = query.filter(seek);
+    }
+    let products = query
+        .load::<Product>(connection)
+        .map_err(DatabaseErrorWrapper)?;
+    Ok((products, total))
+}
 
-    products
+/// Page of a product search: the ids of the matching products, best matches
+/// first, with each matching field highlighted.
+const SEARCH_PRODUCTS_SQL: &str = "
+    WITH search AS (SELECT to_tsquery('simple', $1) AS query)
+    SELECT p.id, ts_rank(s.document, search.query) AS rank,
+        product_search_highlight(p.name, search.query) AS name,
+        product_search_highlight(b.name, search.query) AS brand,
+        product_search_highlight(c.name, search.query) AS category,
+        product_search_highlight(
+            (SELECT string_agg(a.value, ' ' ORDER BY a.id)
+             FROM product_attributes pa
+             JOIN attributes a ON a.id = pa.attribute_id
+             WHERE pa.product_id = p.id),
+            search.query
+        ) AS attributes
+    FROM product_search s
+    CROSS JOIN search
+    JOIN products p ON p.id = s.product_id
+    LEFT JOIN brands b ON b.id = p.brand_id
+    LEFT JOIN categories c ON c.id = p.category_id
+    WHERE s.document @@ search.query
+    ORDER BY rank DESC, p.id
+    LIMIT $2 OFFSET $3";
+
+/// Products matching `search` and how many match across every page.
+pub fn search_products_query(
+    connection: &mut PooledConnection,
+    search: &ProductSearch,
+) -> Result<(Vec<ProductSearchHit>, i64), DatabaseErrorWrapper> {
+    use crate::schema::products;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{BigInt, Bool, Text};
+
+    let total = products::table
+        .filter(
+            sql::<Bool>(
+                "products.id IN (SELECT product_id FROM product_search \
+                 WHERE document @@ to_tsquery('simple', ",
+            )
+            .bind::<Text, _>(&search.query)
+            .sql("))"),
+        )
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(DatabaseErrorWrapper)?;
+
+    let rows = diesel::sql_query(SEARCH_PRODUCTS_SQL)
+        .bind::<Text, _>(&search.query)
+        .bind::<BigInt, _>(i64::from(search.limit) + 1)
+        .bind::<BigInt, _>(i64::from(search.offset))
+        .load::<ProductSearchRow>(connection)
+        .map_err(DatabaseErrorWrapper)?;
+
+    let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+    let mut products: HashMap<i32, Product> = products::table
+        .filter(products::id.eq_any(&ids))
         .load::<Product>(connection)
-        .map_err(DatabaseErrorWrapper)
+        .map_err(DatabaseErrorWrapper)?
+        .into_iter()
+        .map(|product| (product.id, product))
+        .collect();
+
+    let hits = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(ProductSearchHit {
+                product: products.remove(&row.id)?,
+                rank: row.rank,
+                highlights: ProductHighlights {
+                    name: row.name,
+                    brand: row.brand,
+                    category: row.category,
+                    attributes: row.attributes,
+                },
+            })
+        })
+        .collect();
+    Ok((hits, total))
 }
 
 pub fn load_products_with_attributes_query(
@@ -251,8 +449,11 @@ fn products_with_attributes(
     }
     Ok(products_map)
 }
+/// `page` with the attributes, stock and discounts of each product, in the
+/// same order.
 pub fn load_products_with_attributes_and_discounts_query(
     connection: &mut PooledConnection,
+    page: Vec<Product>,
 ) -> Result<Vec<ProductWithAttributes>, DatabaseErrorWrapper> {
     use crate::schema::{
         attributes, discount_brands, discount_categories, discount_products, discounts,
@@ -260,6 +461,8 @@ pub fn load_products_with_attributes_and_discounts_query(
     };
     use diesel::prelude::*;
 
+    let ids: Vec<i32> = page.iter().map(|product| product.id).collect();
+
     // Fetch products with discounts from discount_products
     let product_discount_results = products::table
         .left_join(discount_products::table.on(products::id.eq(discount_products::product_id)))
@@ -268,6 +471,7 @@ pub fn load_products_with_attributes_and_discounts_query(
                 .nullable()
                 .eq(discounts::id.nullable())),
         )
+        .filter(products::id.eq_any(&ids))
         .select((products::all_columns, discounts::all_columns.nullable()))
         .load::<(Product, Option<Discount>)>(connection)
         .map_err(DatabaseErrorWrapper)?;
@@ -282,6 +486,7 @@ pub fn load_products_with_attributes_and_discounts_query(
                 .nullable()
                 .eq(discounts::id.nullable())),
         )
+        .filter(products::id.eq_any(&ids))
         .select((products::all_columns, discounts::all_columns.nullable()))
         .load::<(Product, Option<Discount>)>(connection)
         .map_err(DatabaseErrorWrapper)?;
@@ -297,6 +502,7 @@ pub fn load_products_with_attributes_and_discounts_query(
                 .nullable()
                 .eq(discounts::id.nullable())),
         )
+        .filter(products::id.eq_any(&ids))
         .select((products::all_columns, discounts::all_columns.nullable()))
         .load::<(Product, Option<Discount>)>(connection)
         .map_err(DatabaseErrorWrapper)?;
@@ -329,6 +535,7 @@ pub fn load_products_with_attributes_and_discounts_query(
     let product_with_attrs = products::table
         .left_join(product_attributes::table.on(products::id.eq(product_attributes::product_id)))
         .left_join(attributes::table.on(product_attributes::attribute_id.eq(attributes::id)))
+        .filter(products::id.eq_any(&ids))
         .select((products::all_columns, attributes::all_columns.nullable()))
         .load::<(Product, Option<Attribute>)>(connection)
         .map_err(DatabaseErrorWrapper)?;
@@ -360,6 +567,7 @@ pub fn load_products_with_attributes_and_discounts_query(
     // Fetch products with stock quantities
     let product_stock_quantities = products::table
         .inner_join(stock_quantities::table.on(products::id.eq(stock_quantities::product_id)))
+        .filter(products::id.eq_any(&ids))
         .select((products::id, stock_quantities::quantity))
         .load::<(i32, i32)>(connection)
         .map_err(DatabaseErrorWrapper)?;
@@ -379,7 +587,10 @@ pub fn load_products_with_attributes_and_discounts_query(
         }
     }
 
-    Ok(products_map.into_iter().map(|(_, v)| v).collect())
+    Ok(page
+        .into_iter()
+        .filter_map(|product| products_map.remove(&product.id))
+        .collect())
 }
 pub fn left_join_products_with_discounts(
     connection: &mut PooledConnection,
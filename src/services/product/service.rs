@@ -4,9 +4,17 @@ use super::handler::get_product;
 use super::handler::list_full_products;
 use super::handler::list_products;
 use super::handler::list_products_with_stock;
+use super::handler::patch_product;
+use super::handler::search_products;
 use super::handler::update_product;
+use super::model::{
+    DefaultAttributes, NewProduct, Product, ProductHighlights, ProductSearchHit,
+    ProductWithAttributes,
+};
+use crate::api::pagination::{ProductPage, ProductSearchPage, RawProductPage};
 
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, patch, post, put, scope};
+use utoipa::OpenApi;
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -14,9 +22,39 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", post().to(create_product))
             .route("", get().to(list_full_products))
             .route("", put().to(update_product))
+            .route("/with_stock", get().to(list_products_with_stock))
+            .route("/raw", get().to(list_products))
+            .route("/search", get().to(search_products))
             .route("/{id}", get().to(get_product))
             .route("/{id}", delete().to(delete_product))
-            .route("/with_stock", get().to(list_products_with_stock))
-            .route("/raw", get().to(list_products)),
+            .route("/{id}", patch().to(patch_product)),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_product,
+        super::handler::list_full_products,
+        super::handler::update_product,
+        super::handler::list_products_with_stock,
+        super::handler::list_products,
+        super::handler::get_product,
+        super::handler::delete_product,
+        super::handler::patch_product,
+        super::handler::search_products
+    ),
+    components(schemas(
+        Product,
+        NewProduct,
+        ProductWithAttributes,
+        DefaultAttributes,
+        ProductPage,
+        RawProductPage,
+        ProductSearchHit,
+        ProductHighlights,
+        ProductSearchPage
+    )),
+    tags((name = "product", description = "Products and their attributes"))
+)]
+pub struct ProductApi;
@@ -1,23 +1,49 @@
 use crate::{
+    api::{
+        merge_patch::{self, PatchError},
+        openapi::responses,
+        pagination::PageParams,
+        validation::Valid,
+    },
+    cache::{keys, Cache},
+    error::ConnectionPoolErrorWrapper,
     postgres::{execute_query, execute_query_with_args, ConnectionPool},
-    services::order::model::NewWarehouse,
+    services::order::model::{NewWarehouse, WarehouseFilter, WAREHOUSE_SORT_FIELDS},
     ResourceIdentifierRequest,
 };
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use diesel::Connection;
+use serde_json::{Map, Value};
 
 use super::{
-    model::{NewStockQuantity, StockQuantity},
+    model::{NewStockQuantity, StockQuantity, StockQuantityPath},
     query::{
         delete_stock_quantity_from_product_query, delete_warehouse_query,
-        insert_stock_quantity_query, insert_warehouse_query, load_warehouses_query,
-        select_stock_quantity_for_product, select_stock_quantity_for_warehouse_query,
+        insert_stock_quantity_query, insert_warehouse_query, load_stock_quantity_query,
+        load_warehouses_query, select_stock_quantity_for_product,
+        select_stock_quantity_for_update_query, select_stock_quantity_for_warehouse_query,
         select_warehouse_query, set_stock_quantity_for_product,
     },
 };
 
+/// Sets the stock of a product in a warehouse.
+#[utoipa::path(
+    post,
+    path = "/stock",
+    tag = "stock",
+    request_body = NewStockQuantity,
+    responses(
+        (status = 200, description = "The stock was created"),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 409, response = responses::Conflict),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_stock_quantity(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<NewStockQuantity>,
+    cache: web::Data<Cache>,
+    payload: Valid<NewStockQuantity>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match execute_query_with_args(
@@ -27,13 +53,28 @@ pub async fn create_stock_quantity(
     )
     .await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            cache.invalidate(&[keys::products()]).await;
+            HttpResponse::Ok().finish()
+        }
         Err(e) => e.into(),
     }
 }
 
+/// Deletes the stock of a product in every warehouse.
+#[utoipa::path(
+    delete,
+    path = "/stock/{id}",
+    tag = "stock",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The product has no stock left"),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_stock_quantity_from_product(
     pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
     path: web::Path<ResourceIdentifierRequest>,
 ) -> impl Responder {
     let params = path.into_inner();
@@ -44,17 +85,41 @@ pub async fn delete_stock_quantity_from_product(
     )
     .await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            cache.invalidate(&[keys::products()]).await;
+            HttpResponse::Ok().finish()
+        }
         Err(e) => e.into(),
     }
 }
+/// Lists the stock of every product in every warehouse.
+#[utoipa::path(
+    get,
+    path = "/stock",
+    tag = "stock",
+    responses(
+        (status = 200, description = "Every stock quantity", body = [StockQuantity]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn list_stock_quantity(pool: web::Data<ConnectionPool>) -> impl Responder {
-    match execute_query(pool, load_warehouses_query).await {
+    match execute_query(pool, load_stock_quantity_query).await {
         Ok(stock_quantities) => stock_quantities,
         Err(e) => e.into(),
     }
 }
 
+/// Gets the stock of a product in each warehouse.
+#[utoipa::path(
+    get,
+    path = "/stock/{id}",
+    tag = "stock",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "Stock of the product", body = [StockQuantity]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_stock_quantity_for_product(
     pool: web::Data<ConnectionPool>,
     path: web::Path<ResourceIdentifierRequest>,
@@ -72,9 +137,24 @@ pub async fn get_stock_quantity_for_product(
     }
 }
 
+/// Changes the stock of a product in a warehouse.
+#[utoipa::path(
+    put,
+    path = "/stock",
+    tag = "stock",
+    request_body = StockQuantity,
+    responses(
+        (status = 200, description = "The updated stock", body = StockQuantity),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn update_stock_quantity_for_product(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<StockQuantity>,
+    cache: web::Data<Cache>,
+    payload: Valid<StockQuantity>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match execute_query_with_args(
@@ -84,14 +164,79 @@ pub async fn update_stock_quantity_for_product(
     )
     .await
     {
-        Ok(updated_product) => updated_product,
+        Ok(updated_product) => {
+            cache.invalidate(&[keys::products()]).await;
+            updated_product
+        }
         Err(e) => e.into(),
     }
 }
 
+/// Changes the stock of a product in a warehouse given in a JSON Merge Patch.
+#[utoipa::path(
+    patch,
+    path = "/stock/{product_id}/{warehouse_id}",
+    tag = "stock",
+    params(StockQuantityPath),
+    request_body(
+        content = StockQuantity,
+        content_type = "application/merge-patch+json",
+        description = "Fields to change"
+    ),
+    responses(
+        (status = 200, description = "The updated stock", body = StockQuantity),
+        (status = 400, response = responses::BadRequest),
+        (status = 404, response = responses::NotFound),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn patch_stock_quantity(
+    pool: web::Data<ConnectionPool>,
+    cache: web::Data<Cache>,
+    path: web::Path<StockQuantityPath>,
+    patch: web::Json<Map<String, Value>>,
+) -> HttpResponse {
+    let path = path.into_inner();
+    match pool.get() {
+        Ok(mut conn) => {
+            let patched = conn.transaction(|conn| {
+                let stock = select_stock_quantity_for_update_query(
+                    conn,
+                    path.product_id,
+                    path.warehouse_id,
+                )?;
+                let stock = merge_patch::apply(&stock, &patch, &["product_id", "warehouse_id"])?;
+                Ok::<_, PatchError>(set_stock_quantity_for_product(conn, stock)?)
+            });
+            match patched {
+                Ok(updated_stock) => {
+                    cache.invalidate(&[keys::products()]).await;
+                    HttpResponse::Ok().json(updated_stock)
+                }
+                Err(e) => e.into(),
+            }
+        }
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Creates a warehouse.
+#[utoipa::path(
+    post,
+    path = "/stock/warehouse",
+    tag = "stock",
+    request_body = NewWarehouse,
+    responses(
+        (status = 200, description = "The warehouse was created"),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn create_warehouse(
     pool: web::Data<ConnectionPool>,
-    payload: web::Json<NewWarehouse>,
+    payload: Valid<NewWarehouse>,
 ) -> impl Responder {
     let params = payload.into_inner();
     match execute_query_with_args(
@@ -122,6 +267,18 @@ pub async fn get_stock_quantity_for_warehouse(
         Err(e) => e.into(),
     }
 }
+/// Gets a warehouse.
+#[utoipa::path(
+    get,
+    path = "/stock/warehouse/{id}",
+    tag = "stock",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The warehouse", body = Warehouse),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn get_warehouse(
     pool: web::Data<ConnectionPool>,
     payload: web::Path<ResourceIdentifierRequest>,
@@ -134,17 +291,55 @@ pub async fn get_warehouse(
     )
     .await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(warehouse) => warehouse,
         Err(e) => e.into(),
     }
 }
 
-pub async fn list_warehouses(pool: web::Data<ConnectionPool>) -> impl Responder {
-    match execute_query(pool, load_warehouses_query).await {
-        Ok(products) => products,
+/// Lists warehouses, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/stock/warehouse",
+    tag = "stock",
+    params(PageParams, WarehouseFilter),
+    responses(
+        (status = 200, description = "A page of warehouses", body = WarehousePage),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn list_warehouses(
+    request: HttpRequest,
+    pool: web::Data<ConnectionPool>,
+    params: web::Query<PageParams>,
+    filter: web::Query<WarehouseFilter>,
+) -> impl Responder {
+    let page = match params.resolve(WAREHOUSE_SORT_FIELDS) {
+        Ok(page) => page,
+        Err(e) => return HttpResponse::from(e),
+    };
+    match execute_query(pool, |conn| {
+        load_warehouses_query(conn, &filter, &page).map(|(warehouses, total)| {
+            page.page(&request, warehouses, total, |warehouse| warehouse)
+        })
+    })
+    .await
+    {
+        Ok(warehouses) => warehouses,
         Err(e) => e.into(),
     }
 }
+/// Deletes a warehouse and the stock it holds.
+#[utoipa::path(
+    delete,
+    path = "/stock/warehouse/{id}",
+    tag = "stock",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The warehouse no longer exists"),
+        (status = 500, response = responses::InternalError)
+    )
+)]
 pub async fn delete_warehouse(
     pool: web::Data<ConnectionPool>,
     path: web::Path<ResourceIdentifierRequest>,
@@ -1,8 +1,10 @@
 use crate::schema::stock_quantities;
 use crate::services::order::model::Warehouse;
 use crate::services::product::model::Product;
+use crate::{api::validation::Validate, error::ValidationErrors};
 use diesel::{AsChangeset, Associations, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 #[derive(
     Queryable,
     Selectable,
@@ -14,6 +16,7 @@ use serde::{Deserialize, Serialize};
     Insertable,
     AsChangeset,
     Clone,
+    ToSchema,
 )]
 #[diesel(table_name = stock_quantities)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -25,7 +28,13 @@ pub struct StockQuantity {
     pub quantity: i32,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
+impl Validate for StockQuantity {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.at_least("quantity", self.quantity, 0);
+    }
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug, Clone, ToSchema)]
 #[diesel(table_name = stock_quantities)]
 
 pub struct NewStockQuantity {
@@ -34,6 +43,12 @@ pub struct NewStockQuantity {
     pub quantity: i32,
 }
 
+impl Validate for NewStockQuantity {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.at_least("quantity", self.quantity, 0);
+    }
+}
+
 impl NewStockQuantity {
     pub fn new(product_id: i32, quantity: i32, warehouse_id: Option<i32>) -> Self {
         Self {
@@ -43,3 +58,11 @@ impl NewStockQuantity {
         }
     }
 }
+
+/// Identifies the stock of one product in one warehouse.
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct StockQuantityPath {
+    pub product_id: i32,
+    pub warehouse_id: i32,
+}
@@ -1,8 +1,12 @@
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+use diesel::{
+    pg::Pg, Connection, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
 
 use crate::{
+    api::pagination::{self, PageRequest},
     error::DatabaseErrorWrapper,
-    services::order::model::{NewWarehouse, Warehouse},
+    services::order::model::{NewWarehouse, Warehouse, WarehouseFilter},
     {
         postgres::PooledConnection,
         schema::{stock_quantities, warehouses},
@@ -11,10 +15,13 @@ use crate::{
 
 use super::model::{NewStockQuantity, StockQuantity};
 
-pub fn insert_stock_quantity_query(
-    connection: &mut PooledConnection,
+pub fn insert_stock_quantity_query<C>(
+    connection: &mut C,
     new_stock_quantity: NewStockQuantity,
-) -> Result<usize, DatabaseErrorWrapper> {
+) -> Result<usize, DatabaseErrorWrapper>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     use crate::schema::stock_quantities::dsl::*;
 
     let stock_entry = NewStockQuantity {
@@ -35,14 +42,18 @@ pub fn load_stock_quantity_query(
     connection: &mut PooledConnection,
 ) -> Result<Vec<StockQuantity>, DatabaseErrorWrapper> {
     crate::schema::stock_quantities::table
+        .select(StockQuantity::as_select())
         .load::<StockQuantity>(connection)
         .map_err(DatabaseErrorWrapper)
 }
 
-pub fn set_stock_quantity_for_product(
-    connection: &mut PooledConnection,
+pub fn set_stock_quantity_for_product<C>(
+    connection: &mut C,
     updated_stock_quantity: StockQuantity,
-) -> Result<StockQuantity, DatabaseErrorWrapper> {
+) -> Result<StockQuantity, DatabaseErrorWrapper>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     use crate::schema::stock_quantities::dsl::*;
 
     diesel::update(
@@ -55,14 +66,18 @@ pub fn set_stock_quantity_for_product(
         product_id: updated_stock_quantity.product_id,
         quantity: updated_stock_quantity.quantity,
     })
+    .returning(StockQuantity::as_returning())
     .get_result::<StockQuantity>(connection)
     .map_err(DatabaseErrorWrapper)
 }
 
-pub fn delete_stock_quantity_from_product_query(
-    connection: &mut PooledConnection,
+pub fn delete_stock_quantity_from_product_query<C>(
+    connection: &mut C,
     product_id: i32,
-) -> Result<usize, DatabaseErrorWrapper> {
+) -> Result<usize, DatabaseErrorWrapper>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
     diesel::delete(
         stock_quantities::table
             .filter(crate::schema::stock_quantities::columns::product_id.eq(product_id)),
@@ -71,6 +86,32 @@ pub fn delete_stock_quantity_from_product_query(
     .map_err(DatabaseErrorWrapper)
 }
 
+pub fn select_stock_quantity_query(
+    connection: &mut PooledConnection,
+    product_id: i32,
+    warehouse_id: i32,
+) -> Result<StockQuantity, DatabaseErrorWrapper> {
+    crate::schema::stock_quantities::table
+        .find((product_id, warehouse_id))
+        .select(StockQuantity::as_select())
+        .first::<StockQuantity>(connection)
+        .map_err(DatabaseErrorWrapper)
+}
+
+/// Reads a stock quantity and locks its row until the transaction ends.
+pub fn select_stock_quantity_for_update_query(
+    connection: &mut PooledConnection,
+    product_id: i32,
+    warehouse_id: i32,
+) -> Result<StockQuantity, DatabaseErrorWrapper> {
+    crate::schema::stock_quantities::table
+        .find((product_id, warehouse_id))
+        .select(StockQuantity::as_select())
+        .for_update()
+        .first::<StockQuantity>(connection)
+        .map_err(DatabaseErrorWrapper)
+}
+
 pub fn select_stock_quantity_for_product(
     product_id: i32,
     connection: &mut PooledConnection,
@@ -93,12 +134,35 @@ pub fn insert_warehouse_query(
         .map_err(DatabaseErrorWrapper)
 }
 
+fn filtered_warehouses_query(filter: &WarehouseFilter) -> warehouses::BoxedQuery<'static, Pg> {
+    let mut query = warehouses::table.into_boxed();
+    if let Some(name) = &filter.name {
+        query = query.filter(warehouses::name.ilike(pagination::contains(name)));
+    }
+    query
+}
+
+/// A page of the warehouses matching `filter`, and how many match.
 pub fn load_warehouses_query(
     connection: &mut PooledConnection,
-) -> Result<Vec<Warehouse>, DatabaseErrorWrapper> {
-    crate::schema::warehouses::table
+    filter: &WarehouseFilter,
+    page: &PageRequest,
+) -> Result<(Vec<Warehouse>, i64), DatabaseErrorWrapper> {
+    let total = filtered_warehouses_query(filter)
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(DatabaseErrorWrapper)?;
+    let mut query = filtered_warehouses_query(filter)
+        .order(page.order())
+        .limit(page.load_limit())
+        .offset(page.offset());
+    if let Some(seek) = page.seek() {
+        query = query.filter(seek);
+    }
+    let warehouses = query
         .load::<Warehouse>(connection)
-        .map_err(DatabaseErrorWrapper)
+        .map_err(DatabaseErrorWrapper)?;
+    Ok((warehouses, total))
 }
 
 pub fn select_warehouse_query(
@@ -1,10 +1,14 @@
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, patch, post, put, scope};
+use utoipa::OpenApi;
 
 use super::handler::{
     create_stock_quantity, create_warehouse, delete_stock_quantity_from_product, delete_warehouse,
     get_stock_quantity_for_product, get_warehouse, list_stock_quantity, list_warehouses,
-    update_stock_quantity_for_product,
+    patch_stock_quantity, update_stock_quantity_for_product,
 };
+use super::model::{NewStockQuantity, StockQuantity};
+use crate::api::pagination::WarehousePage;
+use crate::services::order::model::{NewWarehouse, Warehouse};
 
 pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(
@@ -12,11 +16,40 @@ pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
             .route("", post().to(create_stock_quantity))
             .route("", get().to(list_stock_quantity))
             .route("", put().to(update_stock_quantity_for_product))
-            .route("/{id}", get().to(get_stock_quantity_for_product))
-            .route("/{id}", delete().to(delete_stock_quantity_from_product))
             .route("/warehouse", get().to(list_warehouses))
             .route("/warehouse", post().to(create_warehouse))
             .route("/warehouse/{id}", get().to(get_warehouse))
-            .route("/warehouse/{id}", delete().to(delete_warehouse)),
+            .route("/warehouse/{id}", delete().to(delete_warehouse))
+            .route("/{id}", get().to(get_stock_quantity_for_product))
+            .route("/{id}", delete().to(delete_stock_quantity_from_product))
+            .route(
+                "/{product_id}/{warehouse_id}",
+                patch().to(patch_stock_quantity),
+            ),
     );
 }
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_stock_quantity,
+        super::handler::list_stock_quantity,
+        super::handler::update_stock_quantity_for_product,
+        super::handler::list_warehouses,
+        super::handler::create_warehouse,
+        super::handler::get_warehouse,
+        super::handler::delete_warehouse,
+        super::handler::get_stock_quantity_for_product,
+        super::handler::delete_stock_quantity_from_product,
+        super::handler::patch_stock_quantity
+    ),
+    components(schemas(
+        StockQuantity,
+        NewStockQuantity,
+        Warehouse,
+        NewWarehouse,
+        WarehousePage
+    )),
+    tags((name = "stock", description = "Warehouses and the stock they hold"))
+)]
+pub struct StockApi;
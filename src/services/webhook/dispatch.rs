@@ -0,0 +1,654 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use diesel::{result::Error as DieselError, Connection, PgConnection};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect::Policy,
+    Client,
+};
+use sha2::Sha256;
+use url::{Host, Url};
+
+use crate::{
+    cfg::Env, error::ConnectionPoolErrorWrapper, outbox::OutboxEvent, postgres::ConnectionPool,
+};
+
+use super::{
+    model::{
+        status, NewWebhookDelivery, WebhookDelivery, WebhookPayload, WebhookSubscription,
+        PING_EVENT,
+    },
+    query::{
+        claim_due_deliveries_query, insert_deliveries_query, load_active_subscriptions_query,
+        record_attempt_query, record_subscription_failure_query, record_subscription_success_query,
+    },
+};
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body, keyed with the
+/// subscription secret.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+const SIGNATURE_PREFIX: &str = "sha256=";
+const USER_AGENT: &str = "ecom-engine-webhooks";
+
+pub const DEFAULT_DISPATCH_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_RETRY_BASE: Duration = Duration::from_secs(10);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 8;
+pub const DEFAULT_DISABLE_AFTER: i32 = 20;
+/// Deliveries claimed and sent concurrently per round.
+const DISPATCH_BATCH_SIZE: i64 = 50;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The value of [`SIGNATURE_HEADER`] for `body`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!(
+        "{}{}",
+        SIGNATURE_PREFIX,
+        to_hex(&mac.finalize().into_bytes())
+    )
+}
+
+/// Checks a [`SIGNATURE_HEADER`] value in constant time.
+pub fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature.strip_prefix(SIGNATURE_PREFIX).and_then(from_hex) else {
+        return false;
+    };
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Checks that deliveries can be sent to `url`.
+pub fn validate_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|err| format!("is not a valid URL: {}", err))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err("must be an http:// or https:// URL".to_string());
+    }
+    if url.host_str().is_none() {
+        return Err("must have a host".to_string());
+    }
+    Ok(url)
+}
+
+/// Whether deliveries may be sent to `ip`. Loopback, private, link-local and
+/// other special-purpose addresses are refused, so a subscription can't make
+/// the server send requests into its own network.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking, 198.18.0.0/15.
+                || (a == 198 && (b == 18 || b == 19))
+                // Reserved, 240.0.0.0/4, and broadcast.
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7.
+                    || (first & 0xfe00) == 0xfc00
+                    // Link-local, fe80::/10.
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Checks that the host of `url` is not a non-public address or `localhost`.
+/// Host names are checked again when deliveries are sent, against the
+/// addresses they resolve to then.
+pub fn check_host(url: &Url, allow_private_hosts: bool) -> Result<(), String> {
+    if allow_private_hosts {
+        return Ok(());
+    }
+    let public = match url.host() {
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => return Err("must have a host".to_string()),
+    };
+    if public {
+        Ok(())
+    } else {
+        Err("must not point to a loopback, private or link-local address".to_string())
+    }
+}
+
+/// Resolves the hosts of deliveries to their public addresses only, and fails
+/// for hosts that have none.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// The client deliveries are sent with. It doesn't follow redirects or use
+/// proxies, and unless `allow_private_hosts` is set it only connects to public
+/// addresses.
+pub fn delivery_client(config: &DispatchConfig) -> reqwest::Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .redirect(Policy::none())
+        .no_proxy()
+        .timeout(config.timeout);
+    if !config.allow_private_hosts {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    builder.build()
+}
+
+#[derive(Debug, Clone)]
+pub struct DispatchConfig {
+    /// Wait between checks for due deliveries.
+    pub interval: Duration,
+    /// Time allowed for connecting, sending and reading the response status.
+    pub timeout: Duration,
+    /// Wait before the first retry, doubled for every retry after it.
+    pub retry_base: Duration,
+    /// Attempts per delivery before it is marked failed.
+    pub max_attempts: i32,
+    /// Failed attempts in a row after which a subscription is disabled.
+    pub disable_after: i32,
+    /// Allow subscriptions to loopback, private and link-local hosts, for
+    /// local development.
+    pub allow_private_hosts: bool,
+}
+
+impl Default for DispatchConfig {
+    fn default() -> Self {
+        DispatchConfig {
+            interval: DEFAULT_DISPATCH_INTERVAL,
+            timeout: DEFAULT_DELIVERY_TIMEOUT,
+            retry_base: DEFAULT_RETRY_BASE,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            disable_after: DEFAULT_DISABLE_AFTER,
+            allow_private_hosts: false,
+        }
+    }
+}
+
+impl DispatchConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let default = DispatchConfig::default();
+        DispatchConfig {
+            interval: env
+                .webhook_interval
+                .as_deref()
+                .and_then(|interval| interval.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.interval),
+            timeout: env
+                .webhook_timeout
+                .as_deref()
+                .and_then(|timeout| timeout.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+            retry_base: env
+                .webhook_retry_base
+                .as_deref()
+                .and_then(|retry_base| retry_base.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.retry_base),
+            max_attempts: env
+                .webhook_max_attempts
+                .as_deref()
+                .and_then(|max_attempts| max_attempts.parse().ok())
+                .filter(|max_attempts| *max_attempts > 0)
+                .unwrap_or(default.max_attempts),
+            disable_after: env
+                .webhook_disable_after
+                .as_deref()
+                .and_then(|disable_after| disable_after.parse().ok())
+                .filter(|disable_after| *disable_after > 0)
+                .unwrap_or(default.disable_after),
+            allow_private_hosts: env
+                .webhook_allow_private_hosts
+                .as_deref()
+                .and_then(|allow| allow.parse().ok())
+                .unwrap_or(default.allow_private_hosts),
+        }
+    }
+
+    /// Wait before retrying a delivery that has failed `attempts` times.
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+        self.retry_base
+            .saturating_mul(2u32.pow(exponent))
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+impl From<&OutboxEvent> for WebhookPayload {
+    fn from(event: &OutboxEvent) -> Self {
+        WebhookPayload {
+            event_id: Some(event.id),
+            event_type: event.event_type.clone(),
+            aggregate_type: Some(event.aggregate_type.clone()),
+            aggregate_id: Some(event.aggregate_id.clone()),
+            occurred_at: event.created_at.and_utc().to_rfc3339(),
+            payload: event.payload.clone(),
+        }
+    }
+}
+
+fn serialize_body(payload: &WebhookPayload) -> Result<String, DieselError> {
+    serde_json::to_string(payload).map_err(|err| DieselError::SerializationError(Box::new(err)))
+}
+
+/// Queues a delivery of every event to each active subscription that wants
+/// it. Called by the outbox relay in the transaction that publishes the
+/// events, so each event is queued exactly once.
+pub fn enqueue_event_deliveries(
+    connection: &mut PgConnection,
+    events: &[OutboxEvent],
+) -> Result<usize, DieselError> {
+    let subscriptions = load_active_subscriptions_query(connection)?;
+    if subscriptions.is_empty() {
+        return Ok(0);
+    }
+
+    let mut deliveries = Vec::new();
+    for event in events {
+        let mut body = None;
+        for subscription in subscriptions
+            .iter()
+            .filter(|subscription| subscription.wants(&event.event_type))
+        {
+            let body = match &body {
+                Some(body) => body,
+                None => body.insert(serialize_body(&WebhookPayload::from(event))?),
+            };
+            deliveries.push(NewWebhookDelivery {
+                subscription_id: subscription.id,
+                event_id: Some(event.id),
+                event_type: event.event_type.clone(),
+                body: body.clone(),
+            });
+        }
+    }
+
+    // Stay well below the Postgres limit on bind parameters.
+    let mut queued = 0;
+    for chunk in deliveries.chunks(5_000) {
+        queued += insert_deliveries_query(connection, chunk)?;
+    }
+    Ok(queued)
+}
+
+/// A test delivery for `subscription`, sent like any other.
+pub fn ping_delivery(
+    subscription: &WebhookSubscription,
+) -> Result<NewWebhookDelivery, DieselError> {
+    let payload = WebhookPayload {
+        event_id: None,
+        event_type: PING_EVENT.to_string(),
+        aggregate_type: None,
+        aggregate_id: None,
+        occurred_at: Utc::now().to_rfc3339(),
+        payload: serde_json::json!({ "subscription_id": subscription.id }),
+    };
+    Ok(NewWebhookDelivery {
+        subscription_id: subscription.id,
+        event_id: None,
+        event_type: PING_EVENT.to_string(),
+        body: serialize_body(&payload)?,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct AttemptOutcome {
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl AttemptOutcome {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && matches!(self.status_code, Some(200..=299))
+    }
+}
+
+/// `err` with the errors that caused it, e.g. why connecting failed.
+fn describe(err: &reqwest::Error) -> String {
+    let mut description = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        description.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    description
+}
+
+/// Sends `body` as a POST and returns the response status.
+async fn post(
+    client: &Client,
+    url: &Url,
+    headers: &[(&str, String)],
+    body: &str,
+) -> Result<u16, String> {
+    let mut request = client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let response = request.send().await.map_err(|err| describe(&err))?;
+    Ok(response.status().as_u16())
+}
+
+/// Makes one attempt at sending `delivery` to `subscription`.
+pub async fn attempt_delivery(
+    client: &Client,
+    delivery: &WebhookDelivery,
+    subscription: &WebhookSubscription,
+    config: &DispatchConfig,
+) -> AttemptOutcome {
+    let timeout = config.timeout;
+    let url = match validate_url(&subscription.url)
+        .and_then(|url| check_host(&url, config.allow_private_hosts).map(|_| url))
+    {
+        Ok(url) => url,
+        Err(err) => {
+            return AttemptOutcome {
+                status_code: None,
+                error: Some(format!("URL {}", err)),
+            }
+        }
+    };
+    let headers = [
+        (
+            SIGNATURE_HEADER,
+            sign(&subscription.secret, delivery.body.as_bytes()),
+        ),
+        (EVENT_HEADER, delivery.event_type.clone()),
+        (DELIVERY_HEADER, delivery.id.to_string()),
+    ];
+
+    match tokio::time::timeout(timeout, post(client, &url, &headers, &delivery.body)).await {
+        Ok(Ok(status_code)) if (200..300).contains(&status_code) => AttemptOutcome {
+            status_code: Some(status_code),
+            error: None,
+        },
+        Ok(Ok(status_code)) => AttemptOutcome {
+            status_code: Some(status_code),
+            error: Some(format!("Receiver responded with {}", status_code)),
+        },
+        Ok(Err(err)) => AttemptOutcome {
+            status_code: None,
+            error: Some(err),
+        },
+        Err(_) => AttemptOutcome {
+            status_code: None,
+            error: Some(format!("Timed out after {}s", timeout.as_secs())),
+        },
+    }
+}
+
+/// Stores the outcome of an attempt, scheduling a retry with exponential
+/// backoff or marking the delivery failed once it is out of attempts.
+pub fn record_outcome(
+    connection: &mut PgConnection,
+    delivery: &WebhookDelivery,
+    outcome: &AttemptOutcome,
+    config: &DispatchConfig,
+) -> Result<WebhookDelivery, DieselError> {
+    connection.transaction(|connection| {
+        let status_code = outcome.status_code.map(i32::from);
+        if outcome.is_success() {
+            record_subscription_success_query(connection, delivery.subscription_id)?;
+            return record_attempt_query(
+                connection,
+                delivery.id,
+                status::DELIVERED,
+                None,
+                status_code,
+                None,
+            );
+        }
+
+        let attempts = delivery.attempts + 1;
+        let (delivery_status, next_attempt_at) = if attempts >= config.max_attempts {
+            (status::FAILED, None)
+        } else {
+            let delay = chrono::Duration::from_std(config.backoff(attempts))
+                .unwrap_or(chrono::Duration::hours(1));
+            (status::PENDING, Some(Utc::now().naive_utc() + delay))
+        };
+        let updated = record_attempt_query(
+            connection,
+            delivery.id,
+            delivery_status,
+            next_attempt_at,
+            status_code,
+            outcome.error.clone(),
+        )?;
+        if record_subscription_failure_query(
+            connection,
+            delivery.subscription_id,
+            config.disable_after,
+        )? {
+            warn!(
+                "Disabled webhook subscription {} after {} failed attempts in a row",
+                delivery.subscription_id, config.disable_after
+            );
+        }
+        Ok(updated)
+    })
+}
+
+/// Claims the deliveries that are due, sends them concurrently and records
+/// the outcomes. Returns how many were attempted.
+pub async fn dispatch_due(
+    pool: &ConnectionPool,
+    client: &Client,
+    config: &DispatchConfig,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    // Long enough for every attempt of the round to time out.
+    let lease =
+        chrono::Duration::from_std(config.timeout * 2).unwrap_or(chrono::Duration::minutes(1));
+    let claim_pool = pool.clone();
+    let due = tokio::task::spawn_blocking(move || {
+        let mut connection = claim_pool.get().map_err(ConnectionPoolErrorWrapper)?;
+        let lease_until = Utc::now().naive_utc() + lease;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(claim_due_deliveries_query(
+            &mut connection,
+            DISPATCH_BATCH_SIZE,
+            lease_until,
+        )?)
+    })
+    .await??;
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let attempts = due.iter().map(|(delivery, subscription)| async move {
+        let outcome = attempt_delivery(client, delivery, subscription, config).await;
+        (delivery.clone(), outcome)
+    });
+    let outcomes = futures::future::join_all(attempts).await;
+
+    let record_pool = pool.clone();
+    let record_config = config.clone();
+    let attempted = outcomes.len();
+    tokio::task::spawn_blocking(move || {
+        let mut connection = record_pool.get().map_err(ConnectionPoolErrorWrapper)?;
+        for (delivery, outcome) in outcomes {
+            match outcome.error.as_deref() {
+                None => info!(
+                    "Delivered webhook {} ({}) to subscription {}",
+                    delivery.id, delivery.event_type, delivery.subscription_id
+                ),
+                Some(err) => warn!(
+                    "Webhook {} to subscription {} failed: {}",
+                    delivery.id, delivery.subscription_id, err
+                ),
+            }
+            record_outcome(&mut connection, &delivery, &outcome, &record_config)?;
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    })
+    .await??;
+    Ok(attempted)
+}
+
+/// Sends due webhook deliveries every `config.interval` until the process
+/// exits.
+pub async fn run_webhook_dispatcher(pool: ConnectionPool, config: DispatchConfig) {
+    let client = match delivery_client(&config) {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "Failed to create the webhook client, not sending webhooks: {}",
+                e
+            );
+            return;
+        }
+    };
+    loop {
+        match dispatch_due(&pool, &client, &config).await {
+            // A full round may have left more deliveries due.
+            Ok(attempted) if attempted as i64 >= DISPATCH_BATCH_SIZE => continue,
+            Ok(_) => {}
+            Err(e) => error!("Failed to dispatch webhooks: {}", e),
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn verifies_only_matching_signatures() {
+        let signature = sign("secret", b"{\"id\":1}");
+        assert!(verify("secret", b"{\"id\":1}", &signature));
+        assert!(!verify("other", b"{\"id\":1}", &signature));
+        assert!(!verify("secret", b"{\"id\":2}", &signature));
+        assert!(!verify(
+            "secret",
+            b"{\"id\":1}",
+            signature.trim_start_matches(SIGNATURE_PREFIX)
+        ));
+        assert!(!verify("secret", b"{\"id\":1}", "sha256=abc"));
+        assert!(!verify("secret", b"{\"id\":1}", "sha256=zz"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max_delay() {
+        let config = DispatchConfig {
+            retry_base: Duration::from_secs(10),
+            ..DispatchConfig::default()
+        };
+        assert_eq!(config.backoff(0), Duration::from_secs(10));
+        assert_eq!(config.backoff(1), Duration::from_secs(10));
+        assert_eq!(config.backoff(2), Duration::from_secs(20));
+        assert_eq!(config.backoff(4), Duration::from_secs(80));
+        assert_eq!(config.backoff(10), MAX_RETRY_DELAY);
+        assert_eq!(config.backoff(i32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn refuses_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "198.18.0.1",
+            "255.255.255.255",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+        for ip in ["93.184.216.34", "100.128.0.1", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} is not public", ip);
+        }
+    }
+
+    #[test]
+    fn checks_the_host_of_urls() {
+        let check = |url: &str| check_host(&Url::parse(url).unwrap(), false);
+        assert!(check("https://example.com/hook").is_ok());
+        assert!(check("https://93.184.216.34/hook").is_ok());
+        assert!(check("http://localhost:8080/hook").is_err());
+        assert!(check("http://api.localhost./hook").is_err());
+        assert!(check("http://10.0.0.1/hook").is_err());
+        assert!(check("http://[::1]/hook").is_err());
+        assert!(check_host(&Url::parse("http://localhost/hook").unwrap(), true).is_ok());
+    }
+}
@@ -0,0 +1,232 @@
+use actix_web::{web, HttpResponse, Responder};
+use diesel::PgConnection;
+use rand::Rng;
+
+use crate::{
+    api::{openapi::responses, validation::Valid},
+    error::{AppError, ConnectionPoolErrorWrapper, DatabaseErrorWrapper, ValidationErrors},
+    ResourceIdentifierRequest,
+};
+
+use super::{
+    dispatch::{check_host, ping_delivery, to_hex, validate_url, DispatchConfig},
+    model::{CreatedWebhookSubscription, DeliveryQuery, NewWebhookSubscription},
+    query::{
+        delete_subscription_query, enable_subscription_query, insert_delivery_query,
+        insert_subscription_query, load_deliveries_query, load_subscriptions_query,
+        select_subscription_query,
+    },
+};
+
+const DEFAULT_DELIVERY_LIMIT: i64 = 50;
+const MAX_DELIVERY_LIMIT: i64 = 500;
+
+fn generate_secret() -> String {
+    to_hex(&rand::thread_rng().gen::<[u8; 32]>())
+}
+
+/// Subscribes a URL to domain events. The response is the only one that includes the signing secret.
+#[utoipa::path(
+    post,
+    path = "/webhook",
+    tag = "webhook",
+    request_body = NewWebhookSubscription,
+    responses(
+        (status = 201, description = "The created subscription", body = CreatedWebhookSubscription),
+        (status = 400, response = responses::BadRequest),
+        (status = 422, response = responses::UnprocessableEntity),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn create_subscription(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    config: web::Data<DispatchConfig>,
+    payload: Valid<NewWebhookSubscription>,
+) -> impl Responder {
+    let mut new_subscription = payload.into_inner();
+    if let Err(e) = validate_url(&new_subscription.url)
+        .and_then(|url| check_host(&url, config.allow_private_hosts))
+    {
+        let mut errors = ValidationErrors::new();
+        errors.add("url", &e);
+        return errors.into();
+    }
+    let secret = new_subscription
+        .secret
+        .get_or_insert_with(generate_secret)
+        .clone();
+    match pool.get() {
+        Ok(mut conn) => match insert_subscription_query(&mut conn, new_subscription) {
+            Ok(subscription) => HttpResponse::Created().json(CreatedWebhookSubscription {
+                subscription,
+                secret,
+            }),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Lists webhook subscriptions.
+#[utoipa::path(
+    get,
+    path = "/webhook",
+    tag = "webhook",
+    responses(
+        (status = 200, description = "Every subscription", body = [WebhookSubscription]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn list_subscriptions(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+) -> impl Responder {
+    match pool.get() {
+        Ok(mut conn) => match load_subscriptions_query(&mut conn) {
+            Ok(subscriptions) => HttpResponse::Ok().json(subscriptions),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Gets a webhook subscription.
+#[utoipa::path(
+    get,
+    path = "/webhook/{id}",
+    tag = "webhook",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The subscription", body = WebhookSubscription),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn get_subscription(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+) -> impl Responder {
+    let params = path.into_inner();
+    match pool.get() {
+        Ok(mut conn) => match select_subscription_query(&mut conn, params.id) {
+            Ok(subscription) => HttpResponse::Ok().json(subscription),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Deletes a webhook subscription and its deliveries.
+#[utoipa::path(
+    delete,
+    path = "/webhook/{id}",
+    tag = "webhook",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The subscription no longer exists"),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn delete_subscription(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+) -> impl Responder {
+    let params = path.into_inner();
+    match pool.get() {
+        Ok(mut conn) => match delete_subscription_query(&mut conn, params.id) {
+            Ok(0) => {
+                AppError::not_found(format!("No webhook subscription has id {}", params.id)).into()
+            }
+            Ok(_) => HttpResponse::Ok().finish(),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Re-activates a subscription that was disabled after failing too often.
+#[utoipa::path(
+    post,
+    path = "/webhook/{id}/enable",
+    tag = "webhook",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 200, description = "The enabled subscription", body = WebhookSubscription),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn enable_subscription(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+) -> impl Responder {
+    let params = path.into_inner();
+    match pool.get() {
+        Ok(mut conn) => match enable_subscription_query(&mut conn, params.id) {
+            Ok(subscription) => HttpResponse::Ok().json(subscription),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Queues a `Ping` delivery, to check that the receiver gets and verifies
+/// signed deliveries.
+#[utoipa::path(
+    post,
+    path = "/webhook/{id}/ping",
+    tag = "webhook",
+    params(ResourceIdentifierRequest),
+    responses(
+        (status = 202, description = "The queued delivery", body = WebhookDelivery),
+        (status = 404, response = responses::NotFound),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn ping_subscription(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+) -> impl Responder {
+    let params = path.into_inner();
+    match pool.get() {
+        Ok(mut conn) => match select_subscription_query(&mut conn, params.id)
+            .and_then(|subscription| ping_delivery(&subscription))
+            .and_then(|delivery| insert_delivery_query(&mut conn, delivery))
+        {
+            Ok(delivery) => HttpResponse::Accepted().json(delivery),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
+
+/// Lists the latest deliveries of a webhook subscription.
+#[utoipa::path(
+    get,
+    path = "/webhook/{id}/deliveries",
+    tag = "webhook",
+    params(ResourceIdentifierRequest, DeliveryQuery),
+    responses(
+        (status = 200, description = "Deliveries, newest first", body = [WebhookDelivery]),
+        (status = 500, response = responses::InternalError)
+    )
+)]
+pub async fn list_deliveries(
+    pool: web::Data<r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>>,
+    path: web::Path<ResourceIdentifierRequest>,
+    query: web::Query<DeliveryQuery>,
+) -> impl Responder {
+    let params = path.into_inner();
+    let query = query.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERY_LIMIT)
+        .clamp(1, MAX_DELIVERY_LIMIT);
+    match pool.get() {
+        Ok(mut conn) => match load_deliveries_query(&mut conn, params.id, query.status, limit) {
+            Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+            Err(e) => DatabaseErrorWrapper(e).into(),
+        },
+        Err(e) => ConnectionPoolErrorWrapper(e).into(),
+    }
+}
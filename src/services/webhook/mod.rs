@@ -0,0 +1,5 @@
+pub mod dispatch;
+pub mod handler;
+pub mod model;
+pub mod query;
+pub mod service;
@@ -0,0 +1,126 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::schema::{webhook_deliveries, webhook_subscriptions};
+use crate::{
+    api::validation::Validate, error::ValidationErrors, services::webhook::dispatch::validate_url,
+};
+
+/// Values of `WebhookDelivery::status`.
+pub mod status {
+    pub const PENDING: &str = "pending";
+    pub const DELIVERED: &str = "delivered";
+    /// Every attempt failed; the delivery is not retried again.
+    pub const FAILED: &str = "failed";
+}
+
+/// Event type of the deliveries sent by `POST /webhook/{id}/ping`.
+pub const PING_EVENT: &str = "Ping";
+
+#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[diesel(table_name = webhook_subscriptions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    /// Domain event types sent to the subscriber, or every type if empty.
+    pub event_types: Vec<String>,
+    /// Only returned when the subscription is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Cleared after too many consecutive failed attempts.
+    pub active: bool,
+    pub consecutive_failures: i32,
+    pub created_at: NaiveDateTime,
+    pub disabled_at: Option<NaiveDateTime>,
+}
+
+impl WebhookSubscription {
+    pub fn wants(&self, event_type: &str) -> bool {
+        self.event_types.is_empty() || self.event_types.iter().any(|wanted| wanted == event_type)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable, ToSchema)]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct NewWebhookSubscription {
+    pub url: String,
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// Generated if not given.
+    pub secret: Option<String>,
+}
+
+impl Validate for NewWebhookSubscription {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if let Err(e) = validate_url(&self.url) {
+            errors.add("url", &e);
+        }
+        for event_type in &self.event_types {
+            errors.not_blank("event_types", event_type);
+        }
+        if let Some(secret) = &self.secret {
+            errors.not_blank("secret", secret);
+        }
+    }
+}
+
+/// Response to creating a subscription, the only one that includes the
+/// signing secret.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CreatedWebhookSubscription {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    pub secret: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[diesel(table_name = webhook_deliveries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: i32,
+    /// Id of the outbox event delivered, none for pings.
+    pub event_id: Option<i64>,
+    pub event_type: String,
+    pub body: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery {
+    pub subscription_id: i32,
+    pub event_id: Option<i64>,
+    pub event_type: String,
+    pub body: String,
+}
+
+/// Body of every delivery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event_id: Option<i64>,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub aggregate_type: Option<String>,
+    pub aggregate_id: Option<String>,
+    pub occurred_at: String,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    /// `pending`, `delivered` or `failed`.
+    pub status: Option<String>,
+    /// Deliveries to return, newest first. Defaults to 50, at most 500.
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,251 @@
+use chrono::NaiveDateTime;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+
+use crate::schema::{webhook_deliveries, webhook_subscriptions};
+
+use super::model::{
+    status, NewWebhookDelivery, NewWebhookSubscription, WebhookDelivery, WebhookSubscription,
+};
+
+pub fn insert_subscription_query<C>(
+    connection: &mut C,
+    new_subscription: NewWebhookSubscription,
+) -> Result<WebhookSubscription, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::insert_into(webhook_subscriptions::table)
+        .values(&new_subscription)
+        .returning(WebhookSubscription::as_select())
+        .get_result(connection)
+}
+
+pub fn load_subscriptions_query<C>(
+    connection: &mut C,
+) -> Result<Vec<WebhookSubscription>, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    webhook_subscriptions::table
+        .order(webhook_subscriptions::id)
+        .select(WebhookSubscription::as_select())
+        .load(connection)
+}
+
+pub fn load_active_subscriptions_query<C>(
+    connection: &mut C,
+) -> Result<Vec<WebhookSubscription>, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    webhook_subscriptions::table
+        .filter(webhook_subscriptions::active.eq(true))
+        .select(WebhookSubscription::as_select())
+        .load(connection)
+}
+
+pub fn select_subscription_query<C>(
+    connection: &mut C,
+    id: i32,
+) -> Result<WebhookSubscription, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    webhook_subscriptions::table
+        .find(id)
+        .select(WebhookSubscription::as_select())
+        .first(connection)
+}
+
+pub fn delete_subscription_query<C>(
+    connection: &mut C,
+    id: i32,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::delete(webhook_subscriptions::table.find(id)).execute(connection)
+}
+
+/// Re-activates a subscription and resets its failure count.
+pub fn enable_subscription_query<C>(
+    connection: &mut C,
+    id: i32,
+) -> Result<WebhookSubscription, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::update(webhook_subscriptions::table.find(id))
+        .set((
+            webhook_subscriptions::active.eq(true),
+            webhook_subscriptions::consecutive_failures.eq(0),
+            webhook_subscriptions::disabled_at.eq(None::<NaiveDateTime>),
+        ))
+        .returning(WebhookSubscription::as_select())
+        .get_result(connection)
+}
+
+pub fn insert_delivery_query<C>(
+    connection: &mut C,
+    delivery: NewWebhookDelivery,
+) -> Result<WebhookDelivery, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::insert_into(webhook_deliveries::table)
+        .values(&delivery)
+        .returning(WebhookDelivery::as_select())
+        .get_result(connection)
+}
+
+pub fn insert_deliveries_query<C>(
+    connection: &mut C,
+    deliveries: &[NewWebhookDelivery],
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::insert_into(webhook_deliveries::table)
+        .values(deliveries)
+        .execute(connection)
+}
+
+/// Newest deliveries of a subscription first.
+pub fn load_deliveries_query<C>(
+    connection: &mut C,
+    subscription_id: i32,
+    delivery_status: Option<String>,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    let mut query = webhook_deliveries::table
+        .filter(webhook_deliveries::subscription_id.eq(subscription_id))
+        .into_boxed();
+    if let Some(delivery_status) = delivery_status {
+        query = query.filter(webhook_deliveries::status.eq(delivery_status));
+    }
+    query
+        .order(webhook_deliveries::id.desc())
+        .limit(limit)
+        .select(WebhookDelivery::as_select())
+        .load(connection)
+}
+
+/// Claims up to `limit` pending deliveries that are due, for active
+/// subscriptions, by pushing their next attempt to `lease_until`. Another
+/// dispatcher picks them up again if this one dies before recording a result.
+pub fn claim_due_deliveries_query<C>(
+    connection: &mut C,
+    limit: i64,
+    lease_until: NaiveDateTime,
+) -> Result<Vec<(WebhookDelivery, WebhookSubscription)>, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    connection.transaction(|connection| {
+        let due: Vec<(WebhookDelivery, WebhookSubscription)> = webhook_deliveries::table
+            .inner_join(webhook_subscriptions::table)
+            .filter(webhook_deliveries::status.eq(status::PENDING))
+            .filter(webhook_deliveries::next_attempt_at.le(diesel::dsl::now))
+            .filter(webhook_subscriptions::active.eq(true))
+            .order(webhook_deliveries::id)
+            .limit(limit)
+            .for_update()
+            .skip_locked()
+            .select((
+                WebhookDelivery::as_select(),
+                WebhookSubscription::as_select(),
+            ))
+            .load(connection)?;
+
+        let ids: Vec<i64> = due.iter().map(|(delivery, _)| delivery.id).collect();
+        diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq_any(&ids)))
+            .set(webhook_deliveries::next_attempt_at.eq(lease_until))
+            .execute(connection)?;
+        Ok(due)
+    })
+}
+
+/// Records the outcome of one attempt. `next_attempt_at` of `None` means the
+/// delivery is finished, as `status`.
+pub fn record_attempt_query<C>(
+    connection: &mut C,
+    delivery_id: i64,
+    delivery_status: &str,
+    next_attempt_at: Option<NaiveDateTime>,
+    status_code: Option<i32>,
+    error: Option<String>,
+) -> Result<WebhookDelivery, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    let now = chrono::Utc::now().naive_utc();
+    let delivered_at = (delivery_status == status::DELIVERED).then_some(now);
+    diesel::update(webhook_deliveries::table.find(delivery_id))
+        .set((
+            webhook_deliveries::status.eq(delivery_status),
+            webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+            webhook_deliveries::next_attempt_at.eq(next_attempt_at.unwrap_or(now)),
+            webhook_deliveries::last_status_code.eq(status_code),
+            webhook_deliveries::last_error.eq(error),
+            webhook_deliveries::delivered_at.eq(delivered_at),
+        ))
+        .returning(WebhookDelivery::as_select())
+        .get_result(connection)
+}
+
+/// Resets the failure count of a subscription after a successful attempt.
+pub fn record_subscription_success_query<C>(
+    connection: &mut C,
+    id: i32,
+) -> Result<usize, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    diesel::update(webhook_subscriptions::table.find(id))
+        .set(webhook_subscriptions::consecutive_failures.eq(0))
+        .execute(connection)
+}
+
+/// Counts a failed attempt against a subscription, disabling it once
+/// `disable_after` attempts in a row have failed. Returns whether it was
+/// disabled by this failure.
+pub fn record_subscription_failure_query<C>(
+    connection: &mut C,
+    id: i32,
+    disable_after: i32,
+) -> Result<bool, diesel::result::Error>
+where
+    C: Connection<Backend = diesel::pg::Pg> + diesel::connection::LoadConnection,
+{
+    connection.transaction(|connection| {
+        let failures: Option<i32> = diesel::update(webhook_subscriptions::table.find(id))
+            .set(
+                webhook_subscriptions::consecutive_failures
+                    .eq(webhook_subscriptions::consecutive_failures + 1),
+            )
+            .returning(webhook_subscriptions::consecutive_failures)
+            .get_result(connection)
+            .optional()?;
+        match failures {
+            Some(failures) if failures >= disable_after => {
+                let disabled = diesel::update(
+                    webhook_subscriptions::table
+                        .find(id)
+                        .filter(webhook_subscriptions::active.eq(true)),
+                )
+                .set((
+                    webhook_subscriptions::active.eq(false),
+                    webhook_subscriptions::disabled_at.eq(diesel::dsl::now),
+                ))
+                .execute(connection)?;
+                Ok(disabled > 0)
+            }
+            _ => Ok(false),
+        }
+    })
+}
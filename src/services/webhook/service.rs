@@ -0,0 +1,39 @@
+use actix_web::web::{delete, get, post, scope};
+use utoipa::OpenApi;
+
+use super::handler::{
+    create_subscription, delete_subscription, enable_subscription, get_subscription,
+    list_deliveries, list_subscriptions, ping_subscription,
+};
+use super::model::{
+    CreatedWebhookSubscription, NewWebhookSubscription, WebhookDelivery, WebhookSubscription,
+};
+
+pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(
+        scope("/webhook")
+            .route("", post().to(create_subscription))
+            .route("", get().to(list_subscriptions))
+            .route("/{id}", get().to(get_subscription))
+            .route("/{id}", delete().to(delete_subscription))
+            .route("/{id}/enable", post().to(enable_subscription))
+            .route("/{id}/ping", post().to(ping_subscription))
+            .route("/{id}/deliveries", get().to(list_deliveries)),
+    );
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::handler::create_subscription,
+        super::handler::list_subscriptions,
+        super::handler::get_subscription,
+        super::handler::delete_subscription,
+        super::handler::enable_subscription,
+        super::handler::ping_subscription,
+        super::handler::list_deliveries
+    ),
+    components(schemas(WebhookSubscription, NewWebhookSubscription, CreatedWebhookSubscription, WebhookDelivery)),
+    tags((name = "webhook", description = "Subscriptions to domain events and their deliveries"))
+)]
+pub struct WebhookApi;
@@ -0,0 +1,571 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use redis::AsyncCommands;
+
+use crate::{cfg::Env, error::StreamBackendError, redis::RedisManager};
+
+use super::{
+    batch::BatchReport,
+    control::ChannelState,
+    dead_letter::{self, DeadLetter},
+    event_log::{self, EventLogConfig, LogEntry},
+    stats::{self, StatsCounters},
+    StreamChannel, UpdateStreamEvent,
+};
+
+/// Hash of channel states, shared by every processor using the same Redis.
+pub const CHANNEL_STATE_KEY: &str = "update_processor_channels";
+
+/// Where the processor keeps its state: queues of pending updates, one per
+/// channel and consumed oldest first, along with channel states, dead
+/// letters, counters and the event log.
+#[async_trait]
+pub trait StreamBackend: fmt::Debug + Send + Sync {
+    /// Queues each event on the channel of its update.
+    async fn push(&self, events: &[UpdateStreamEvent]) -> Result<(), StreamBackendError>;
+
+    /// Removes and returns up to `count` of the oldest events on `channel`.
+    async fn pop(
+        &self,
+        channel: StreamChannel,
+        count: usize,
+    ) -> Result<Vec<UpdateStreamEvent>, StreamBackendError>;
+
+    /// Number of events waiting on `channel`.
+    async fn depth(&self, channel: StreamChannel) -> Result<usize, StreamBackendError>;
+
+    /// The stored state of `channel`, if it was ever changed.
+    async fn channel_state(
+        &self,
+        channel: StreamChannel,
+    ) -> Result<Option<ChannelState>, StreamBackendError>;
+
+    async fn set_channel_state(
+        &self,
+        channel: StreamChannel,
+        state: &ChannelState,
+    ) -> Result<(), StreamBackendError>;
+
+    /// Stores `dead_letter`, replacing one with the same id.
+    async fn put_dead_letter(&self, dead_letter: &DeadLetter) -> Result<(), StreamBackendError>;
+
+    async fn dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, StreamBackendError>;
+
+    /// Every dead letter, oldest failure first.
+    async fn dead_letters(&self) -> Result<Vec<DeadLetter>, StreamBackendError>;
+
+    /// Removes a dead letter, returning whether there was one.
+    async fn remove_dead_letter(&self, id: &str) -> Result<bool, StreamBackendError>;
+
+    /// Adds `amount` to the counter at `key`, which is dropped `ttl` after
+    /// its last change, and returns the new value.
+    async fn increment(
+        &self,
+        key: &str,
+        amount: i64,
+        ttl: Duration,
+    ) -> Result<i64, StreamBackendError>;
+
+    /// Adds a finished batch to the processor counters.
+    async fn record_batch(&self, report: &BatchReport) -> Result<(), StreamBackendError>;
+
+    async fn stats_counters(&self) -> Result<StatsCounters, StreamBackendError>;
+
+    /// Appends applied events to the event log and trims it to `config`.
+    async fn append_to_log(
+        &self,
+        channel: &str,
+        events: &[&UpdateStreamEvent],
+        config: &EventLogConfig,
+    ) -> Result<(), StreamBackendError>;
+
+    /// Up to `count` log entries from `start` to `end`, given as Redis stream
+    /// ids. `-` and `+` are open bounds, and a `(` prefix excludes the bound.
+    async fn read_log(
+        &self,
+        start: &str,
+        end: &str,
+        count: usize,
+    ) -> Result<Vec<LogEntry>, StreamBackendError>;
+}
+
+/// Picks the backend named by `STREAM_BACKEND`, `redis` unless set to `memory`.
+pub fn backend_from_env(env: &Env, redis: RedisManager) -> Arc<dyn StreamBackend> {
+    match env.stream_backend.as_deref() {
+        Some("memory") => Arc::new(InMemoryBackend::default()),
+        Some(backend) if backend != "redis" => {
+            log::warn!("Unknown stream backend {}, using redis", backend);
+            Arc::new(RedisBackend::new(redis))
+        }
+        _ => Arc::new(RedisBackend::new(redis)),
+    }
+}
+
+/// Redis lists keyed by channel name, shared by every stream instance
+/// pointing at the same server.
+#[derive(Debug, Clone)]
+pub struct RedisBackend {
+    redis: RedisManager,
+}
+
+impl RedisBackend {
+    pub fn new(redis: RedisManager) -> Self {
+        RedisBackend { redis }
+    }
+}
+
+#[async_trait]
+impl StreamBackend for RedisBackend {
+    async fn push(&self, events: &[UpdateStreamEvent]) -> Result<(), StreamBackendError> {
+        let mut pipe = redis::pipe();
+        for event in events {
+            let key: &str = StreamChannel::from_update(&event.data).into();
+            pipe.lpush(key, serde_json::to_string(event)?).ignore();
+        }
+
+        let mut con = self.redis.connection().await?;
+        let _: () = pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    async fn pop(
+        &self,
+        channel: StreamChannel,
+        count: usize,
+    ) -> Result<Vec<UpdateStreamEvent>, StreamBackendError> {
+        let Some(count) = NonZeroUsize::new(count) else {
+            return Ok(Vec::new());
+        };
+        let key: &str = channel.into();
+        let mut con = self.redis.connection().await?;
+        let serialized: Option<Vec<String>> = con.rpop(key, Some(count)).await?;
+
+        let mut events = Vec::new();
+        for event in serialized.unwrap_or_default() {
+            events.push(serde_json::from_str(&event)?);
+        }
+        Ok(events)
+    }
+
+    async fn depth(&self, channel: StreamChannel) -> Result<usize, StreamBackendError> {
+        let key: &str = channel.into();
+        let mut con = self.redis.connection().await?;
+        Ok(con.llen(key).await?)
+    }
+
+    async fn channel_state(
+        &self,
+        channel: StreamChannel,
+    ) -> Result<Option<ChannelState>, StreamBackendError> {
+        let key: &str = channel.into();
+        let mut con = self.redis.connection().await?;
+        let state: Option<String> = con.hget(CHANNEL_STATE_KEY, key).await?;
+        Ok(state
+            .map(|state| serde_json::from_str(&state))
+            .transpose()?)
+    }
+
+    async fn set_channel_state(
+        &self,
+        channel: StreamChannel,
+        state: &ChannelState,
+    ) -> Result<(), StreamBackendError> {
+        let key: &str = channel.into();
+        let mut con = self.redis.connection().await?;
+        let _: () = con
+            .hset(CHANNEL_STATE_KEY, key, serde_json::to_string(state)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn put_dead_letter(&self, dead_letter: &DeadLetter) -> Result<(), StreamBackendError> {
+        Ok(dead_letter::write_dead_letter(&self.redis, dead_letter).await?)
+    }
+
+    async fn dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, StreamBackendError> {
+        Ok(dead_letter::read_dead_letter(&self.redis, id).await?)
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<DeadLetter>, StreamBackendError> {
+        Ok(dead_letter::read_dead_letters(&self.redis).await?)
+    }
+
+    async fn remove_dead_letter(&self, id: &str) -> Result<bool, StreamBackendError> {
+        Ok(dead_letter::remove_dead_letter(&self.redis, id).await?)
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        amount: i64,
+        ttl: Duration,
+    ) -> Result<i64, StreamBackendError> {
+        let mut con = self.redis.connection().await?;
+        let (value,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(key, amount)
+            .expire(key, ttl.as_secs() as i64)
+            .ignore()
+            .query_async(&mut con)
+            .await?;
+        Ok(value)
+    }
+
+    async fn record_batch(&self, report: &BatchReport) -> Result<(), StreamBackendError> {
+        Ok(stats::record_batch(&self.redis, report).await?)
+    }
+
+    async fn stats_counters(&self) -> Result<StatsCounters, StreamBackendError> {
+        Ok(stats::read_counters(&self.redis).await?)
+    }
+
+    async fn append_to_log(
+        &self,
+        channel: &str,
+        events: &[&UpdateStreamEvent],
+        config: &EventLogConfig,
+    ) -> Result<(), StreamBackendError> {
+        Ok(event_log::append_log_entries(&self.redis, channel, events, config).await?)
+    }
+
+    async fn read_log(
+        &self,
+        start: &str,
+        end: &str,
+        count: usize,
+    ) -> Result<Vec<LogEntry>, StreamBackendError> {
+        Ok(event_log::read_log_entries(&self.redis, start, end, count).await?)
+    }
+}
+
+/// Everything the processor keeps, held in this process for running and
+/// testing without Redis. It is lost when the process exits.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    queues: Mutex<HashMap<StreamChannel, VecDeque<UpdateStreamEvent>>>,
+    states: Mutex<HashMap<StreamChannel, ChannelState>>,
+    dead_letters: Mutex<HashMap<String, DeadLetter>>,
+    counters: Mutex<HashMap<String, (i64, Instant)>>,
+    stats: Mutex<MemoryStats>,
+    log: Mutex<MemoryLog>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryStats {
+    fields: HashMap<String, String>,
+    /// Updates processed per minute, by [`stats::minute`].
+    minutes: HashMap<i64, u64>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryLog {
+    entries: VecDeque<LogEntry>,
+    /// Id of the last entry added, which later entries must exceed.
+    last_id: (i64, u64),
+}
+
+/// Parses a Redis stream id or bound, `<milliseconds>[-<sequence>]`, using
+/// `sequence` when it has none.
+fn stream_id(id: &str, sequence: u64) -> Option<(i64, u64)> {
+    match id.split_once('-') {
+        Some((millis, seq)) => Some((millis.parse().ok()?, seq.parse().ok()?)),
+        None => Some((id.parse().ok()?, sequence)),
+    }
+}
+
+/// Whether the log entry id `id` is within the range bound `bound`.
+fn within(id: (i64, u64), bound: &str, start: bool) -> bool {
+    let (exclusive, bound) = match bound.strip_prefix('(') {
+        Some(bound) => (true, bound),
+        None => (false, bound),
+    };
+    let parsed = match bound {
+        "-" => Some((i64::MIN, 0)),
+        "+" => Some((i64::MAX, u64::MAX)),
+        _ => stream_id(bound, if start { 0 } else { u64::MAX }),
+    };
+    match (parsed, start, exclusive) {
+        (None, _, _) => false,
+        (Some(bound), true, false) => id >= bound,
+        (Some(bound), true, true) => id > bound,
+        (Some(bound), false, false) => id <= bound,
+        (Some(bound), false, true) => id < bound,
+    }
+}
+
+#[async_trait]
+impl StreamBackend for InMemoryBackend {
+    async fn push(&self, events: &[UpdateStreamEvent]) -> Result<(), StreamBackendError> {
+        let mut queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+        for event in events {
+            queues
+                .entry(StreamChannel::from_update(&event.data))
+                .or_default()
+                .push_back(event.clone());
+        }
+        Ok(())
+    }
+
+    async fn pop(
+        &self,
+        channel: StreamChannel,
+        count: usize,
+    ) -> Result<Vec<UpdateStreamEvent>, StreamBackendError> {
+        let mut queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(queue) = queues.get_mut(&channel) else {
+            return Ok(Vec::new());
+        };
+        let count = count.min(queue.len());
+        Ok(queue.drain(..count).collect())
+    }
+
+    async fn depth(&self, channel: StreamChannel) -> Result<usize, StreamBackendError> {
+        let queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(queues.get(&channel).map_or(0, VecDeque::len))
+    }
+
+    async fn channel_state(
+        &self,
+        channel: StreamChannel,
+    ) -> Result<Option<ChannelState>, StreamBackendError> {
+        let states = self.states.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(states.get(&channel).cloned())
+    }
+
+    async fn set_channel_state(
+        &self,
+        channel: StreamChannel,
+        state: &ChannelState,
+    ) -> Result<(), StreamBackendError> {
+        let mut states = self.states.lock().unwrap_or_else(|err| err.into_inner());
+        states.insert(channel, state.clone());
+        Ok(())
+    }
+
+    async fn put_dead_letter(&self, dead_letter: &DeadLetter) -> Result<(), StreamBackendError> {
+        let mut dead_letters = self
+            .dead_letters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        dead_letters.insert(dead_letter.id.clone(), dead_letter.clone());
+        Ok(())
+    }
+
+    async fn dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, StreamBackendError> {
+        let dead_letters = self
+            .dead_letters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        Ok(dead_letters.get(id).cloned())
+    }
+
+    async fn dead_letters(&self) -> Result<Vec<DeadLetter>, StreamBackendError> {
+        let dead_letters = self
+            .dead_letters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut dead_letters: Vec<_> = dead_letters.values().cloned().collect();
+        dead_letters.sort_by_key(|dead_letter| dead_letter.failed_at);
+        Ok(dead_letters)
+    }
+
+    async fn remove_dead_letter(&self, id: &str) -> Result<bool, StreamBackendError> {
+        let mut dead_letters = self
+            .dead_letters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        Ok(dead_letters.remove(id).is_some())
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        amount: i64,
+        ttl: Duration,
+    ) -> Result<i64, StreamBackendError> {
+        let mut counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        counters.retain(|_, (_, expires_at)| *expires_at > now);
+        let (value, expires_at) = counters.entry(key.to_string()).or_insert((0, now));
+        *value += amount;
+        *expires_at = now + ttl;
+        Ok(*value)
+    }
+
+    async fn record_batch(&self, report: &BatchReport) -> Result<(), StreamBackendError> {
+        let mut memory = self.stats.lock().unwrap_or_else(|err| err.into_inner());
+        let stats::BatchCounters { increments, values } = stats::batch_counters(report);
+        for (field, amount) in increments {
+            let value = memory.fields.entry(field).or_default();
+            *value = (value.parse::<u64>().unwrap_or(0) + amount).to_string();
+        }
+        for (field, value) in values {
+            memory.fields.insert(field.to_string(), value);
+        }
+        let minute = stats::minute(report.finished_at);
+        *memory.minutes.entry(minute).or_default() += stats::batch_size(report);
+        memory.minutes.retain(|kept, _| *kept >= minute - 1);
+        Ok(())
+    }
+
+    async fn stats_counters(&self) -> Result<StatsCounters, StreamBackendError> {
+        let memory = self.stats.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(StatsCounters {
+            fields: memory.fields.clone(),
+            last_minute: memory
+                .minutes
+                .get(&(stats::minute(Utc::now()) - 1))
+                .copied(),
+        })
+    }
+
+    async fn append_to_log(
+        &self,
+        channel: &str,
+        events: &[&UpdateStreamEvent],
+        config: &EventLogConfig,
+    ) -> Result<(), StreamBackendError> {
+        let mut log = self.log.lock().unwrap_or_else(|err| err.into_inner());
+        for event in events {
+            let millis = Utc::now().timestamp_millis();
+            let id = if millis > log.last_id.0 {
+                (millis, 0)
+            } else {
+                (log.last_id.0, log.last_id.1 + 1)
+            };
+            log.last_id = id;
+            log.entries.push_back(LogEntry {
+                id: format!("{}-{}", id.0, id.1),
+                channel: channel.to_string(),
+                logged_at: Utc.timestamp_millis_opt(id.0).single(),
+                event: (*event).clone(),
+            });
+        }
+
+        let excess = log.entries.len().saturating_sub(config.max_len);
+        log.entries.drain(..excess);
+        if let Some(retention) = config.retention {
+            let oldest = event_log::oldest_retained(retention);
+            log.entries.retain(|entry| {
+                stream_id(&entry.id, 0).is_some_and(|(millis, _)| millis >= oldest)
+            });
+        }
+        Ok(())
+    }
+
+    async fn read_log(
+        &self,
+        start: &str,
+        end: &str,
+        count: usize,
+    ) -> Result<Vec<LogEntry>, StreamBackendError> {
+        let log = self.log.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(log
+            .entries
+            .iter()
+            .filter(|entry| {
+                stream_id(&entry.id, 0)
+                    .is_some_and(|id| within(id, start, true) && within(id, end, false))
+            })
+            .take(count)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> UpdateStreamEvent {
+        UpdateStreamEvent::new(
+            serde_json::from_str(r#"{ "NewBrand": { "name": "Logged" } }"#).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn reads_the_log_between_bounds() {
+        let backend = InMemoryBackend::default();
+        let events = [event(), event(), event()];
+        let config = EventLogConfig::default();
+        backend
+            .append_to_log("brand_updates", &events.iter().collect::<Vec<_>>(), &config)
+            .await
+            .unwrap();
+
+        let all = backend.read_log("-", "+", 10).await.unwrap();
+        let ids: Vec<_> = all.iter().map(|entry| entry.event.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            events
+                .iter()
+                .map(|event| event.id.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let after_first = backend
+            .read_log(&format!("({}", all[0].id), "+", 10)
+            .await
+            .unwrap();
+        assert_eq!(after_first.len(), 2);
+        assert_eq!(after_first[0].id, all[1].id);
+        assert_eq!(
+            backend
+                .read_log(&all[1].id, &all[1].id, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(backend.read_log("-", "+", 1).await.unwrap().len(), 1);
+        assert!(backend
+            .read_log("not an id", "+", 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn trims_the_log_to_its_max_len() {
+        let backend = InMemoryBackend::default();
+        let events = [event(), event(), event()];
+        let config = EventLogConfig {
+            max_len: 2,
+            retention: None,
+        };
+        backend
+            .append_to_log("brand_updates", &events.iter().collect::<Vec<_>>(), &config)
+            .await
+            .unwrap();
+
+        let kept = backend.read_log("-", "+", 10).await.unwrap();
+        let ids: Vec<_> = kept.iter().map(|entry| entry.event.id.as_str()).collect();
+        assert_eq!(ids, [events[1].id.as_str(), events[2].id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn increments_counters() {
+        let backend = InMemoryBackend::default();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(backend.increment("limit", 3, ttl).await.unwrap(), 3);
+        assert_eq!(backend.increment("limit", -1, ttl).await.unwrap(), 2);
+        assert_eq!(backend.increment("other", 1, ttl).await.unwrap(), 1);
+        assert_eq!(
+            backend
+                .increment("expired", 5, Duration::ZERO)
+                .await
+                .unwrap(),
+            5
+        );
+        assert_eq!(backend.increment("expired", 1, ttl).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use diesel::{Connection, PgConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{cfg::Env, error::DatabaseErrorWrapper};
+
+use super::{event::ApplyOutcome, UpdateStreamEvent};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 100;
+
+/// How failures inside a chunk affect the other updates of that chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchSemantics {
+    /// A single failure rolls back every update in the chunk.
+    AllOrNothing,
+    /// Each update runs in its own savepoint; only failed updates are rolled back.
+    PerItem,
+}
+
+impl FromStr for BatchSemantics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all_or_nothing" => Ok(BatchSemantics::AllOrNothing),
+            "per_item" => Ok(BatchSemantics::PerItem),
+            _ => Err(format!("Unknown batch semantics: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchConfig {
+    pub chunk_size: usize,
+    pub semantics: BatchSemantics,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            semantics: BatchSemantics::PerItem,
+        }
+    }
+}
+
+impl BatchConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let default = BatchConfig::default();
+        BatchConfig {
+            chunk_size: env
+                .stream_chunk_size
+                .as_deref()
+                .and_then(|chunk_size| chunk_size.parse().ok())
+                .filter(|chunk_size| *chunk_size > 0)
+                .unwrap_or(default.chunk_size),
+            semantics: env
+                .stream_batch_semantics
+                .as_deref()
+                .and_then(|semantics| semantics.parse().ok())
+                .unwrap_or(default.semantics),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum EventStatus {
+    Applied,
+    /// Already applied earlier; skipped without changing anything.
+    Duplicate,
+    Failed {
+        error: String,
+    },
+    RolledBack {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventResult {
+    pub event: UpdateStreamEvent,
+    #[serde(flatten)]
+    pub status: EventStatus,
+}
+
+impl From<ApplyOutcome> for EventStatus {
+    fn from(outcome: ApplyOutcome) -> Self {
+        match outcome {
+            ApplyOutcome::Applied => EventStatus::Applied,
+            ApplyOutcome::Duplicate => EventStatus::Duplicate,
+        }
+    }
+}
+
+impl EventResult {
+    pub fn error(&self) -> Option<&str> {
+        match &self.status {
+            EventStatus::Applied | EventStatus::Duplicate => None,
+            EventStatus::Failed { error } | EventStatus::RolledBack { error } => Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub channel: String,
+    pub applied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub duration: Duration,
+    pub finished_at: DateTime<Utc>,
+    pub results: Vec<EventResult>,
+}
+
+impl BatchReport {
+    pub fn new(channel: &str, results: Vec<EventResult>, duration: Duration) -> Self {
+        let count = |expected: fn(&EventStatus) -> bool| {
+            results
+                .iter()
+                .filter(|result| expected(&result.status))
+                .count()
+        };
+        let applied = count(|status| matches!(status, EventStatus::Applied));
+        let skipped = count(|status| matches!(status, EventStatus::Duplicate));
+        BatchReport {
+            channel: channel.to_string(),
+            applied,
+            skipped,
+            failed: results.len() - applied - skipped,
+            duration,
+            finished_at: Utc::now(),
+            results,
+        }
+    }
+}
+
+/// Applies `events` in chunks of `config.chunk_size`, one transaction per chunk.
+pub fn apply_batch(
+    con: &mut PgConnection,
+    events: Vec<UpdateStreamEvent>,
+    config: &BatchConfig,
+) -> Vec<EventResult> {
+    let mut results = Vec::with_capacity(events.len());
+    for chunk in events.chunks(config.chunk_size.max(1)) {
+        let statuses = match config.semantics {
+            BatchSemantics::AllOrNothing => apply_all_or_nothing(con, chunk),
+            BatchSemantics::PerItem => apply_per_item(con, chunk),
+        };
+        results.extend(
+            chunk
+                .iter()
+                .cloned()
+                .zip(statuses)
+                .map(|(event, status)| EventResult { event, status }),
+        );
+    }
+    results
+}
+
+fn apply_all_or_nothing(con: &mut PgConnection, chunk: &[UpdateStreamEvent]) -> Vec<EventStatus> {
+    let mut failed_index = None;
+    let result = con.transaction::<_, DatabaseErrorWrapper, _>(|con| {
+        let mut statuses = Vec::with_capacity(chunk.len());
+        for (index, event) in chunk.iter().enumerate() {
+            match event.apply(con) {
+                Ok(outcome) => statuses.push(outcome.into()),
+                Err(err) => {
+                    failed_index = Some(index);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(statuses)
+    });
+
+    match result {
+        Ok(statuses) => statuses,
+        Err(err) => {
+            let error = err.to_string();
+            (0..chunk.len())
+                .map(|index| {
+                    if Some(index) == failed_index {
+                        EventStatus::Failed {
+                            error: error.clone(),
+                        }
+                    } else {
+                        EventStatus::RolledBack {
+                            error: error.clone(),
+                        }
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+fn apply_per_item(con: &mut PgConnection, chunk: &[UpdateStreamEvent]) -> Vec<EventStatus> {
+    let mut statuses = Vec::with_capacity(chunk.len());
+    let result = con.transaction::<_, DatabaseErrorWrapper, _>(|con| {
+        for event in chunk {
+            // `apply` runs in a nested transaction, i.e. a savepoint, so a failure
+            // only undoes this update.
+            let status = match event.apply(con) {
+                Ok(outcome) => outcome.into(),
+                Err(err) => EventStatus::Failed {
+                    error: err.to_string(),
+                },
+            };
+            statuses.push(status);
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(_) => statuses,
+        Err(err) => statuses
+            .into_iter()
+            .map(|status| match status {
+                EventStatus::Applied | EventStatus::Duplicate => EventStatus::RolledBack {
+                    error: err.to_string(),
+                },
+                status => status,
+            })
+            .collect(),
+    }
+}
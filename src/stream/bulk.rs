@@ -0,0 +1,283 @@
+use std::{collections::HashSet, sync::Arc};
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{message, problem, AppError, FieldError, ValidationErrors};
+
+use super::{
+    event::{AddUpdateRequest, SCHEMA_VERSION},
+    AddUpdateQuery, StreamChannel, UpdateProcessor, UpdateStreamEvent,
+};
+
+/// Number of lines written to Redis in one round trip.
+pub const BULK_CHUNK_SIZE: usize = 1000;
+
+/// Longest line a bulk request may have.
+pub const MAX_BULK_LINE_BYTES: usize = 1024 * 1024;
+
+/// Largest body a bulk request may have.
+pub const MAX_BULK_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LineStatus {
+    Accepted { id: String },
+    Rejected { errors: Vec<FieldError> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineResult {
+    /// 1-based line number in the request body.
+    pub line: usize,
+    #[serde(flatten)]
+    pub status: LineStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkReport {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub results: Vec<LineResult>,
+}
+
+impl BulkReport {
+    fn accept(&mut self, line: usize, id: String) {
+        self.accepted += 1;
+        self.results.push(LineResult {
+            line,
+            status: LineStatus::Accepted { id },
+        });
+    }
+
+    fn reject(&mut self, line: usize, errors: ValidationErrors) {
+        self.rejected += 1;
+        self.results.push(LineResult {
+            line,
+            status: LineStatus::Rejected {
+                errors: errors.errors,
+            },
+        });
+    }
+
+    fn reject_line(&mut self, line: usize, message: &str) {
+        let mut errors = ValidationErrors::new();
+        errors.add("line", message);
+        self.reject(line, errors);
+    }
+}
+
+impl UpdateProcessor {
+    /// Validates `lines` and queues the valid ones, adding a result for every
+    /// line to `report`. Nothing is queued until every line has been
+    /// validated, and lines are queued under `api_key`'s rate limit unless
+    /// this is a dry run.
+    pub async fn ingest_lines(
+        &self,
+        lines: Vec<(usize, UpdateStreamEvent)>,
+        api_key: &str,
+        dry_run: bool,
+        report: &mut BulkReport,
+    ) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let updates = lines.iter().map(|(_, event)| event.data.clone()).collect();
+        let validations = match self.validate_all(updates).await {
+            Ok(validations) => validations,
+            Err(err) => {
+                for (line, _) in lines {
+                    report.reject_line(line, &format!("could not be validated: {}", err));
+                }
+                return;
+            }
+        };
+
+        let mut accepted = Vec::with_capacity(lines.len());
+        for ((line, event), errors) in lines.into_iter().zip(validations) {
+            if errors.is_empty() {
+                accepted.push((line, event));
+            } else {
+                report.reject(line, errors);
+            }
+        }
+
+        if !dry_run && !accepted.is_empty() {
+            let channels: Vec<StreamChannel> = accepted
+                .iter()
+                .map(|(_, event)| StreamChannel::from_update(&event.data))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            if let Err(err) = self.admit(api_key, &channels, accepted.len() as u64).await {
+                for (line, _) in accepted {
+                    report.reject_line(line, &format!("could not be queued: {}", err));
+                }
+                return;
+            }
+
+            let mut chunks = accepted.chunks(BULK_CHUNK_SIZE);
+            for chunk in chunks.by_ref() {
+                let events: Vec<_> = chunk.iter().map(|(_, event)| event.clone()).collect();
+                if let Err(err) = self.enqueue(&events).await {
+                    // Later chunks are not queued either, so lines that
+                    // depend on this chunk aren't applied without it.
+                    for (line, _) in chunk.iter().chain(chunks.flatten()) {
+                        report.reject_line(*line, &format!("could not be queued: {}", err));
+                    }
+                    return;
+                }
+                for (line, event) in chunk {
+                    report.accept(*line, event.id.clone());
+                }
+            }
+            return;
+        }
+
+        for (line, event) in accepted {
+            report.accept(line, event.id);
+        }
+    }
+}
+
+/// Parses one line of a bulk request. Blank lines are skipped, and lines that
+/// aren't an update are rejected in `report`.
+fn parse_line(line: usize, bytes: Vec<u8>, report: &mut BulkReport) -> Option<UpdateStreamEvent> {
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => {
+            report.reject_line(line, "is not valid UTF-8");
+            return None;
+        }
+    };
+    if text.trim().is_empty() {
+        return None;
+    }
+    match serde_json::from_str::<AddUpdateRequest>(text.trim()) {
+        Ok(request) => {
+            let event = UpdateStreamEvent::from(request);
+            if event.schema_version > SCHEMA_VERSION {
+                let mut errors = ValidationErrors::new();
+                errors.at_most(
+                    "schema_version",
+                    event.schema_version as i32,
+                    SCHEMA_VERSION as i32,
+                );
+                report.reject(line, errors);
+                None
+            } else {
+                Some(event)
+            }
+        }
+        Err(err) => {
+            report.reject_line(line, &err.to_string());
+            None
+        }
+    }
+}
+
+fn payload_too_large(detail: String) -> HttpResponse {
+    AppError::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        problem::PAYLOAD_TOO_LARGE,
+        message::PAYLOAD_TOO_LARGE,
+    )
+    .with_detail(detail)
+    .error_response()
+}
+
+/// Accepts newline-delimited JSON, one update or event per line. The whole
+/// body is read and validated before any of it is queued, so a request that
+/// fails partway through leaves nothing behind.
+pub(super) async fn add_update_bulk(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    query: web::Query<AddUpdateQuery>,
+    mut body: web::Payload,
+) -> impl Responder {
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared_length.is_some_and(|length| length > MAX_BULK_BODY_BYTES) {
+        return payload_too_large(format!(
+            "Bulk requests may be at most {} bytes",
+            MAX_BULK_BODY_BYTES
+        ));
+    }
+
+    let api_key = processor.limits.limit_key(&request);
+    // Queue depth is checked once the channels the lines touch are known.
+    if !query.dry_run {
+        if let Err(e) = processor.admit(&api_key, &[], 0).await {
+            return e.error_response();
+        }
+    }
+
+    let mut report = BulkReport::default();
+    let mut buffer = Vec::new();
+    let mut parsed = Vec::new();
+    let mut received = 0;
+    let mut line = 0;
+
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return e.error_response(),
+        };
+        received += chunk.len();
+        if received > MAX_BULK_BODY_BYTES {
+            return payload_too_large(format!(
+                "Bulk requests may be at most {} bytes",
+                MAX_BULK_BODY_BYTES
+            ));
+        }
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let mut bytes: Vec<u8> = buffer.drain(..=end).collect();
+            bytes.pop();
+            line += 1;
+            if bytes.len() > MAX_BULK_LINE_BYTES {
+                return line_too_long(line);
+            }
+            if let Some(event) = parse_line(line, bytes, &mut report) {
+                parsed.push((line, event));
+            }
+        }
+        if buffer.len() > MAX_BULK_LINE_BYTES {
+            return line_too_long(line + 1);
+        }
+    }
+    if !buffer.is_empty() {
+        line += 1;
+        if let Some(event) = parse_line(line, buffer, &mut report) {
+            parsed.push((line, event));
+        }
+    }
+
+    processor
+        .ingest_lines(parsed, &api_key, query.dry_run, &mut report)
+        .await;
+
+    report.results.sort_by_key(|result| result.line);
+    log::info!(
+        "Bulk update: {} accepted, {} rejected",
+        report.accepted,
+        report.rejected
+    );
+    HttpResponse::Ok().json(report)
+}
+
+fn line_too_long(line: usize) -> HttpResponse {
+    payload_too_large(format!(
+        "Line {} is longer than {} bytes",
+        line, MAX_BULK_LINE_BYTES
+    ))
+}
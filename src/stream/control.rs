@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::error::StreamBackendError;
+
+use super::{limits, StreamChannel, UpdateProcessor};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelStatus {
+    #[default]
+    Running,
+    Paused,
+    /// Finishing the batch in flight, after which the channel is paused.
+    Draining,
+}
+
+/// Whether a channel is processed, and who last changed that and why. Kept
+/// by the stream backend, so it survives restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelState {
+    pub status: ChannelStatus,
+    pub changed_by: Option<String>,
+    pub reason: Option<String>,
+    pub changed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelStateEntry {
+    pub channel: StreamChannel,
+    #[serde(flatten)]
+    pub state: ChannelState,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ControlRequest {
+    /// Leaving the channel out applies the change to every channel.
+    pub channel: Option<StreamChannel>,
+    /// Defaults to the API key of the request.
+    pub by: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl UpdateProcessor {
+    pub async fn channel_state(
+        &self,
+        channel: StreamChannel,
+    ) -> Result<ChannelState, StreamBackendError> {
+        Ok(self
+            .backend
+            .channel_state(channel)
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn channel_states(&self) -> Result<Vec<ChannelStateEntry>, StreamBackendError> {
+        let mut states = Vec::with_capacity(StreamChannel::ALL.len());
+        for channel in StreamChannel::ALL {
+            states.push(ChannelStateEntry {
+                channel,
+                state: self.channel_state(channel).await?,
+            });
+        }
+        Ok(states)
+    }
+
+    /// Sets the status of `channel`, or of every channel, and wakes the
+    /// affected processing loops so they notice.
+    pub async fn set_status(
+        &self,
+        channel: Option<StreamChannel>,
+        status: ChannelStatus,
+        by: Option<String>,
+        reason: Option<String>,
+    ) -> Result<(), StreamBackendError> {
+        let state = ChannelState {
+            status,
+            changed_by: by,
+            reason,
+            changed_at: Some(Utc::now()),
+        };
+        let channels = match channel {
+            Some(channel) => vec![channel],
+            None => StreamChannel::ALL.to_vec(),
+        };
+        for channel in channels {
+            self.backend.set_channel_state(channel, &state).await?;
+            info!("{:?} is now {:?}", channel, state);
+            self.notify(channel);
+            self.wake(channel);
+        }
+        Ok(())
+    }
+
+    /// Whether the processing loop of `channel` may start a batch. A draining
+    /// channel is paused here, since any batch it had in flight is done.
+    pub(super) async fn may_process(&self, channel: StreamChannel) -> bool {
+        let state = match self.channel_state(channel).await {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to read state of {:?}: {:?}", channel, e);
+                return false;
+            }
+        };
+        match state.status {
+            ChannelStatus::Running => true,
+            ChannelStatus::Paused => false,
+            ChannelStatus::Draining => {
+                self.pause_drained(channel, state).await;
+                false
+            }
+        }
+    }
+
+    /// Pauses `channel` if it is draining. Called by its processing loop
+    /// whenever no batch is in flight.
+    pub(super) async fn pause_if_draining(&self, channel: StreamChannel) {
+        match self.channel_state(channel).await {
+            Ok(state) if state.status == ChannelStatus::Draining => {
+                self.pause_drained(channel, state).await
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to read state of {:?}: {:?}", channel, e),
+        }
+    }
+
+    async fn pause_drained(&self, channel: StreamChannel, state: ChannelState) {
+        let drained = ChannelState {
+            status: ChannelStatus::Paused,
+            changed_at: Some(Utc::now()),
+            ..state
+        };
+        match self.backend.set_channel_state(channel, &drained).await {
+            Ok(_) => info!("{:?} drained and paused", channel),
+            Err(e) => error!("Failed to pause drained {:?}: {:?}", channel, e),
+        }
+    }
+}
+
+async fn change_status(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: Option<web::Json<ControlRequest>>,
+    status: ChannelStatus,
+) -> HttpResponse {
+    let payload = payload.map(web::Json::into_inner).unwrap_or_default();
+    let by = payload.by.or_else(|| Some(limits::api_key(&request)));
+    if let Err(e) = processor
+        .set_status(payload.channel, status, by, payload.reason)
+        .await
+    {
+        return e.error_response();
+    }
+    match processor.channel_states().await {
+        Ok(states) => HttpResponse::Ok().json(states),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub(super) async fn pause_channels(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: Option<web::Json<ControlRequest>>,
+) -> impl Responder {
+    change_status(request, processor, payload, ChannelStatus::Paused).await
+}
+
+pub(super) async fn resume_channels(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: Option<web::Json<ControlRequest>>,
+) -> impl Responder {
+    change_status(request, processor, payload, ChannelStatus::Running).await
+}
+
+pub(super) async fn drain_channels(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: Option<web::Json<ControlRequest>>,
+) -> impl Responder {
+    change_status(request, processor, payload, ChannelStatus::Draining).await
+}
+
+pub(super) async fn get_channel_states(
+    processor: web::Data<Arc<UpdateProcessor>>,
+) -> impl Responder {
+    match processor.channel_states().await {
+        Ok(states) => HttpResponse::Ok().json(states),
+        Err(e) => e.error_response(),
+    }
+}
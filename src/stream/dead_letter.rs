@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{AppError, StreamBackendError},
+    redis::RedisManager,
+};
+
+use super::{event::ApplyOutcome, UpdateProcessor, UpdateStreamEvent};
+
+pub const DEAD_LETTER_KEY: &str = "dead_letters";
+
+pub fn create_dead_letter_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/dead_letters")
+            .route("", web::get().to(list_dead_letters))
+            .route("/{id}", web::get().to(get_dead_letter))
+            .route("/{id}", web::delete().to(discard_dead_letter))
+            .route("/{id}/retry", web::post().to(retry_dead_letter)),
+    );
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: String,
+    pub channel: String,
+    pub event: UpdateStreamEvent,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    pub fn new(channel: &str, event: UpdateStreamEvent, error: String) -> Self {
+        DeadLetter {
+            id: uuid::Uuid::new_v4().to_string(),
+            channel: channel.to_string(),
+            event,
+            error,
+            attempts: 1,
+            failed_at: Utc::now(),
+        }
+    }
+
+    /// Records another failed attempt for the same event.
+    pub fn failed_again(mut self, error: String) -> Self {
+        self.error = error;
+        self.attempts += 1;
+        self.failed_at = Utc::now();
+        self
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterPath {
+    pub id: String,
+}
+
+pub(super) fn serialization_error(err: serde_json::Error) -> redis::RedisError {
+    redis::RedisError::from((
+        redis::ErrorKind::TypeError,
+        "Serialization error",
+        format!("{}", err),
+    ))
+}
+
+pub async fn write_dead_letter(
+    redis: &RedisManager,
+    dead_letter: &DeadLetter,
+) -> redis::RedisResult<()> {
+    let mut con = redis.connection().await?;
+    let _: () = con
+        .hset(
+            DEAD_LETTER_KEY,
+            &dead_letter.id,
+            serde_json::to_string(dead_letter).map_err(serialization_error)?,
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn read_dead_letter(
+    redis: &RedisManager,
+    id: &str,
+) -> redis::RedisResult<Option<DeadLetter>> {
+    let mut con = redis.connection().await?;
+    let serialized: Option<String> = con.hget(DEAD_LETTER_KEY, id).await?;
+    match serialized {
+        Some(dead_letter_str) => Ok(Some(
+            serde_json::from_str(&dead_letter_str).map_err(serialization_error)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+pub async fn read_dead_letters(redis: &RedisManager) -> redis::RedisResult<Vec<DeadLetter>> {
+    let mut con = redis.connection().await?;
+    let serialized: Vec<String> = con.hvals(DEAD_LETTER_KEY).await?;
+
+    let mut dead_letters = Vec::new();
+    for dead_letter_str in serialized {
+        dead_letters.push(serde_json::from_str(&dead_letter_str).map_err(serialization_error)?);
+    }
+    dead_letters.sort_by_key(|dead_letter: &DeadLetter| dead_letter.failed_at);
+
+    Ok(dead_letters)
+}
+
+pub async fn remove_dead_letter(redis: &RedisManager, id: &str) -> redis::RedisResult<bool> {
+    let mut con = redis.connection().await?;
+    let removed: usize = con.hdel(DEAD_LETTER_KEY, id).await?;
+    Ok(removed > 0)
+}
+
+/// Outcome of re-applying a dead-lettered event.
+pub enum RetryOutcome {
+    Applied,
+    Failed(Box<DeadLetter>),
+    NotFound,
+}
+
+impl UpdateProcessor {
+    pub(super) async fn dead_letter(&self, channel: &str, event: UpdateStreamEvent, err: String) {
+        let dead_letter = DeadLetter::new(channel, event, err);
+        match self.backend.put_dead_letter(&dead_letter).await {
+            Ok(_) => info!("Update moved to dead letters: {}", dead_letter.id),
+            Err(e) => error!("Failed to write dead letter {}: {:?}", dead_letter.id, e),
+        }
+    }
+
+    pub async fn retry_dead_letter(&self, id: &str) -> Result<RetryOutcome, StreamBackendError> {
+        let dead_letter = match self.backend.dead_letter(id).await? {
+            Some(dead_letter) => dead_letter,
+            None => return Ok(RetryOutcome::NotFound),
+        };
+
+        let pool = self.pool.clone();
+        let event = dead_letter.event.clone();
+        let result = tokio::task::spawn_blocking(move || match pool.get() {
+            Ok(mut connection) => {
+                Self::handle_update(&mut connection, event).map_err(|err| err.to_string())
+            }
+            Err(err) => Err(err.to_string()),
+        })
+        .await
+        .unwrap_or_else(|err| Err(err.to_string()));
+
+        match result {
+            Ok(outcome) => {
+                if outcome == ApplyOutcome::Applied {
+                    self.log_events(&dead_letter.channel, vec![&dead_letter.event])
+                        .await;
+                }
+                self.backend.remove_dead_letter(id).await?;
+                Ok(RetryOutcome::Applied)
+            }
+            Err(err) => {
+                let dead_letter = dead_letter.failed_again(err);
+                self.backend.put_dead_letter(&dead_letter).await?;
+                Ok(RetryOutcome::Failed(Box::new(dead_letter)))
+            }
+        }
+    }
+}
+
+fn dead_letter_not_found(id: &str) -> HttpResponse {
+    AppError::not_found(format!("No dead letter has id {}", id)).into()
+}
+
+async fn list_dead_letters(processor: web::Data<Arc<UpdateProcessor>>) -> impl Responder {
+    match processor.backend.dead_letters().await {
+        Ok(dead_letters) => HttpResponse::Ok().json(dead_letters),
+        Err(e) => e.error_response(),
+    }
+}
+
+async fn get_dead_letter(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    path: web::Path<DeadLetterPath>,
+) -> impl Responder {
+    match processor.backend.dead_letter(&path.id).await {
+        Ok(Some(dead_letter)) => HttpResponse::Ok().json(dead_letter),
+        Ok(None) => dead_letter_not_found(&path.id),
+        Err(e) => e.error_response(),
+    }
+}
+
+async fn discard_dead_letter(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    path: web::Path<DeadLetterPath>,
+) -> impl Responder {
+    match processor.backend.remove_dead_letter(&path.id).await {
+        Ok(true) => {
+            log::info!("Discarded dead letter {}", path.id);
+            HttpResponse::Ok().json("Dead letter discarded")
+        }
+        Ok(false) => dead_letter_not_found(&path.id),
+        Err(e) => e.error_response(),
+    }
+}
+
+async fn retry_dead_letter(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    path: web::Path<DeadLetterPath>,
+) -> impl Responder {
+    log::info!("Retrying dead letter {}", path.id);
+    match processor.retry_dead_letter(&path.id).await {
+        Ok(RetryOutcome::Applied) => HttpResponse::Ok().json("Dead letter applied"),
+        Ok(RetryOutcome::Failed(dead_letter)) => {
+            HttpResponse::UnprocessableEntity().json(dead_letter)
+        }
+        Ok(RetryOutcome::NotFound) => dead_letter_not_found(&path.id),
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, PgConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::DatabaseErrorWrapper, schema::applied_events};
+
+use super::Update;
+
+/// Version of the event envelope written by this build.
+pub const SCHEMA_VERSION: u32 = 1;
+/// Producer recorded for events sent without a `producer_id`.
+pub const ANONYMOUS_PRODUCER: &str = "anonymous";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateStreamEvent {
+    /// Unique per producer. Producers that resend an event must reuse its id.
+    #[serde(default = "new_event_id")]
+    pub id: String,
+    #[serde(default = "anonymous_producer")]
+    pub producer_id: String,
+    #[serde(default = "Utc::now")]
+    pub timestamp: DateTime<Utc>,
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
+    pub data: Update,
+}
+
+fn new_event_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn anonymous_producer() -> String {
+    ANONYMOUS_PRODUCER.to_string()
+}
+
+fn current_schema_version() -> u32 {
+    SCHEMA_VERSION
+}
+
+/// Body of `/add_update`: either a full event or a bare update, which is
+/// given a fresh id and therefore never deduplicated.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AddUpdateRequest {
+    Event(UpdateStreamEvent),
+    Update(Update),
+}
+
+impl From<AddUpdateRequest> for UpdateStreamEvent {
+    fn from(request: AddUpdateRequest) -> Self {
+        match request {
+            AddUpdateRequest::Event(event) => event,
+            AddUpdateRequest::Update(data) => UpdateStreamEvent::new(data),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyOutcome {
+    Applied,
+    /// The event was applied before and has been skipped.
+    Duplicate,
+}
+
+impl UpdateStreamEvent {
+    pub fn new(data: Update) -> Self {
+        UpdateStreamEvent {
+            id: new_event_id(),
+            producer_id: anonymous_producer(),
+            timestamp: Utc::now(),
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    /// Applies the update unless its id was already applied. The id is
+    /// recorded in the same transaction as the update, so a redelivered
+    /// event takes effect exactly once.
+    pub fn apply(&self, con: &mut PgConnection) -> Result<ApplyOutcome, DatabaseErrorWrapper> {
+        con.transaction(|con| {
+            let recorded = diesel::insert_into(applied_events::table)
+                .values((
+                    applied_events::producer_id.eq(&self.producer_id),
+                    applied_events::event_id.eq(&self.id),
+                ))
+                .on_conflict_do_nothing()
+                .execute(con)?;
+            if recorded == 0 {
+                log::info!(
+                    "Skipping duplicate event {} from {}",
+                    self.id,
+                    self.producer_id
+                );
+                return Ok(ApplyOutcome::Duplicate);
+            }
+
+            self.data.apply(con)?;
+            Ok(ApplyOutcome::Applied)
+        })
+    }
+
+    /// Applies the update even if its id was already applied, recording the
+    /// id if it wasn't.
+    pub fn reapply(&self, con: &mut PgConnection) -> Result<ApplyOutcome, DatabaseErrorWrapper> {
+        con.transaction(|con| {
+            diesel::insert_into(applied_events::table)
+                .values((
+                    applied_events::producer_id.eq(&self.producer_id),
+                    applied_events::event_id.eq(&self.id),
+                ))
+                .on_conflict_do_nothing()
+                .execute(con)?;
+            self.data.apply(con)?;
+            Ok(ApplyOutcome::Applied)
+        })
+    }
+}
@@ -0,0 +1,415 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, TimeZone, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    api::request_id,
+    cfg::Env,
+    error::{AppError, ConnectionPoolErrorWrapper, PROBLEM_CONTENT_TYPE},
+    redis::RedisManager,
+};
+
+use super::{
+    dead_letter::serialization_error, event::ApplyOutcome, UpdateProcessor, UpdateStreamEvent,
+};
+
+/// Redis stream holding a copy of every applied event.
+pub const EVENT_LOG_KEY: &str = "event_log";
+pub const DEFAULT_LOG_MAX_LEN: usize = 1_000_000;
+/// Entries read from the log per round trip when replaying.
+const REPLAY_PAGE_SIZE: usize = 500;
+
+pub fn create_event_log_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/event_log")
+            .route("", web::get().to(list_log_entries))
+            .route("/replay", web::post().to(replay_event_log)),
+    );
+}
+
+/// How much of the event log is retained. Entries beyond `max_len`, or older
+/// than `retention` when set, are trimmed as new entries are added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventLogConfig {
+    pub max_len: usize,
+    pub retention: Option<Duration>,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfig {
+            max_len: DEFAULT_LOG_MAX_LEN,
+            retention: None,
+        }
+    }
+}
+
+impl EventLogConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let default = EventLogConfig::default();
+        EventLogConfig {
+            max_len: env
+                .stream_log_max_len
+                .as_deref()
+                .and_then(|max_len| max_len.parse().ok())
+                .unwrap_or(default.max_len),
+            retention: env
+                .stream_log_retention
+                .as_deref()
+                .and_then(|retention| retention.parse().ok())
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Redis stream id, `<milliseconds>-<sequence>`.
+    pub id: String,
+    pub channel: String,
+    pub logged_at: Option<DateTime<Utc>>,
+    pub event: UpdateStreamEvent,
+}
+
+impl LogEntry {
+    fn from_stream_entry(id: String, fields: Vec<String>) -> redis::RedisResult<Self> {
+        let mut channel = String::new();
+        let mut event = None;
+        for pair in fields.chunks(2) {
+            match pair {
+                [field, value] if field == "channel" => channel = value.clone(),
+                [field, value] if field == "event" => {
+                    event = Some(serde_json::from_str(value).map_err(serialization_error)?)
+                }
+                _ => {}
+            }
+        }
+        let event = event.ok_or_else(|| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "Log entry without event",
+                id.clone(),
+            ))
+        })?;
+        let logged_at = id
+            .split('-')
+            .next()
+            .and_then(|millis| millis.parse().ok())
+            .and_then(|millis| Utc.timestamp_millis_opt(millis).single());
+
+        Ok(LogEntry {
+            id,
+            channel,
+            logged_at,
+            event,
+        })
+    }
+}
+
+/// Selects log entries by time or by log entry id. Ids take precedence over
+/// times; missing bounds are open.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub from_id: Option<String>,
+    pub to_id: Option<String>,
+}
+
+impl LogRange {
+    fn bounds(&self) -> (String, String) {
+        let start = self
+            .from_id
+            .clone()
+            .or_else(|| self.from.map(|from| from.timestamp_millis().to_string()))
+            .unwrap_or_else(|| "-".to_string());
+        let end = self
+            .to_id
+            .clone()
+            .or_else(|| self.to.map(|to| to.timestamp_millis().to_string()))
+            .unwrap_or_else(|| "+".to_string());
+        (start, end)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListLogQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub from_id: Option<String>,
+    pub to_id: Option<String>,
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayRequest {
+    #[serde(flatten)]
+    pub range: LogRange,
+    /// Re-apply events even if they are recorded as applied, e.g. after the
+    /// data was lost but `applied_events` was not.
+    #[serde(default)]
+    pub force: bool,
+    /// Most events to replay. The rest can be replayed from the report's
+    /// `last_id`.
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFailure {
+    pub log_id: String,
+    pub event_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub applied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub failures: Vec<ReplayFailure>,
+    /// Last log entry replayed; pass it as `from_id` to continue after it.
+    pub last_id: Option<String>,
+}
+
+/// A replay that stopped partway, e.g. because Postgres or the log could not
+/// be reached. `report` covers the events replayed before it stopped.
+#[derive(Debug, Error)]
+#[error("Replay stopped after {} events: {error}", report.replayed)]
+pub struct ReplayError {
+    pub report: ReplayReport,
+    pub error: AppError,
+}
+
+/// The problem a stopped replay is reported as, with the counts and `last_id`
+/// of its report.
+#[derive(Serialize)]
+struct StoppedReplay<'a> {
+    #[serde(flatten)]
+    problem: AppError,
+    #[serde(flatten)]
+    report: &'a ReplayReport,
+}
+
+impl ResponseError for ReplayError {
+    fn error_response(&self) -> HttpResponse {
+        let mut problem = self.error.clone();
+        problem.request_id = request_id::current();
+        let body = StoppedReplay {
+            problem,
+            report: &self.report,
+        };
+        match serde_json::to_string(&body) {
+            Ok(body) => HttpResponse::build(self.error.status())
+                .content_type(PROBLEM_CONTENT_TYPE)
+                .body(body),
+            Err(_) => HttpResponse::build(self.error.status()).finish(),
+        }
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        self.error.status()
+    }
+}
+
+pub async fn read_log_entries(
+    redis: &RedisManager,
+    start: &str,
+    end: &str,
+    count: usize,
+) -> redis::RedisResult<Vec<LogEntry>> {
+    let mut con = redis.connection().await?;
+    let entries: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+        .arg(EVENT_LOG_KEY)
+        .arg(start)
+        .arg(end)
+        .arg("COUNT")
+        .arg(count)
+        .query_async(&mut con)
+        .await?;
+
+    entries
+        .into_iter()
+        .map(|(id, fields)| LogEntry::from_stream_entry(id, fields))
+        .collect()
+}
+
+/// Appends events to the log in Redis and trims it to `config`'s retention.
+pub async fn append_log_entries(
+    redis: &RedisManager,
+    channel: &str,
+    events: &[&UpdateStreamEvent],
+    config: &EventLogConfig,
+) -> redis::RedisResult<()> {
+    let mut pipe = redis::pipe();
+    for event in events {
+        pipe.cmd("XADD")
+            .arg(EVENT_LOG_KEY)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(config.max_len)
+            .arg("*")
+            .arg("channel")
+            .arg(channel)
+            .arg("event")
+            .arg(serde_json::to_string(event).map_err(serialization_error)?)
+            .ignore();
+    }
+    if let Some(retention) = config.retention {
+        pipe.cmd("XTRIM")
+            .arg(EVENT_LOG_KEY)
+            .arg("MINID")
+            .arg("~")
+            .arg(oldest_retained(retention))
+            .ignore();
+    }
+
+    let mut con = redis.connection().await?;
+    pipe.query_async(&mut con).await
+}
+
+/// Milliseconds timestamp of the oldest entry kept under `retention`.
+pub fn oldest_retained(retention: Duration) -> i64 {
+    Utc::now().timestamp_millis() - retention.as_millis() as i64
+}
+
+impl UpdateProcessor {
+    /// Appends applied events to the event log and trims it to the configured
+    /// retention.
+    pub(super) async fn log_events(&self, channel: &str, events: Vec<&UpdateStreamEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        if let Err(e) = self
+            .backend
+            .append_to_log(channel, &events, &self.event_log)
+            .await
+        {
+            error!("Failed to append to the event log: {:?}", e);
+        }
+    }
+
+    /// Applies the logged events in `request.range` to Postgres again, in log
+    /// order, one transaction per event, up to `request.count` events.
+    pub async fn replay(&self, request: ReplayRequest) -> Result<ReplayReport, ReplayError> {
+        let mut report = ReplayReport::default();
+        match self.replay_pages(&request, &mut report).await {
+            Ok(()) => {
+                info!(
+                    "Replayed {} events: {} applied, {} skipped, {} failed",
+                    report.replayed, report.applied, report.skipped, report.failed
+                );
+                Ok(report)
+            }
+            Err(error) => {
+                error!(
+                    "Replay stopped after {}: {}",
+                    report.last_id.as_deref().unwrap_or("the start"),
+                    error
+                );
+                Err(ReplayError { report, error })
+            }
+        }
+    }
+
+    async fn replay_pages(
+        &self,
+        request: &ReplayRequest,
+        report: &mut ReplayReport,
+    ) -> Result<(), AppError> {
+        let (mut start, end) = request.range.bounds();
+        let mut remaining = request.count.unwrap_or(usize::MAX);
+
+        while remaining > 0 {
+            let page_size = remaining.min(REPLAY_PAGE_SIZE);
+            let entries = self
+                .backend
+                .read_log(&start, &end, page_size)
+                .await
+                .map_err(|err| AppError::from(&err))?;
+            let Some(last) = entries.last() else {
+                break;
+            };
+            start = format!("({}", last.id);
+            let page_len = entries.len();
+
+            let pool = self.pool.clone();
+            let force = request.force;
+            let outcomes = tokio::task::spawn_blocking(move || {
+                let mut connection = pool.get().map_err(ConnectionPoolErrorWrapper)?;
+                let outcomes: Vec<_> = entries
+                    .into_iter()
+                    .map(|entry| {
+                        let outcome = if force {
+                            entry.event.reapply(&mut connection)
+                        } else {
+                            entry.event.apply(&mut connection)
+                        };
+                        (entry, outcome)
+                    })
+                    .collect();
+                Ok::<_, ConnectionPoolErrorWrapper>(outcomes)
+            })
+            .await
+            .map_err(|err| AppError::internal(format!("Replay did not complete: {}", err)))?
+            .map_err(|err| AppError::from(&err))?;
+
+            for (entry, outcome) in outcomes {
+                report.replayed += 1;
+                match outcome {
+                    Ok(ApplyOutcome::Applied) => report.applied += 1,
+                    Ok(ApplyOutcome::Duplicate) => report.skipped += 1,
+                    Err(err) => {
+                        report.failed += 1;
+                        report.failures.push(ReplayFailure {
+                            log_id: entry.id.clone(),
+                            event_id: entry.event.id.clone(),
+                            error: err.to_string(),
+                        });
+                    }
+                }
+                report.last_id = Some(entry.id);
+            }
+
+            remaining -= page_len;
+            if page_len < page_size {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn list_log_entries(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    query: web::Query<ListLogQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let range = LogRange {
+        from: query.from,
+        to: query.to,
+        from_id: query.from_id,
+        to_id: query.to_id,
+    };
+    let (start, end) = range.bounds();
+    let count = query.count.unwrap_or(100);
+    match processor.backend.read_log(&start, &end, count).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => e.error_response(),
+    }
+}
+
+async fn replay_event_log(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: web::Json<ReplayRequest>,
+) -> impl Responder {
+    log::info!("Replaying event log: {:?}", payload);
+    match processor.replay(payload.into_inner()).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,193 @@
+use std::{collections::HashSet, time::Duration};
+
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    HttpRequest, HttpResponse, ResponseError,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cfg::Env,
+    error::{message, problem, AppError, StreamBackendError},
+};
+
+use super::{StreamChannel, UpdateProcessor};
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+/// Key used to rate limit requests that don't send a configured API key.
+pub const ANONYMOUS_API_KEY: &str = "anonymous";
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit";
+
+/// Limits on how fast updates may be added to the stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestLimits {
+    /// Queue length of a channel at which new updates for it are refused.
+    pub high_water_mark: Option<usize>,
+    /// Suggested wait before retrying when a queue is full.
+    pub retry_after: Duration,
+    /// Updates each API key may add per minute.
+    pub rate_limit: Option<u64>,
+    /// API keys limited on their own. Requests with any other key, or none,
+    /// share the [`ANONYMOUS_API_KEY`] limit.
+    pub api_keys: HashSet<String>,
+}
+
+impl Default for IngestLimits {
+    fn default() -> Self {
+        IngestLimits {
+            high_water_mark: None,
+            retry_after: DEFAULT_RETRY_AFTER,
+            rate_limit: None,
+            api_keys: HashSet::new(),
+        }
+    }
+}
+
+impl IngestLimits {
+    pub fn from_env(env: &Env) -> Self {
+        IngestLimits {
+            high_water_mark: env
+                .stream_high_water_mark
+                .as_deref()
+                .and_then(|high_water_mark| high_water_mark.parse().ok()),
+            retry_after: env
+                .stream_retry_after
+                .as_deref()
+                .and_then(|retry_after| retry_after.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RETRY_AFTER),
+            rate_limit: env
+                .stream_rate_limit
+                .as_deref()
+                .and_then(|rate_limit| rate_limit.parse().ok()),
+            api_keys: env
+                .stream_api_keys
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|api_key| !api_key.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// The key `request` is rate limited under: its API key if that is one of
+    /// [`IngestLimits::api_keys`], otherwise [`ANONYMOUS_API_KEY`]. Clients
+    /// can't escape the limit by sending a new key with every request.
+    pub fn limit_key(&self, request: &HttpRequest) -> String {
+        let api_key = api_key(request);
+        if self.api_keys.contains(&api_key) {
+            api_key
+        } else {
+            ANONYMOUS_API_KEY.to_string()
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AdmissionError {
+    #[error("Queue {channel} has reached its high-water mark")]
+    QueueFull {
+        channel: String,
+        retry_after: Duration,
+    },
+    #[error("Rate limit of {limit} updates per minute exceeded")]
+    RateLimited { limit: u64, retry_after: Duration },
+    #[error(transparent)]
+    Backend(#[from] StreamBackendError),
+}
+
+impl ResponseError for AdmissionError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AdmissionError::QueueFull { retry_after, .. }
+            | AdmissionError::RateLimited { retry_after, .. } => {
+                let mut response = AppError::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    problem::RATE_LIMITED,
+                    message::TOO_MANY_REQUESTS,
+                )
+                .with_detail(self.to_string())
+                .error_response();
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(retry_after.as_secs().max(1)),
+                );
+                response
+            }
+            AdmissionError::Backend(err) => err.error_response(),
+        }
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            AdmissionError::QueueFull { .. } | AdmissionError::RateLimited { .. } => {
+                actix_web::http::StatusCode::TOO_MANY_REQUESTS
+            }
+            AdmissionError::Backend(err) => err.status_code(),
+        }
+    }
+}
+
+/// The API key a request was sent with, as given by the client.
+pub fn api_key(request: &HttpRequest) -> String {
+    request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .unwrap_or(ANONYMOUS_API_KEY)
+        .to_string()
+}
+
+impl UpdateProcessor {
+    /// Checks that `count` more updates for `channels` may be queued by
+    /// `api_key`. A `count` of zero only checks that there is room left.
+    pub async fn admit(
+        &self,
+        api_key: &str,
+        channels: &[StreamChannel],
+        count: u64,
+    ) -> Result<(), AdmissionError> {
+        if let Some(high_water_mark) = self.limits.high_water_mark {
+            for channel in channels {
+                let key: &str = (*channel).into();
+                let depth = self.backend.depth(*channel).await?;
+                if depth >= high_water_mark {
+                    return Err(AdmissionError::QueueFull {
+                        channel: key.to_string(),
+                        retry_after: self.limits.retry_after,
+                    });
+                }
+            }
+        }
+
+        if let Some(limit) = self.limits.rate_limit {
+            let window_secs = RATE_LIMIT_WINDOW.as_secs() as i64;
+            let now = Utc::now().timestamp();
+            let window = now / window_secs;
+            let key = format!("{}:{}:{}", RATE_LIMIT_KEY_PREFIX, api_key, window);
+            let ttl = RATE_LIMIT_WINDOW * 2;
+
+            let used = self.backend.increment(&key, count as i64, ttl).await?;
+            if used > limit as i64 || (count == 0 && used >= limit as i64) {
+                // Refused updates don't count against the limit.
+                self.backend.increment(&key, -(count as i64), ttl).await?;
+                return Err(AdmissionError::RateLimited {
+                    limit,
+                    retry_after: Duration::from_secs(((window + 1) * window_secs - now) as u64),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,717 @@
+pub mod backend;
+pub mod batch;
+pub mod bulk;
+pub mod control;
+pub mod dead_letter;
+pub mod event;
+pub mod event_log;
+pub mod limits;
+pub mod mode;
+pub mod stats;
+pub mod update;
+pub mod validate;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use diesel::PgConnection;
+use log::{error, info};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+extern crate redis;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, Notify, RwLock},
+    time::Instant,
+};
+
+use crate::{
+    error::{
+        AppError, ConnectionPoolErrorWrapper, DatabaseErrorWrapper, StreamBackendError,
+        ValidationErrors,
+    },
+    postgres::ConnectionPool,
+};
+use backend::StreamBackend;
+use batch::{apply_batch, BatchConfig, BatchReport, EventStatus};
+use event::{AddUpdateRequest, ApplyOutcome, SCHEMA_VERSION};
+use event_log::EventLogConfig;
+use limits::IngestLimits;
+use mode::ChannelMode;
+use validate::PendingRows;
+
+pub use event::UpdateStreamEvent;
+pub use mode::StreamMode;
+pub use update::Update;
+
+pub fn create_add_update_route(cfg: &mut web::ServiceConfig) {
+    cfg.route("/add_update", web::post().to(add_update))
+        .route("/add_update/bulk", web::post().to(bulk::add_update_bulk))
+        .route("/pause", web::post().to(control::pause_channels))
+        .route("/resume", web::post().to(control::resume_channels))
+        .route("/start", web::post().to(control::resume_channels))
+        .route("/drain", web::post().to(control::drain_channels))
+        .route("/channels", web::get().to(control::get_channel_states))
+        .route("/batch_reports", web::get().to(get_batch_reports))
+        .route("/mode", web::get().to(mode::get_modes))
+        .route("/mode", web::post().to(mode::set_mode))
+        .route("/stats", web::get().to(stats::get_stats));
+}
+async fn get_batch_reports(processor: web::Data<Arc<UpdateProcessor>>) -> impl Responder {
+    HttpResponse::Ok().json(processor.batch_reports().await)
+}
+/// Serialized like its Redis key, e.g. `product_updates`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamChannel {
+    BrandUpdates,
+    CategoryUpdates,
+    ProductUpdates,
+    DiscountUpdates,
+    StockUpdates,
+}
+
+impl StreamChannel {
+    /// Every channel, with the ones other entities reference listed first.
+    pub const ALL: [StreamChannel; 5] = [
+        StreamChannel::BrandUpdates,
+        StreamChannel::CategoryUpdates,
+        StreamChannel::ProductUpdates,
+        StreamChannel::DiscountUpdates,
+        StreamChannel::StockUpdates,
+    ];
+
+    fn from_update(update: &Update) -> Self {
+        update.channel()
+    }
+}
+
+impl Into<String> for StreamChannel {
+    fn into(self) -> String {
+        let key: &str = self.into();
+        key.to_string()
+    }
+}
+
+impl Into<&str> for StreamChannel {
+    fn into(self) -> &'static str {
+        match self {
+            StreamChannel::BrandUpdates => "brand_updates",
+            StreamChannel::CategoryUpdates => "category_updates",
+            StreamChannel::ProductUpdates => "product_updates",
+            StreamChannel::DiscountUpdates => "discount_updates",
+            StreamChannel::StockUpdates => "stock_updates",
+        }
+    }
+}
+
+impl std::str::FromStr for StreamChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StreamChannel::ALL
+            .into_iter()
+            .find(|channel| Into::<&str>::into(*channel) == s)
+            .ok_or_else(|| format!("Unknown stream channel: {}", s))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AddUpdateQuery {
+    /// Validate the update without queueing it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+async fn add_update(
+    request: HttpRequest,
+    processor: web::Data<Arc<UpdateProcessor>>,
+    query: web::Query<AddUpdateQuery>,
+    payload: web::Json<AddUpdateRequest>,
+) -> impl Responder {
+    let event = UpdateStreamEvent::from(payload.into_inner());
+    if event.schema_version > SCHEMA_VERSION {
+        return AppError::bad_request(format!(
+            "Unsupported schema version {}, expected at most {}",
+            event.schema_version, SCHEMA_VERSION
+        ))
+        .with_field("schema_version")
+        .into();
+    }
+    let channel = StreamChannel::from_update(&event.data);
+    // Invalid updates are refused before they count against the rate limit.
+    match processor.validate(event.data.clone()).await {
+        Ok(errors) if !errors.is_empty() => return errors.error_response(),
+        Ok(_) => (),
+        Err(e) => return e.error_response(),
+    }
+    if query.dry_run {
+        return HttpResponse::Ok().json("Update is valid");
+    }
+    if let Err(e) = processor
+        .admit(&processor.limits.limit_key(&request), &[channel], 1)
+        .await
+    {
+        return e.error_response();
+    }
+
+    if let Err(e) = processor.enqueue(&[event]).await {
+        return e.error_response();
+    }
+
+    HttpResponse::Ok().json("Update added")
+}
+
+#[derive(Debug)]
+struct ChannelControl {
+    events: Notify,
+    mode: watch::Sender<StreamMode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateProcessor {
+    backend: Arc<dyn StreamBackend>,
+    channels: Arc<HashMap<StreamChannel, ChannelControl>>,
+    pool: ConnectionPool,
+    batch_config: BatchConfig,
+    limits: IngestLimits,
+    event_log: EventLogConfig,
+    reports: Arc<RwLock<HashMap<String, BatchReport>>>,
+}
+
+impl UpdateProcessor {
+    pub async fn new(
+        backend: Arc<dyn StreamBackend>,
+        pool: ConnectionPool,
+        batch_config: BatchConfig,
+        modes: HashMap<StreamChannel, StreamMode>,
+        limits: IngestLimits,
+        event_log: EventLogConfig,
+    ) -> Result<Self, StreamBackendError> {
+        let channels = StreamChannel::ALL
+            .into_iter()
+            .map(|channel| {
+                let mode = modes.get(&channel).copied().unwrap_or_default();
+                let control = ChannelControl {
+                    events: Notify::new(),
+                    mode: watch::Sender::new(mode),
+                };
+                (channel, control)
+            })
+            .collect();
+
+        Ok(UpdateProcessor {
+            backend,
+            channels: Arc::new(channels),
+            pool,
+            batch_config,
+            limits,
+            event_log,
+            reports: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Queues `events` on the backend and wakes the channels they were
+    /// queued on.
+    pub async fn enqueue(&self, events: &[UpdateStreamEvent]) -> Result<(), StreamBackendError> {
+        self.backend.push(events).await?;
+        let channels: HashSet<StreamChannel> = events
+            .iter()
+            .map(|event| StreamChannel::from_update(&event.data))
+            .collect();
+        for channel in channels {
+            self.notify(channel);
+        }
+        Ok(())
+    }
+
+    /// Signals that an update was queued on `channel`.
+    pub fn notify(&self, channel: StreamChannel) {
+        if let Some(control) = self.channels.get(&channel) {
+            control.events.notify_one();
+        }
+    }
+
+    /// Wakes the processing loop of `channel` whatever its mode, so it
+    /// notices a change of state without waiting for its next batch.
+    fn wake(&self, channel: StreamChannel) {
+        if let Some(control) = self.channels.get(&channel) {
+            control.mode.send_modify(|_| {});
+        }
+    }
+
+    pub fn modes(&self) -> Vec<ChannelMode> {
+        StreamChannel::ALL
+            .into_iter()
+            .filter_map(|channel| {
+                self.channels.get(&channel).map(|control| ChannelMode {
+                    channel: Some(channel),
+                    mode: *control.mode.borrow(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn set_mode(&self, channel: StreamChannel, mode: StreamMode) {
+        if let Some(control) = self.channels.get(&channel) {
+            control.mode.send_replace(mode);
+        }
+    }
+
+    async fn get_batch_size(&self, channel: StreamChannel) -> Result<usize, StreamBackendError> {
+        let list_size = self.backend.depth(channel).await?;
+        info!("Received list size: {}", list_size);
+        Ok(list_size)
+    }
+
+    /// Validates `update` on a pooled connection without applying it.
+    pub async fn validate(&self, update: Update) -> Result<ValidationErrors, DatabaseErrorWrapper> {
+        let mut validations = self.validate_all(vec![update]).await?;
+        Ok(validations.pop().unwrap_or_default())
+    }
+
+    /// Validates every update on a single pooled connection, returning the
+    /// errors of each in order. Updates may refer to rows that valid updates
+    /// before them create.
+    pub async fn validate_all(
+        &self,
+        updates: Vec<Update>,
+    ) -> Result<Vec<ValidationErrors>, DatabaseErrorWrapper> {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = pool.get().map_err(ConnectionPoolErrorWrapper)?;
+            let mut pending = PendingRows::default();
+            updates
+                .iter()
+                .map(|update| update.validate(&mut connection, &mut pending))
+                .collect()
+        })
+        .await
+        .map_err(|err| {
+            DatabaseErrorWrapper(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::Unknown,
+                Box::new(err.to_string()),
+            ))
+        })?
+    }
+
+    pub async fn batch_reports(&self) -> Vec<BatchReport> {
+        self.reports.read().await.values().cloned().collect()
+    }
+
+    async fn process_batch(&self, channel: StreamChannel) {
+        let key: &str = channel.into();
+        // Check out a connection before popping so events are never consumed
+        // without a database to apply them to.
+        let mut connection = match self.pool.get() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Failed to get a database connection: {:?}", e);
+                return;
+            }
+        };
+        let batch_size = match self.get_batch_size(channel).await {
+            Ok(batch_size) => batch_size,
+            Err(e) => {
+                error!("Failed to read size of {}: {:?}", key, e);
+                return;
+            }
+        };
+        if batch_size == 0 {
+            return;
+        }
+        let updates = match self.backend.pop(channel, batch_size).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!("Failed to read updates from {}: {:?}", key, e);
+                return;
+            }
+        };
+
+        let start = Instant::now();
+        let batch_config = self.batch_config.clone();
+        let results = match tokio::task::spawn_blocking(move || {
+            apply_batch(&mut connection, updates, &batch_config)
+        })
+        .await
+        {
+            Ok(results) => results,
+            Err(e) => {
+                error!("Batch on {} did not complete: {:?}", key, e);
+                return;
+            }
+        };
+        let duration = start.elapsed();
+
+        for result in &results {
+            match result.error() {
+                Some(err) => {
+                    self.dead_letter(key, result.event.clone(), err.to_string())
+                        .await
+                }
+                None => info!("Applied update on {}: {:?}", key, result.event.data),
+            }
+        }
+
+        let applied = results
+            .iter()
+            .filter(|result| matches!(result.status, EventStatus::Applied))
+            .map(|result| &result.event)
+            .collect();
+        self.log_events(key, applied).await;
+
+        let report = BatchReport::new(key, results, duration);
+        info!(
+            "Batch on {}: {} applied, {} skipped, {} failed",
+            key, report.applied, report.skipped, report.failed
+        );
+        self.record_stats(&report).await;
+        self.reports.write().await.insert(key.to_string(), report);
+        Self::log_update_info(duration).await;
+    }
+    /// Runs one processing loop per channel until they all exit.
+    pub async fn process_updates(&self, channels: Vec<StreamChannel>) {
+        let handles: Vec<_> = channels
+            .into_iter()
+            .map(|channel| {
+                let processor = self.clone();
+                tokio::spawn(async move { processor.process_channel(channel).await })
+            })
+            .collect();
+
+        futures::future::join_all(handles).await;
+    }
+
+    async fn process_channel(&self, channel: StreamChannel) {
+        let Some(control) = self.channels.get(&channel) else {
+            return;
+        };
+        let key: &str = channel.into();
+        let mut mode_rx = control.mode.subscribe();
+
+        loop {
+            let mode = *mode_rx.borrow_and_update();
+            log::debug!("Processing {} in mode {:?}", key, mode);
+            let triggered = match mode {
+                StreamMode::OnEvent => tokio::select! {
+                    _ = control.events.notified() => true,
+                    _ = mode_rx.changed() => false,
+                },
+                StreamMode::OnSchedule(interval) => tokio::select! {
+                    _ = tokio::time::sleep(interval) => true,
+                    _ = mode_rx.changed() => false,
+                },
+                StreamMode::OnThreshold {
+                    batch_size,
+                    timeout,
+                } => tokio::select! {
+                    _ = self.wait_for_batch_size(channel, batch_size, &control.events) => true,
+                    _ = tokio::time::sleep(timeout) => true,
+                    _ = mode_rx.changed() => false,
+                },
+            };
+
+            let processing = triggered && self.may_process(channel).await;
+            if processing {
+                self.process_batch(channel).await;
+            }
+            // Drained while idle, or during the batch that just finished.
+            if processing || !triggered {
+                self.pause_if_draining(channel).await;
+            }
+        }
+    }
+
+    async fn wait_for_batch_size(
+        &self,
+        channel: StreamChannel,
+        batch_size: usize,
+        events: &Notify,
+    ) {
+        let key: &str = channel.into();
+        loop {
+            match self.get_batch_size(channel).await {
+                Ok(size) if size >= batch_size => return,
+                Ok(_) => {}
+                Err(e) => error!("Failed to read size of {}: {:?}", key, e),
+            }
+            events.notified().await;
+        }
+    }
+
+    async fn log_update_info(duration: Duration) {
+        info!("Processing took: {}", duration.as_millis());
+    }
+
+    fn handle_update(
+        con: &mut PgConnection,
+        update: UpdateStreamEvent,
+    ) -> Result<ApplyOutcome, DatabaseErrorWrapper> {
+        update.apply(con)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use serde_json::json;
+
+    use super::{backend::InMemoryBackend, control::ChannelStatus, *};
+
+    fn event(update: serde_json::Value) -> UpdateStreamEvent {
+        UpdateStreamEvent::new(serde_json::from_value(update).unwrap())
+    }
+
+    /// A pool that only connects when a connection is checked out.
+    fn pool(database_url: &str) -> ConnectionPool {
+        Pool::builder()
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(200))
+            .build_unchecked(ConnectionManager::new(database_url))
+    }
+
+    async fn processor(backend: Arc<InMemoryBackend>, pool: ConnectionPool) -> UpdateProcessor {
+        UpdateProcessor::new(
+            backend,
+            pool,
+            BatchConfig::default(),
+            HashMap::new(),
+            IngestLimits::default(),
+            EventLogConfig::default(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn unreachable_pool() -> ConnectionPool {
+        pool("postgres://postgres@127.0.0.1:1/ecom")
+    }
+
+    #[tokio::test]
+    async fn enqueue_queues_each_event_on_its_channel() {
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), unreachable_pool()).await;
+        let first = event(json!({ "NewBrand": { "name": "First" } }));
+        let second = event(json!({ "NewBrand": { "name": "Second" } }));
+        let category = event(json!({ "NewCategory": { "name": "Shoes", "description": "" } }));
+
+        processor
+            .enqueue(&[first.clone(), category.clone(), second.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(backend.depth(StreamChannel::BrandUpdates).await.unwrap(), 2);
+        assert_eq!(
+            backend.depth(StreamChannel::CategoryUpdates).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            backend.depth(StreamChannel::ProductUpdates).await.unwrap(),
+            0
+        );
+        let brands = backend.pop(StreamChannel::BrandUpdates, 10).await.unwrap();
+        let ids: Vec<_> = brands.iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(ids, [first.id.as_str(), second.id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn enqueue_wakes_the_channel() {
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend, unreachable_pool()).await;
+
+        processor
+            .enqueue(&[event(
+                json!({ "NewCategory": { "name": "Shoes", "description": "" } }),
+            )])
+            .await
+            .unwrap();
+
+        let control = &processor.channels[&StreamChannel::CategoryUpdates];
+        let woken = tokio::time::timeout(Duration::from_secs(1), control.events.notified()).await;
+        assert!(woken.is_ok());
+    }
+
+    #[tokio::test]
+    async fn drain_pauses_an_idle_channel_right_away() {
+        let backend = Arc::new(InMemoryBackend::default());
+        let modes = HashMap::from([(
+            StreamChannel::BrandUpdates,
+            StreamMode::OnSchedule(Duration::from_secs(3600)),
+        )]);
+        let processor = UpdateProcessor::new(
+            backend,
+            unreachable_pool(),
+            BatchConfig::default(),
+            modes,
+            IngestLimits::default(),
+            EventLogConfig::default(),
+        )
+        .await
+        .unwrap();
+        let running = processor.clone();
+        tokio::spawn(async move {
+            running
+                .process_updates(vec![StreamChannel::BrandUpdates])
+                .await
+        });
+        tokio::task::yield_now().await;
+
+        processor
+            .set_status(
+                Some(StreamChannel::BrandUpdates),
+                ChannelStatus::Draining,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let paused = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let state = processor
+                    .channel_state(StreamChannel::BrandUpdates)
+                    .await
+                    .unwrap();
+                if state.status == ChannelStatus::Paused {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(paused.is_ok());
+    }
+
+    #[tokio::test]
+    async fn process_batch_keeps_events_without_a_database() {
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), unreachable_pool()).await;
+        processor
+            .enqueue(&[event(json!({ "NewBrand": { "name": "Kept" } }))])
+            .await
+            .unwrap();
+
+        processor.process_batch(StreamChannel::BrandUpdates).await;
+
+        assert_eq!(backend.depth(StreamChannel::BrandUpdates).await.unwrap(), 1);
+        assert!(backend.dead_letters().await.unwrap().is_empty());
+        assert!(processor.batch_reports().await.is_empty());
+    }
+
+    /// Needs a migrated database at `DATABASE_URL`; skipped without one. The
+    /// update fails, so nothing is written to it.
+    #[tokio::test]
+    async fn process_batch_dead_letters_failed_updates() {
+        let Ok(database_url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), pool(&database_url)).await;
+        let missing = event(json!({ "UpdateBrand": { "id": i32::MAX, "name": "Missing" } }));
+        processor
+            .enqueue(std::slice::from_ref(&missing))
+            .await
+            .unwrap();
+
+        processor.process_batch(StreamChannel::BrandUpdates).await;
+
+        assert_eq!(backend.depth(StreamChannel::BrandUpdates).await.unwrap(), 0);
+        let dead_letters = backend.dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].event.id, missing.id);
+        assert_eq!(dead_letters[0].channel, "brand_updates");
+
+        let stats = processor.stats().await.unwrap();
+        assert_eq!((stats.processed, stats.failed, stats.batches), (0, 1, 1));
+        assert!(backend.read_log("-", "+", 10).await.unwrap().is_empty());
+    }
+
+    /// Needs a migrated database at `DATABASE_URL`; skipped without one. The
+    /// delete matches no row, so nothing is written to it.
+    #[tokio::test]
+    async fn process_batch_dead_letters_deletes_of_missing_rows() {
+        let Ok(database_url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), pool(&database_url)).await;
+        let missing = event(json!({ "DeleteDiscount": { "id": i32::MAX } }));
+        processor
+            .enqueue(std::slice::from_ref(&missing))
+            .await
+            .unwrap();
+
+        processor
+            .process_batch(StreamChannel::DiscountUpdates)
+            .await;
+
+        let dead_letters = backend.dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].event.id, missing.id);
+        let stats = processor.stats().await.unwrap();
+        assert_eq!((stats.processed, stats.failed), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn replay_reports_where_it_stopped() {
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), unreachable_pool()).await;
+        let logged = event(json!({ "NewBrand": { "name": "Logged" } }));
+        backend
+            .append_to_log("brand_updates", &[&logged], &EventLogConfig::default())
+            .await
+            .unwrap();
+
+        let err = processor
+            .replay(event_log::ReplayRequest::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.report.replayed, 0);
+        assert_eq!(err.report.last_id, None);
+        let response = err.error_response();
+        assert_eq!(response.status(), 500);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "/problems/connection-pool");
+        assert_eq!(body["replayed"], 0);
+        assert!(body["last_id"].is_null());
+    }
+
+    /// Needs a migrated database at `DATABASE_URL`; skipped without one. The
+    /// updates fail, so nothing is written to it.
+    #[tokio::test]
+    async fn replay_stops_after_count() {
+        let Ok(database_url) = std::env::var("DATABASE_URL") else {
+            return;
+        };
+        let backend = Arc::new(InMemoryBackend::default());
+        let processor = processor(backend.clone(), pool(&database_url)).await;
+        let missing = event(json!({ "UpdateBrand": { "id": i32::MAX, "name": "Missing" } }));
+        backend
+            .append_to_log(
+                "brand_updates",
+                &[&missing, &missing, &missing],
+                &EventLogConfig::default(),
+            )
+            .await
+            .unwrap();
+        let entries = backend.read_log("-", "+", 10).await.unwrap();
+
+        let report = processor
+            .replay(event_log::ReplayRequest {
+                count: Some(2),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!((report.replayed, report.failed), (2, 2));
+        assert_eq!(report.last_id.as_deref(), Some(entries[1].id.as_str()));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc, time::Duration};
+
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::{cfg::Env, error::ValidationErrors};
+
+use super::{StreamChannel, UpdateProcessor};
+
+/// When a channel's queued updates are taken off Redis and applied. Written
+/// the same way in `STREAM_MODES` and in `/mode` requests and responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum StreamMode {
+    /// Process as soon as an update is added.
+    #[default]
+    OnEvent,
+    /// Process whatever is queued at a fixed interval.
+    OnSchedule(Duration),
+    /// Process once `batch_size` updates are queued or `timeout` has passed,
+    /// whichever comes first.
+    OnThreshold {
+        batch_size: usize,
+        timeout: Duration,
+    },
+}
+
+/// Parses `on_event`, `on_schedule:<interval_ms>` or
+/// `on_threshold:<batch_size>:<timeout_ms>`. Intervals, timeouts and batch
+/// sizes must be above zero, or the channel's loop would never wait.
+impl FromStr for StreamMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let millis = |value: &str| match value.parse::<u64>() {
+            Ok(0) => Err(format!("Duration must be above zero in stream mode: {}", s)),
+            Ok(millis) => Ok(Duration::from_millis(millis)),
+            Err(_) => Err(format!("Invalid duration in stream mode: {}", s)),
+        };
+        match parts.as_slice() {
+            ["on_event"] => Ok(StreamMode::OnEvent),
+            ["on_schedule", interval] => Ok(StreamMode::OnSchedule(millis(interval)?)),
+            ["on_threshold", batch_size, timeout] => Ok(StreamMode::OnThreshold {
+                batch_size: match batch_size.parse::<usize>() {
+                    Ok(0) => Err(format!(
+                        "Batch size must be above zero in stream mode: {}",
+                        s
+                    )),
+                    Ok(batch_size) => Ok(batch_size),
+                    Err(_) => Err(format!("Invalid batch size in stream mode: {}", s)),
+                }?,
+                timeout: millis(timeout)?,
+            }),
+            _ => Err(format!("Unknown stream mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for StreamMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamMode::OnEvent => write!(f, "on_event"),
+            StreamMode::OnSchedule(interval) => write!(f, "on_schedule:{}", interval.as_millis()),
+            StreamMode::OnThreshold {
+                batch_size,
+                timeout,
+            } => write!(f, "on_threshold:{}:{}", batch_size, timeout.as_millis()),
+        }
+    }
+}
+
+impl TryFrom<String> for StreamMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<StreamMode> for String {
+    fn from(mode: StreamMode) -> Self {
+        mode.to_string()
+    }
+}
+
+/// Reads the initial mode of every channel from `STREAM_MODES`, a comma
+/// separated list of `<channel>=<mode>` entries. An entry without a channel
+/// sets the mode for all channels not listed explicitly.
+pub fn modes_from_env(env: &Env) -> HashMap<StreamChannel, StreamMode> {
+    let mut default = StreamMode::default();
+    let mut overrides = HashMap::new();
+
+    for entry in env
+        .stream_modes
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parsed = match entry.split_once('=') {
+            Some((channel, mode)) => channel
+                .trim()
+                .parse::<StreamChannel>()
+                .and_then(|channel| Ok((Some(channel), mode.parse::<StreamMode>()?))),
+            None => entry.parse::<StreamMode>().map(|mode| (None, mode)),
+        };
+        match parsed {
+            Ok((Some(channel), mode)) => {
+                overrides.insert(channel, mode);
+            }
+            Ok((None, mode)) => default = mode,
+            Err(err) => log::warn!("Ignoring stream mode '{}': {}", entry, err),
+        }
+    }
+
+    StreamChannel::ALL
+        .iter()
+        .map(|channel| (*channel, *overrides.get(channel).unwrap_or(&default)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelMode {
+    /// Leaving the channel out of a `/mode` request applies the mode to every channel.
+    pub channel: Option<StreamChannel>,
+    pub mode: StreamMode,
+}
+
+/// Body of a `/mode` request, parsed by [`ModeRequest::resolve`] so a bad
+/// channel or mode is reported as a `422`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModeRequest {
+    pub channel: Option<String>,
+    pub mode: String,
+}
+
+impl ModeRequest {
+    pub fn resolve(&self) -> Result<ChannelMode, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let channel = match self.channel.as_deref().map(str::parse::<StreamChannel>) {
+            Some(Ok(channel)) => Some(channel),
+            Some(Err(e)) => {
+                errors.add("channel", &e);
+                None
+            }
+            None => None,
+        };
+        match self.mode.parse::<StreamMode>() {
+            Ok(mode) if errors.is_empty() => Ok(ChannelMode { channel, mode }),
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.add("mode", &e);
+                Err(errors)
+            }
+        }
+    }
+}
+
+pub(super) async fn get_modes(processor: web::Data<Arc<UpdateProcessor>>) -> impl Responder {
+    HttpResponse::Ok().json(processor.modes())
+}
+
+pub(super) async fn set_mode(
+    processor: web::Data<Arc<UpdateProcessor>>,
+    payload: web::Json<ModeRequest>,
+) -> impl Responder {
+    let request = match payload.resolve() {
+        Ok(request) => request,
+        Err(e) => return HttpResponse::from(e),
+    };
+    match request.channel {
+        Some(channel) => processor.set_mode(channel, request.mode),
+        None => {
+            for channel in StreamChannel::ALL {
+                processor.set_mode(channel, request.mode);
+            }
+        }
+    }
+    log::info!("Stream mode changed: {:?}", request);
+    HttpResponse::Ok().json(processor.modes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_mode() {
+        assert_eq!("on_event".parse(), Ok(StreamMode::OnEvent));
+        assert_eq!(
+            " on_schedule:5000 ".parse(),
+            Ok(StreamMode::OnSchedule(Duration::from_secs(5)))
+        );
+        assert_eq!(
+            "on_threshold:500:10000".parse(),
+            Ok(StreamMode::OnThreshold {
+                batch_size: 500,
+                timeout: Duration::from_secs(10),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_zero_intervals_and_batch_sizes() {
+        assert!("on_schedule:0".parse::<StreamMode>().is_err());
+        assert!("on_threshold:0:1000".parse::<StreamMode>().is_err());
+        assert!("on_threshold:10:0".parse::<StreamMode>().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_modes() {
+        for mode in [
+            "",
+            "on_events",
+            "on_schedule",
+            "on_schedule:soon",
+            "on_schedule:-5",
+            "on_threshold:10",
+            "on_threshold:many:1000",
+            "on_event:1",
+        ] {
+            assert!(mode.parse::<StreamMode>().is_err(), "{:?} parsed", mode);
+        }
+    }
+
+    #[test]
+    fn displays_as_it_parses() {
+        for mode in ["on_event", "on_schedule:250", "on_threshold:500:10000"] {
+            assert_eq!(mode.parse::<StreamMode>().unwrap().to_string(), mode);
+        }
+    }
+
+    #[test]
+    fn mode_requests_use_the_env_format() {
+        let request = ModeRequest {
+            channel: Some("product_updates".to_string()),
+            mode: "on_schedule:1000".to_string(),
+        };
+        let mode = request.resolve().unwrap();
+        assert_eq!(mode.channel, Some(StreamChannel::ProductUpdates));
+        assert_eq!(mode.mode, StreamMode::OnSchedule(Duration::from_secs(1)));
+
+        let request = ModeRequest {
+            channel: Some("ProductUpdates".to_string()),
+            mode: "on_schedule:0".to_string(),
+        };
+        let fields: Vec<String> = request
+            .resolve()
+            .unwrap_err()
+            .errors
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(fields, ["channel", "mode"]);
+    }
+
+    #[test]
+    fn serializes_as_a_string() {
+        let mode = ChannelMode {
+            channel: Some(StreamChannel::StockUpdates),
+            mode: StreamMode::OnThreshold {
+                batch_size: 5,
+                timeout: Duration::from_millis(100),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&mode).unwrap(),
+            serde_json::json!({ "channel": "stock_updates", "mode": "on_threshold:5:100" })
+        );
+    }
+}
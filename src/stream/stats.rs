@@ -0,0 +1,242 @@
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::StreamBackendError, redis::RedisManager};
+
+use super::{
+    batch::BatchReport,
+    control::{ChannelState, ChannelStateEntry, ChannelStatus},
+    mode::ChannelMode,
+    StreamChannel, UpdateProcessor,
+};
+
+/// Redis hash holding the processor counters, shared by every stream instance.
+pub const STATS_KEY: &str = "update_processor_stats";
+/// Prefix of the per-minute processed counters used for throughput.
+const MINUTE_KEY_PREFIX: &str = "update_processor_stats:minute";
+/// Per-minute counters only need to outlive the minute after them.
+const MINUTE_KEY_TTL_SECS: i64 = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub channel: StreamChannel,
+    pub state: ChannelState,
+    pub queue_depth: usize,
+    pub processed: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessorStats {
+    /// Whether any channel is running.
+    pub active: bool,
+    pub modes: Vec<ChannelMode>,
+    pub channels: Vec<ChannelStats>,
+    pub processed: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub batches: u64,
+    pub last_batch_size: u64,
+    pub last_batch_duration_ms: u64,
+    /// Updates processed during the previous full minute.
+    pub updates_per_minute: u64,
+    /// Updates applied per second of batch processing time.
+    pub updates_per_second: f64,
+    pub last_batch_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+}
+
+/// The counters of [`STATS_KEY`], and the updates processed during the
+/// previous full minute.
+#[derive(Debug, Clone, Default)]
+pub struct StatsCounters {
+    pub fields: HashMap<String, String>,
+    pub last_minute: Option<u64>,
+}
+
+/// Minutes since the epoch, numbering the per-minute counters.
+pub fn minute(at: DateTime<Utc>) -> i64 {
+    at.timestamp() / 60
+}
+
+fn minute_key(minute: i64) -> String {
+    format!("{}:{}", MINUTE_KEY_PREFIX, minute)
+}
+
+/// Updates a finished batch counts as processed.
+pub fn batch_size(report: &BatchReport) -> u64 {
+    (report.applied + report.skipped + report.failed) as u64
+}
+
+/// How a finished batch changes the counters.
+#[derive(Debug, Clone, Default)]
+pub struct BatchCounters {
+    /// Amounts added to fields.
+    pub increments: Vec<(String, u64)>,
+    /// Fields that are overwritten.
+    pub values: Vec<(&'static str, String)>,
+}
+
+pub fn batch_counters(report: &BatchReport) -> BatchCounters {
+    let duration_ms = report.duration.as_millis() as u64;
+    let finished_at = report.finished_at.to_rfc3339();
+    let increments = vec![
+        ("processed".to_string(), report.applied as u64),
+        ("skipped".to_string(), report.skipped as u64),
+        ("failed".to_string(), report.failed as u64),
+        ("batches".to_string(), 1),
+        ("total_duration_ms".to_string(), duration_ms),
+        (
+            format!("processed:{}", report.channel),
+            report.applied as u64,
+        ),
+        (format!("failed:{}", report.channel), report.failed as u64),
+    ];
+    let mut values = vec![
+        ("last_batch_size", batch_size(report).to_string()),
+        ("last_batch_duration_ms", duration_ms.to_string()),
+        ("last_batch_at", finished_at.clone()),
+    ];
+    if report.failed == 0 {
+        values.push(("last_success_at", finished_at));
+    }
+    BatchCounters { increments, values }
+}
+
+/// Adds a finished batch to the counters in Redis.
+pub async fn record_batch(redis: &RedisManager, report: &BatchReport) -> redis::RedisResult<()> {
+    let mut con = redis.connection().await?;
+    let BatchCounters { increments, values } = batch_counters(report);
+    let minute_key = minute_key(minute(report.finished_at));
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for (field, amount) in increments {
+        pipe.hincr(STATS_KEY, field, amount);
+    }
+    for (field, value) in values {
+        pipe.hset(STATS_KEY, field, value);
+    }
+    pipe.incr(&minute_key, batch_size(report))
+        .expire(&minute_key, MINUTE_KEY_TTL_SECS);
+    let _: () = pipe.query_async(&mut con).await?;
+    Ok(())
+}
+
+/// Reads the counters from Redis.
+pub async fn read_counters(redis: &RedisManager) -> redis::RedisResult<StatsCounters> {
+    let mut con = redis.connection().await?;
+
+    let (fields, last_minute): (HashMap<String, String>, Option<u64>) = redis::pipe()
+        .hgetall(STATS_KEY)
+        .get(minute_key(minute(Utc::now()) - 1))
+        .query_async(&mut con)
+        .await?;
+    Ok(StatsCounters {
+        fields,
+        last_minute,
+    })
+}
+
+/// Combines the counters with the queue state, which comes from the stream
+/// backend.
+pub fn processor_stats(
+    counters: StatsCounters,
+    states: Vec<ChannelStateEntry>,
+    queue_depths: Vec<usize>,
+    modes: Vec<ChannelMode>,
+) -> ProcessorStats {
+    let StatsCounters {
+        fields: counters,
+        last_minute,
+    } = counters;
+
+    let counter = |field: &str| {
+        counters
+            .get(field)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    let timestamp = |field: &str| {
+        counters
+            .get(field)
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|value| value.with_timezone(&Utc))
+    };
+
+    let active = states
+        .iter()
+        .any(|entry| entry.state.status == ChannelStatus::Running);
+    let channels = states
+        .into_iter()
+        .zip(queue_depths)
+        .map(|(ChannelStateEntry { channel, state }, queue_depth)| {
+            let key: &str = channel.into();
+            ChannelStats {
+                channel,
+                state,
+                queue_depth,
+                processed: counter(&format!("processed:{}", key)),
+                failed: counter(&format!("failed:{}", key)),
+            }
+        })
+        .collect();
+
+    let processed = counter("processed");
+    let total_duration_ms = counter("total_duration_ms");
+    let updates_per_second = if total_duration_ms > 0 {
+        processed as f64 * 1000.0 / total_duration_ms as f64
+    } else {
+        0.0
+    };
+
+    ProcessorStats {
+        active,
+        modes,
+        channels,
+        processed,
+        skipped: counter("skipped"),
+        failed: counter("failed"),
+        batches: counter("batches"),
+        last_batch_size: counter("last_batch_size"),
+        last_batch_duration_ms: counter("last_batch_duration_ms"),
+        updates_per_minute: last_minute.unwrap_or(0),
+        updates_per_second,
+        last_batch_at: timestamp("last_batch_at"),
+        last_success_at: timestamp("last_success_at"),
+    }
+}
+
+impl UpdateProcessor {
+    pub(super) async fn record_stats(&self, report: &BatchReport) {
+        if let Err(e) = self.backend.record_batch(report).await {
+            error!("Failed to record stats for {}: {:?}", report.channel, e);
+        }
+    }
+
+    pub async fn stats(&self) -> Result<ProcessorStats, StreamBackendError> {
+        let states = self.channel_states().await?;
+        let mut queue_depths = Vec::with_capacity(StreamChannel::ALL.len());
+        for channel in StreamChannel::ALL {
+            queue_depths.push(self.backend.depth(channel).await?);
+        }
+        let counters = self.backend.stats_counters().await?;
+        Ok(processor_stats(
+            counters,
+            states,
+            queue_depths,
+            self.modes(),
+        ))
+    }
+}
+
+pub(super) async fn get_stats(processor: web::Data<Arc<UpdateProcessor>>) -> impl Responder {
+    match processor.stats().await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::Debug;
+
+use diesel::{result::Error as DieselError, PgConnection};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::DatabaseErrorWrapper,
+    services::{
+        brand::model::{Brand, NewBrand},
+        category::model::{Category, CreateCategoryRequest, NewCategory},
+        discount::model::{
+            relations::{DiscountBrand, DiscountCategory, DiscountProduct},
+            Discount, NewDiscount,
+        },
+        product::model::{NewProduct, Product, UpsertProduct},
+        stock::model::{NewStockQuantity, StockQuantity},
+    },
+    ResourceIdentifierRequest,
+};
+
+use super::StreamChannel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Update {
+    NewProduct(NewProduct),
+    UpdateProduct(Product),
+    /// Matches on the product's SKU instead of its id.
+    UpsertProduct(UpsertProduct),
+    DeleteProduct(ResourceIdentifierRequest),
+
+    NewBrand(NewBrand),
+    UpdateBrand(Brand),
+    DeleteBrand(ResourceIdentifierRequest),
+
+    NewCategory(CreateCategoryRequest),
+    UpdateCategory(Category),
+    DeleteCategory(ResourceIdentifierRequest),
+
+    NewDiscount(NewDiscount),
+    UpdateDiscount(Discount),
+    DeleteDiscount(ResourceIdentifierRequest),
+    NewDiscountBrand(DiscountBrand),
+    DeleteDiscountBrand(DiscountBrand),
+    NewDiscountCategory(DiscountCategory),
+    DeleteDiscountCategory(DiscountCategory),
+    NewDiscountProduct(DiscountProduct),
+    DeleteDiscountProduct(DiscountProduct),
+
+    NewStockQuantity(NewStockQuantity),
+    UpdateStockQuantity(StockQuantity),
+    DeleteStockQuantity(ResourceIdentifierRequest),
+}
+
+impl Update {
+    pub fn channel(&self) -> StreamChannel {
+        match self {
+            Update::NewProduct(_)
+            | Update::UpdateProduct(_)
+            | Update::UpsertProduct(_)
+            | Update::DeleteProduct(_) => StreamChannel::ProductUpdates,
+            Update::NewBrand(_) | Update::UpdateBrand(_) | Update::DeleteBrand(_) => {
+                StreamChannel::BrandUpdates
+            }
+            Update::NewCategory(_) | Update::UpdateCategory(_) | Update::DeleteCategory(_) => {
+                StreamChannel::CategoryUpdates
+            }
+            Update::NewDiscount(_)
+            | Update::UpdateDiscount(_)
+            | Update::DeleteDiscount(_)
+            | Update::NewDiscountBrand(_)
+            | Update::DeleteDiscountBrand(_)
+            | Update::NewDiscountCategory(_)
+            | Update::DeleteDiscountCategory(_)
+            | Update::NewDiscountProduct(_)
+            | Update::DeleteDiscountProduct(_) => StreamChannel::DiscountUpdates,
+            Update::NewStockQuantity(_)
+            | Update::UpdateStockQuantity(_)
+            | Update::DeleteStockQuantity(_) => StreamChannel::StockUpdates,
+        }
+    }
+
+    pub fn apply(&self, con: &mut PgConnection) -> Result<(), DatabaseErrorWrapper> {
+        use crate::services::{
+            brand::query as brand, category::query as category, discount::query as discount,
+            product::query as product, stock::query as stock,
+        };
+
+        match self {
+            Update::NewProduct(new_product) => log_result(
+                "Create product",
+                product::insert_product_query(new_product.clone(), con),
+            ),
+            Update::UpdateProduct(updated_product) => log_result(
+                "Update product",
+                product::set_product_query(updated_product.clone(), con),
+            ),
+            Update::UpsertProduct(upsert) => log_result(
+                "Upsert product",
+                product::upsert_product_by_sku_query(upsert.clone(), con),
+            ),
+            Update::DeleteProduct(path) => log_result(
+                "Delete product",
+                deleted(product::delete_product_query_internal(con, path.id)),
+            ),
+
+            Update::NewBrand(new_brand) => log_result(
+                "Create brand",
+                brand::insert_brand_query(con, new_brand.clone()).map_err(DatabaseErrorWrapper),
+            ),
+            Update::UpdateBrand(updated_brand) => log_result(
+                "Update brand",
+                brand::set_brand_query(con, updated_brand.clone()).map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteBrand(path) => log_result(
+                "Delete brand",
+                deleted(brand::delete_brand_query(con, path.id).map_err(DatabaseErrorWrapper)),
+            ),
+
+            Update::NewCategory(request) => log_result(
+                "Create category",
+                category::insert_product_query_pooled_conn(
+                    con,
+                    NewCategory::new(&request.name, &request.description),
+                )
+                .map_err(DatabaseErrorWrapper),
+            ),
+            Update::UpdateCategory(updated_category) => log_result(
+                "Update category",
+                category::set_product_query(con, updated_category.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteCategory(path) => log_result(
+                "Delete category",
+                deleted(category::delete_product_query(con, path.id).map_err(DatabaseErrorWrapper)),
+            ),
+
+            Update::NewDiscount(new_discount) => log_result(
+                "Create discount",
+                discount::insert_discount_query(con, new_discount.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::UpdateDiscount(updated_discount) => log_result(
+                "Update discount",
+                discount::set_discount_query(con, updated_discount.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteDiscount(path) => log_result(
+                "Delete discount",
+                deleted(
+                    discount::delete_discount_query(con, path.id).map_err(DatabaseErrorWrapper),
+                ),
+            ),
+            Update::NewDiscountBrand(relation) => log_result(
+                "Create discount brand",
+                discount::insert_discount_brand_query(con, relation.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteDiscountBrand(relation) => log_result(
+                "Delete discount brand",
+                deleted(
+                    discount::delete_discount_brand_query(con, relation.clone())
+                        .map_err(DatabaseErrorWrapper),
+                ),
+            ),
+            Update::NewDiscountCategory(relation) => log_result(
+                "Create discount category",
+                discount::insert_discount_category_query(con, relation.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteDiscountCategory(relation) => log_result(
+                "Delete discount category",
+                deleted(
+                    discount::delete_discount_category_query(con, relation.clone())
+                        .map_err(DatabaseErrorWrapper),
+                ),
+            ),
+            Update::NewDiscountProduct(relation) => log_result(
+                "Create discount product",
+                discount::insert_discount_product_query(con, relation.clone())
+                    .map_err(DatabaseErrorWrapper),
+            ),
+            Update::DeleteDiscountProduct(relation) => log_result(
+                "Delete discount product",
+                deleted(
+                    discount::delete_discount_product_query(con, relation.clone())
+                        .map_err(DatabaseErrorWrapper),
+                ),
+            ),
+
+            Update::NewStockQuantity(new_stock_quantity) => log_result(
+                "Create stock quantity",
+                stock::insert_stock_quantity_query(con, new_stock_quantity.clone()),
+            ),
+            Update::UpdateStockQuantity(updated_stock_quantity) => log_result(
+                "Update stock quantity",
+                stock::set_stock_quantity_for_product(con, updated_stock_quantity.clone()),
+            ),
+            Update::DeleteStockQuantity(path) => log_result(
+                "Delete stock quantity",
+                deleted(stock::delete_stock_quantity_from_product_query(
+                    con, path.id,
+                )),
+            ),
+        }
+    }
+}
+
+/// A delete that matches no row fails like an update does, so it is reported
+/// as failed instead of applied.
+fn deleted(result: Result<usize, DatabaseErrorWrapper>) -> Result<usize, DatabaseErrorWrapper> {
+    match result {
+        Ok(0) => Err(DatabaseErrorWrapper(DieselError::NotFound)),
+        result => result,
+    }
+}
+
+fn log_result<T: Debug>(
+    action: &str,
+    result: Result<T, DatabaseErrorWrapper>,
+) -> Result<(), DatabaseErrorWrapper> {
+    match result {
+        Ok(result) => {
+            info!("{} completed: {:?}", action, result);
+            Ok(())
+        }
+        Err(err) => {
+            error!("{} failed: {:?}", action, err);
+            Err(err)
+        }
+    }
+}
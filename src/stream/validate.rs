@@ -0,0 +1,492 @@
+use std::collections::HashSet;
+
+use diesel::{dsl::exists, prelude::*, PgConnection};
+
+use crate::{
+    api::validation::Validate,
+    error::{DatabaseErrorWrapper, ValidationErrors},
+    schema::{
+        brands, categories, discount_brands, discount_categories, discount_products, discounts,
+        products, stock_quantities, warehouses,
+    },
+    services::{
+        discount::model::relations::{DiscountBrand, DiscountCategory, DiscountProduct},
+        product::model::ProductChanges,
+    },
+};
+
+use super::Update;
+
+/// What updates validated earlier in the same request will change, so later
+/// updates are checked against it: the rows they delete and the SKUs they
+/// create. Ids of new rows are only known once they are applied, so later
+/// updates can refer to a new product by its SKU but not to any new row by
+/// its id.
+#[derive(Debug, Default)]
+pub struct PendingRows {
+    products: DeletedRows,
+    brands: DeletedRows,
+    categories: DeletedRows,
+    discounts: DeletedRows,
+    skus: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct DeletedRows(HashSet<i32>);
+
+impl DeletedRows {
+    /// Whether `id` exists, checking the database unless an earlier update
+    /// deletes it.
+    fn exists(
+        &self,
+        id: i32,
+        exists: impl FnOnce() -> Result<bool, DatabaseErrorWrapper>,
+    ) -> Result<bool, DatabaseErrorWrapper> {
+        if self.0.contains(&id) {
+            Ok(false)
+        } else {
+            exists()
+        }
+    }
+}
+
+impl PendingRows {
+    fn product_exists(
+        &self,
+        con: &mut PgConnection,
+        id: i32,
+    ) -> Result<bool, DatabaseErrorWrapper> {
+        self.products.exists(id, || product_exists(con, id))
+    }
+
+    fn brand_exists(&self, con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+        self.brands.exists(id, || brand_exists(con, id))
+    }
+
+    fn category_exists(
+        &self,
+        con: &mut PgConnection,
+        id: i32,
+    ) -> Result<bool, DatabaseErrorWrapper> {
+        self.categories.exists(id, || category_exists(con, id))
+    }
+
+    fn discount_exists(
+        &self,
+        con: &mut PgConnection,
+        id: i32,
+    ) -> Result<bool, DatabaseErrorWrapper> {
+        self.discounts.exists(id, || discount_exists(con, id))
+    }
+
+    /// Whether a product with `sku` exists or is created by an earlier update.
+    fn sku_exists(&self, con: &mut PgConnection, sku: &str) -> Result<bool, DatabaseErrorWrapper> {
+        if self.skus.contains(sku) {
+            Ok(true)
+        } else {
+            sku_exists(con, sku)
+        }
+    }
+
+    /// Records the rows a valid `update` deletes and the SKU it creates.
+    fn record(&mut self, update: &Update) {
+        match update {
+            Update::NewProduct(product) => {
+                if let Some(sku) = &product.sku {
+                    self.skus.insert(sku.clone());
+                }
+            }
+            Update::UpsertProduct(upsert) => {
+                self.skus.insert(upsert.sku.clone());
+            }
+            Update::DeleteProduct(path) => {
+                self.products.0.insert(path.id);
+            }
+            Update::DeleteBrand(path) => {
+                self.brands.0.insert(path.id);
+            }
+            Update::DeleteCategory(path) => {
+                self.categories.0.insert(path.id);
+            }
+            Update::DeleteDiscount(path) => {
+                self.discounts.0.insert(path.id);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Update {
+    /// Checks the update against the rules the processor would otherwise only
+    /// hit when applying it, including that referenced rows exist, either in
+    /// the database or in `pending`. A valid update is added to `pending`.
+    pub fn validate(
+        &self,
+        con: &mut PgConnection,
+        pending: &mut PendingRows,
+    ) -> Result<ValidationErrors, DatabaseErrorWrapper> {
+        let mut errors = ValidationErrors::new();
+
+        match self {
+            Update::NewProduct(product) => {
+                product.validate(&mut errors);
+                validate_references(
+                    &mut errors,
+                    con,
+                    pending,
+                    product.brand_id,
+                    product.category_id,
+                )?;
+            }
+            Update::UpdateProduct(product) => {
+                errors.exists("id", pending.product_exists(con, product.id)?);
+                errors.not_blank("name", &product.name);
+                if let Some(sku) = &product.sku {
+                    errors.not_blank("sku", sku);
+                }
+                validate_product_fields(
+                    &mut errors,
+                    con,
+                    pending,
+                    &ProductChanges {
+                        name: None,
+                        in_stock: None,
+                        category_id: product.category_id,
+                        brand_id: product.brand_id,
+                        price: Some(product.price),
+                        tax_rate: Some(product.tax_rate),
+                    },
+                )?;
+            }
+            Update::UpsertProduct(upsert) => {
+                errors.not_blank("sku", &upsert.sku);
+                if let Some(name) = &upsert.changes.name {
+                    errors.not_blank("name", name);
+                }
+                // A new SKU creates the product, which needs every column
+                // without a default.
+                if !pending.sku_exists(con, &upsert.sku)? {
+                    errors.required("name", &upsert.changes.name);
+                    errors.required("price", &upsert.changes.price);
+                    errors.required("tax_rate", &upsert.changes.tax_rate);
+                }
+                validate_product_fields(&mut errors, con, pending, &upsert.changes)?;
+            }
+
+            Update::NewBrand(brand) => brand.validate(&mut errors),
+            Update::UpdateBrand(brand) => {
+                errors.exists("id", pending.brand_exists(con, brand.id)?);
+                brand.validate(&mut errors);
+            }
+
+            Update::NewCategory(category) => errors.not_blank("name", &category.name),
+            Update::UpdateCategory(category) => {
+                errors.exists("id", pending.category_exists(con, category.id)?);
+                errors.not_blank("name", &category.name);
+            }
+
+            Update::NewDiscount(discount) => discount.validate(&mut errors),
+            Update::UpdateDiscount(discount) => {
+                errors.exists("id", pending.discount_exists(con, discount.id)?);
+                discount.validate(&mut errors);
+            }
+            Update::NewDiscountBrand(relation) => {
+                errors.exists(
+                    "discount_id",
+                    pending.discount_exists(con, relation.discount_id)?,
+                );
+                errors.exists("brand_id", pending.brand_exists(con, relation.brand_id)?);
+            }
+            Update::NewDiscountCategory(relation) => {
+                errors.exists(
+                    "discount_id",
+                    pending.discount_exists(con, relation.discount_id)?,
+                );
+                errors.exists(
+                    "category_id",
+                    pending.category_exists(con, relation.category_id)?,
+                );
+            }
+            Update::NewDiscountProduct(relation) => {
+                errors.exists(
+                    "discount_id",
+                    pending.discount_exists(con, relation.discount_id)?,
+                );
+                errors.exists(
+                    "product_id",
+                    pending.product_exists(con, relation.product_id)?,
+                );
+            }
+
+            Update::NewStockQuantity(stock) => {
+                errors.exists("product_id", pending.product_exists(con, stock.product_id)?);
+                if let Some(warehouse_id) = stock.warehouse_id {
+                    errors.exists("warehouse_id", warehouse_exists(con, warehouse_id)?);
+                }
+                stock.validate(&mut errors);
+            }
+            Update::UpdateStockQuantity(stock) => {
+                errors.exists("product_id", pending.product_exists(con, stock.product_id)?);
+                errors.exists("warehouse_id", warehouse_exists(con, stock.warehouse_id)?);
+                stock.validate(&mut errors);
+            }
+
+            // A delete that matches no row fails when it is applied, so the
+            // row must exist.
+            Update::DeleteProduct(path) => {
+                errors.exists("id", pending.product_exists(con, path.id)?)
+            }
+            Update::DeleteBrand(path) => errors.exists("id", pending.brand_exists(con, path.id)?),
+            Update::DeleteCategory(path) => {
+                errors.exists("id", pending.category_exists(con, path.id)?)
+            }
+            Update::DeleteDiscount(path) => {
+                errors.exists("id", pending.discount_exists(con, path.id)?)
+            }
+            Update::DeleteDiscountBrand(relation) => {
+                if !discount_brand_exists(con, relation)? {
+                    errors.add("brand_id", "is not linked to the discount");
+                }
+            }
+            Update::DeleteDiscountCategory(relation) => {
+                if !discount_category_exists(con, relation)? {
+                    errors.add("category_id", "is not linked to the discount");
+                }
+            }
+            Update::DeleteDiscountProduct(relation) => {
+                if !discount_product_exists(con, relation)? {
+                    errors.add("product_id", "is not linked to the discount");
+                }
+            }
+            Update::DeleteStockQuantity(path) => {
+                if !stock_quantity_exists(con, path.id)? {
+                    errors.add("id", "has no stock quantities");
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            pending.record(self);
+        }
+        Ok(errors)
+    }
+}
+
+fn validate_product_fields(
+    errors: &mut ValidationErrors,
+    con: &mut PgConnection,
+    pending: &PendingRows,
+    fields: &ProductChanges,
+) -> Result<(), DatabaseErrorWrapper> {
+    if let Some(price) = fields.price {
+        errors.at_least("price", price, 0);
+    }
+    if let Some(tax_rate) = fields.tax_rate {
+        errors.at_least("tax_rate", tax_rate, 0);
+        errors.at_most("tax_rate", tax_rate, 100);
+    }
+    validate_references(errors, con, pending, fields.brand_id, fields.category_id)
+}
+
+fn validate_references(
+    errors: &mut ValidationErrors,
+    con: &mut PgConnection,
+    pending: &PendingRows,
+    brand_id: Option<i32>,
+    category_id: Option<i32>,
+) -> Result<(), DatabaseErrorWrapper> {
+    if let Some(brand_id) = brand_id {
+        errors.exists("brand_id", pending.brand_exists(con, brand_id)?);
+    }
+    if let Some(category_id) = category_id {
+        errors.exists("category_id", pending.category_exists(con, category_id)?);
+    }
+    Ok(())
+}
+
+fn product_exists(con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(products::table.find(id))).get_result(con)?)
+}
+
+fn brand_exists(con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(brands::table.find(id))).get_result(con)?)
+}
+
+fn category_exists(con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(categories::table.find(id))).get_result(con)?)
+}
+
+fn discount_exists(con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(discounts::table.find(id))).get_result(con)?)
+}
+
+fn sku_exists(con: &mut PgConnection, sku: &str) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(products::table.filter(products::sku.eq(sku)))).get_result(con)?)
+}
+
+fn warehouse_exists(con: &mut PgConnection, id: i32) -> Result<bool, DatabaseErrorWrapper> {
+    Ok(diesel::select(exists(warehouses::table.find(id))).get_result(con)?)
+}
+
+fn discount_brand_exists(
+    con: &mut PgConnection,
+    relation: &DiscountBrand,
+) -> Result<bool, DatabaseErrorWrapper> {
+    let link = discount_brands::table.find((relation.discount_id, relation.brand_id));
+    Ok(diesel::select(exists(link)).get_result(con)?)
+}
+
+fn discount_category_exists(
+    con: &mut PgConnection,
+    relation: &DiscountCategory,
+) -> Result<bool, DatabaseErrorWrapper> {
+    let link = discount_categories::table.find((relation.discount_id, relation.category_id));
+    Ok(diesel::select(exists(link)).get_result(con)?)
+}
+
+fn discount_product_exists(
+    con: &mut PgConnection,
+    relation: &DiscountProduct,
+) -> Result<bool, DatabaseErrorWrapper> {
+    let link = discount_products::table.find((relation.discount_id, relation.product_id));
+    Ok(diesel::select(exists(link)).get_result(con)?)
+}
+
+fn stock_quantity_exists(
+    con: &mut PgConnection,
+    product_id: i32,
+) -> Result<bool, DatabaseErrorWrapper> {
+    let stock = stock_quantities::table.filter(stock_quantities::product_id.eq(product_id));
+    Ok(diesel::select(exists(stock)).get_result(con)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Needs a migrated database at `DATABASE_URL`; `None` without one.
+    /// Validation only reads from it.
+    fn connection() -> Option<PgConnection> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        Some(PgConnection::establish(&database_url).unwrap())
+    }
+
+    fn update(update: serde_json::Value) -> Update {
+        serde_json::from_value(update).unwrap()
+    }
+
+    fn fields(errors: &ValidationErrors) -> Vec<&str> {
+        errors
+            .errors
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rejects_references_to_rows_created_earlier() {
+        let Some(mut con) = connection() else {
+            return;
+        };
+        let next_id = brands::table
+            .select(diesel::dsl::max(brands::id))
+            .first::<Option<i32>>(&mut con)
+            .unwrap()
+            .unwrap_or(0)
+            + 1;
+        let mut pending = PendingRows::default();
+
+        let errors = update(json!({ "NewBrand": { "name": "New" } }))
+            .validate(&mut con, &mut pending)
+            .unwrap();
+        assert!(errors.is_empty());
+        let errors =
+            update(json!({ "NewDiscountBrand": { "discount_id": 0, "brand_id": next_id } }))
+                .validate(&mut con, &mut pending)
+                .unwrap();
+        assert!(fields(&errors).contains(&"brand_id"));
+    }
+
+    #[test]
+    fn rejects_references_to_rows_deleted_earlier() {
+        let Some(mut con) = connection() else {
+            return;
+        };
+        let Some(id) = brands::table
+            .select(brands::id)
+            .first::<i32>(&mut con)
+            .optional()
+            .unwrap()
+        else {
+            return;
+        };
+        let mut pending = PendingRows::default();
+
+        let errors = update(json!({ "DeleteBrand": { "id": id } }))
+            .validate(&mut con, &mut pending)
+            .unwrap();
+        assert!(errors.is_empty());
+        let errors = update(json!({ "UpdateBrand": { "id": id, "name": "Gone" } }))
+            .validate(&mut con, &mut pending)
+            .unwrap();
+        assert_eq!(fields(&errors), ["id"]);
+    }
+
+    #[test]
+    fn rejects_deletes_of_missing_rows() {
+        let Some(mut con) = connection() else {
+            return;
+        };
+        let mut pending = PendingRows::default();
+        for (delete, field) in [
+            (json!({ "DeleteBrand": { "id": -1 } }), "id"),
+            (
+                json!({ "DeleteDiscountBrand": { "discount_id": -1, "brand_id": -1 } }),
+                "brand_id",
+            ),
+            (json!({ "DeleteStockQuantity": { "id": -1 } }), "id"),
+        ] {
+            let errors = update(delete).validate(&mut con, &mut pending).unwrap();
+            assert_eq!(fields(&errors), [field]);
+        }
+    }
+
+    #[test]
+    fn new_products_need_every_required_field() {
+        let Some(mut con) = connection() else {
+            return;
+        };
+        let errors = update(json!({ "NewProduct": { "name": " " } }))
+            .validate(&mut con, &mut PendingRows::default())
+            .unwrap();
+        let mut fields = fields(&errors);
+        fields.sort_unstable();
+        assert_eq!(fields, ["name", "price", "tax_rate"]);
+    }
+
+    #[test]
+    fn upserts_of_new_skus_need_every_required_field() {
+        let Some(mut con) = connection() else {
+            return;
+        };
+        let mut pending = PendingRows::default();
+        let sku = format!("test-{}", uuid::Uuid::new_v4());
+        let changes = json!({ "UpsertProduct": { "sku": sku, "price": 5 } });
+
+        let errors = update(changes.clone())
+            .validate(&mut con, &mut pending)
+            .unwrap();
+        assert_eq!(fields(&errors), ["name", "tax_rate"]);
+
+        let errors = update(json!({
+            "NewProduct": { "name": "Shoe", "price": 10, "tax_rate": 20, "sku": sku }
+        }))
+        .validate(&mut con, &mut pending)
+        .unwrap();
+        assert!(errors.is_empty());
+        let errors = update(changes).validate(&mut con, &mut pending).unwrap();
+        assert!(errors.is_empty());
+    }
+}